@@ -1,19 +1,101 @@
 //! Commands for validating plugins.
 
+use std::path::Path;
 use std::process::ExitCode;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use anyhow::{Context, Result};
-use colored::Colorize;
+use colored::{ColoredString, Colorize};
+use serde::Serialize;
 
 use super::{println_wrapped, TextWrapper};
-use crate::tests::TestStatus;
-use crate::validator::{self, SingleTestSettings, ValidatorSettings};
+use crate::plugin::host::{LogSeverity, LogThread};
+use crate::tests::{TestResult, TestStatus};
+#[cfg(target_os = "linux")]
+use crate::validator::ScanSideEffectsSettings;
+use crate::validator::{
+    self, SingleTestSettings, TestSubject, ValidationEvent, ValidatorSettings, WorkerSettings,
+};
 use crate::Verbosity;
 
+/// A test result printed as a single line of JSON in the `--stream-json` mode.
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct StreamedTestResult<'a> {
+    library_path: &'a Path,
+    /// The plugin's ID, or `None` for plugin library tests.
+    plugin_id: Option<&'a str>,
+    #[serde(flatten)]
+    result: &'a TestResult,
+}
+
 /// The main validator command. This will validate one or more plugins and print the results.
 pub fn validate(verbosity: Verbosity, settings: &ValidatorSettings) -> Result<ExitCode> {
-    let mut result =
-        validator::validate(verbosity, settings).context("Could not run the validator")?;
+    let show_progress = !settings.no_progress && !matches!(verbosity, Verbosity::Quiet);
+    let num_started = AtomicUsize::new(0);
+    let num_finished = AtomicUsize::new(0);
+    let on_event = |event: ValidationEvent| match event {
+        ValidationEvent::TestStarted { subject, test_name } => {
+            let num_started = num_started.fetch_add(1, Ordering::Relaxed) + 1;
+            if show_progress {
+                let num_finished = num_finished.load(Ordering::Relaxed);
+                eprintln!(
+                    "[{num_finished} finished, {} running] Started {test_name} ({subject})",
+                    num_started - num_finished
+                );
+            }
+        }
+        ValidationEvent::TestFinished { subject, result } => {
+            let num_finished = num_finished.fetch_add(1, Ordering::Relaxed) + 1;
+            if show_progress {
+                let num_started = num_started.load(Ordering::Relaxed);
+                eprintln!(
+                    "[{num_finished} finished, {} running] {} {} ({subject})",
+                    num_started.saturating_sub(num_finished),
+                    status_text(&result.status),
+                    result.name
+                );
+            }
+
+            if settings.stream_json && (!settings.only_failed || result.status.failed_or_warning())
+            {
+                let (library_path, plugin_id) = match subject {
+                    TestSubject::PluginLibrary(library_path) => (library_path, None),
+                    TestSubject::Plugin {
+                        library_path,
+                        plugin_id,
+                    } => (library_path, Some(plugin_id)),
+                };
+                println!(
+                    "{}",
+                    serde_json::to_string(&StreamedTestResult {
+                        library_path,
+                        plugin_id,
+                        result,
+                    })
+                    .expect("Could not format JSON")
+                );
+            }
+        }
+        ValidationEvent::TestErrored {
+            subject,
+            test_name,
+            error,
+        } => {
+            let num_finished = num_finished.fetch_add(1, Ordering::Relaxed) + 1;
+            if show_progress {
+                let num_started = num_started.load(Ordering::Relaxed);
+                eprintln!(
+                    "[{num_finished} finished, {} running] {} {test_name} ({subject}): {error:#}",
+                    num_started.saturating_sub(num_finished),
+                    "ERROR".red(),
+                );
+            }
+        }
+    };
+
+    let mut result = validator::validate(verbosity, settings, &on_event)
+        .context("Could not run the validator")?;
     let tally = result.tally();
 
     // Filtering out tests should be done after we did the tally for consistency's sake
@@ -53,7 +135,9 @@ pub fn validate(verbosity: Verbosity, settings: &ValidatorSettings) -> Result<Ex
             .collect();
     }
 
-    if settings.json {
+    if settings.stream_json {
+        // All of the results have already been printed
+    } else if settings.json {
         println!(
             "{}",
             serde_json::to_string_pretty(&result).expect("Could not format JSON")
@@ -66,18 +150,48 @@ pub fn validate(verbosity: Verbosity, settings: &ValidatorSettings) -> Result<Ex
             ($test:expr) => {
                 println_wrapped!(wrapper, "   - {}: {}", $test.name, $test.description);
 
-                let status_text = match $test.status {
-                    TestStatus::Success { .. } => "PASSED".green(),
-                    TestStatus::Crashed { .. } => "CRASHED".red().bold(),
-                    TestStatus::Failed { .. } => "FAILED".red(),
-                    TestStatus::Skipped { .. } => "SKIPPED".yellow(),
-                    TestStatus::Warning { .. } => "WARNING".yellow(),
-                };
+                let status_text = status_text(&$test.status);
                 let test_result = match $test.status.details() {
                     Some(reason) => format!("     {status_text}: {reason}"),
                     None => format!("     {status_text}"),
                 };
                 wrapper.print_auto(test_result);
+
+                if !$test.sandbox_writes.is_empty() {
+                    let paths: Vec<String> = $test
+                        .sandbox_writes
+                        .iter()
+                        .map(|path| format!("'{}'", path.display()))
+                        .collect();
+                    wrapper.print_auto(format!(
+                        "     Files written to outside of the sandbox's temporary directory: {}",
+                        paths.join(", ")
+                    ));
+                }
+                if !$test.sandbox_read_only_dirs.is_empty() {
+                    let paths: Vec<String> = $test
+                        .sandbox_read_only_dirs
+                        .iter()
+                        .map(|path| format!("'{}'", path.display()))
+                        .collect();
+                    wrapper.print_auto(format!(
+                        "     Directories made read-only in the sandbox, writes to these were not \
+                         recorded: {}",
+                        paths.join(", ")
+                    ));
+                }
+
+                if !$test.plugin_log.is_empty() {
+                    wrapper.print_auto("     Messages logged by the plugin:");
+                    for message in &$test.plugin_log {
+                        wrapper.print_auto(format!(
+                            "       {} ({}): {}",
+                            severity_text(message.severity),
+                            thread_text(message.thread),
+                            message.message
+                        ));
+                    }
+                }
             };
         }
 
@@ -132,6 +246,42 @@ pub fn validate(verbosity: Verbosity, settings: &ValidatorSettings) -> Result<Ex
     }
 }
 
+/// The colored text used to represent a test's status.
+fn status_text(status: &TestStatus) -> ColoredString {
+    match status {
+        TestStatus::Success { .. } => "PASSED".green(),
+        TestStatus::Crashed { .. } => "CRASHED".red().bold(),
+        TestStatus::Failed { .. } => "FAILED".red(),
+        TestStatus::Skipped { .. } => "SKIPPED".yellow(),
+        TestStatus::Warning { .. } => "WARNING".yellow(),
+        TestStatus::WrapperFailed { .. } => "WRAPPER FAILED".red(),
+    }
+}
+
+/// Get the text used to display a plugin log message's severity. Messages where the plugin reports
+/// that the validator is misbehaving are highlighted since they may indicate a bug in the
+/// validator.
+fn severity_text(severity: LogSeverity) -> ColoredString {
+    match severity {
+        LogSeverity::Debug => "debug".normal(),
+        LogSeverity::Info => "info".normal(),
+        LogSeverity::Warning => "warning".yellow(),
+        LogSeverity::Error => "error".red(),
+        LogSeverity::Fatal => "fatal".red().bold(),
+        LogSeverity::HostMisbehaving => "HOST MISBEHAVING".magenta().bold(),
+        LogSeverity::PluginMisbehaving => "plugin misbehaving".red(),
+    }
+}
+
+/// Get the text used to describe the thread a plugin log message was logged from.
+fn thread_text(thread: LogThread) -> &'static str {
+    match thread {
+        LogThread::Main => "main thread",
+        LogThread::Audio => "audio thread",
+        LogThread::Other => "other thread",
+    }
+}
+
 /// Run a single test and write the output to a file. This command is a hidden implementation detail
 /// used by the validator to run tests in a different process.
 pub fn run_single(settings: &SingleTestSettings) -> Result<ExitCode> {
@@ -141,3 +291,19 @@ pub fn run_single(settings: &SingleTestSettings) -> Result<ExitCode> {
         .map(|()| ExitCode::SUCCESS)
         .context("Could not run test the case")
 }
+
+/// Scan a plugin library for the `scan-side-effects` test, and write the observed side effects to
+/// the output file.
+#[cfg(target_os = "linux")]
+pub fn run_scan_side_effects(settings: &ScanSideEffectsSettings) -> Result<ExitCode> {
+    crate::tests::scan_side_effects_to_file(&settings.path, &settings.output_file)
+        .map(|()| ExitCode::SUCCESS)
+        .context("Could not scan the plugin library")
+}
+
+/// Run plugin tests in a persistent worker process until the validator closes the process' STDIN.
+pub fn run_worker(settings: &WorkerSettings) -> Result<ExitCode> {
+    validator::run_worker(settings)
+        .map(|()| ExitCode::SUCCESS)
+        .context("Could not run the worker process")
+}
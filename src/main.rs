@@ -1,7 +1,9 @@
 use clap::{Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 use std::process::ExitCode;
-use validator::{SingleTestSettings, ValidatorSettings};
+#[cfg(target_os = "linux")]
+use validator::ScanSideEffectsSettings;
+use validator::{SingleTestSettings, ValidatorSettings, WorkerSettings};
 
 mod commands;
 mod index;
@@ -47,6 +49,19 @@ enum Command {
     /// option is not shown in the CLI.
     #[command(hide = true)]
     RunSingleTest(SingleTestSettings),
+    /// Run plugin tests in a persistent worker process.
+    ///
+    /// This is used for the out-of-process testing when the `--workers` option is enabled. Like
+    /// the `run-single-test` command, this is not shown in the CLI.
+    #[command(hide = true)]
+    Worker(WorkerSettings),
+    /// Scan a plugin library while monitoring the scan's side effects.
+    ///
+    /// This is used by the `scan-side-effects` test to scan the library in a fresh process. Like
+    /// the `run-single-test` command, this is not shown in the CLI.
+    #[cfg(target_os = "linux")]
+    #[command(hide = true)]
+    ScanSideEffects(ScanSideEffectsSettings),
 
     #[command(subcommand)]
     List(ListCommand),
@@ -106,6 +121,9 @@ fn main() -> ExitCode {
     let result = match cli.command {
         Command::Validate(settings) => commands::validate::validate(cli.verbosity, &settings),
         Command::RunSingleTest(settings) => commands::validate::run_single(&settings),
+        Command::Worker(settings) => commands::validate::run_worker(&settings),
+        #[cfg(target_os = "linux")]
+        Command::ScanSideEffects(settings) => commands::validate::run_scan_side_effects(&settings),
         Command::List(ListCommand::Plugins { json }) => commands::list::plugins(json),
         Command::List(ListCommand::Presets { json, paths }) => {
             commands::list::presets(json, paths.as_deref())
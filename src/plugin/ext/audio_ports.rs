@@ -2,22 +2,36 @@
 
 use anyhow::{Context, Result};
 use clap_sys::ext::audio_ports::{
-    clap_audio_port_info, clap_plugin_audio_ports, CLAP_EXT_AUDIO_PORTS, CLAP_PORT_MONO,
-    CLAP_PORT_STEREO,
+    clap_audio_port_info, clap_plugin_audio_ports, CLAP_AUDIO_PORTS_RESCAN_CHANNEL_COUNT,
+    CLAP_AUDIO_PORTS_RESCAN_FLAGS, CLAP_AUDIO_PORTS_RESCAN_IN_PLACE_PAIR,
+    CLAP_AUDIO_PORTS_RESCAN_LIST, CLAP_AUDIO_PORTS_RESCAN_NAMES, CLAP_AUDIO_PORTS_RESCAN_PORT_TYPE,
+    CLAP_EXT_AUDIO_PORTS, CLAP_PORT_MONO, CLAP_PORT_STEREO,
 };
 use clap_sys::ext::draft::ambisonic::CLAP_PORT_AMBISONIC;
 use clap_sys::ext::draft::cv::CLAP_PORT_CV;
 use clap_sys::ext::draft::surround::CLAP_PORT_SURROUND;
 use clap_sys::id::CLAP_INVALID_ID;
+use clap_sys::plugin::clap_plugin;
 use std::collections::HashMap;
 use std::ffi::CStr;
 use std::ptr::NonNull;
 
-use crate::plugin::instance::Plugin;
-use crate::util::unsafe_clap_call;
+use crate::plugin::instance::{Plugin, PluginStatus};
+use crate::util::{self, unsafe_clap_call};
 
 use super::Extension;
 
+/// All `CLAP_AUDIO_PORTS_RESCAN_*` flags.
+pub const RESCAN_FLAGS: u32 = CLAP_AUDIO_PORTS_RESCAN_NAMES
+    | CLAP_AUDIO_PORTS_RESCAN_FLAGS
+    | CLAP_AUDIO_PORTS_RESCAN_CHANNEL_COUNT
+    | CLAP_AUDIO_PORTS_RESCAN_PORT_TYPE
+    | CLAP_AUDIO_PORTS_RESCAN_IN_PLACE_PAIR
+    | CLAP_AUDIO_PORTS_RESCAN_LIST;
+/// The `CLAP_AUDIO_PORTS_RESCAN_*` flags that may only be used while the plugin is deactivated.
+/// Only the port names can change while the plugin is active.
+const DEACTIVATED_RESCAN_FLAGS: u32 = RESCAN_FLAGS & !CLAP_AUDIO_PORTS_RESCAN_NAMES;
+
 /// Abstraction for the `audio-ports` extension covering the main thread functionality.
 #[derive(Debug)]
 pub struct AudioPorts<'a> {
@@ -26,7 +40,7 @@ pub struct AudioPorts<'a> {
 }
 
 /// The audio port configuration for a plugin.
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct AudioPortConfig {
     /// Configuration for the plugin's input audio ports.
     pub inputs: Vec<AudioPort>,
@@ -35,10 +49,16 @@ pub struct AudioPortConfig {
 }
 
 /// The configuration for a single audio port.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AudioPort {
+    /// The audio port's name.
+    pub name: String,
+    /// The raw `CLAP_AUDIO_PORT_*` flags bit field.
+    pub flags: u32,
     /// The number of channels for an audio port.
     pub num_channels: u32,
+    /// The audio port's type, if the plugin set one.
+    pub port_type: Option<String>,
     /// The index if the output/input port this input/output port should be connected to. This is
     /// the index in the other **port list**, not a stable ID (which have already been translated).
     pub in_place_pair_idx: Option<usize>,
@@ -61,163 +81,203 @@ impl AudioPorts<'_> {
     /// Get the audio port configuration for this plugin. This automatically performs a number of
     /// consistency checks on the plugin's audio port configuration.
     pub fn config(&self) -> Result<AudioPortConfig> {
-        let mut config = AudioPortConfig::default();
-
-        // TODO: Refactor this to reduce the duplication a little without hurting the human readable error messages
-        let audio_ports = self.audio_ports.as_ptr();
-        let plugin = self.plugin.as_ptr();
-        let num_inputs = unsafe_clap_call! { audio_ports=>count(plugin, true) };
-        let num_outputs = unsafe_clap_call! { audio_ports=>count(plugin, false) };
-
-        // Audio ports have a stable ID attribute that can be used to connect input and output ports
-        // so the host can do in-place processing. This uses stable IDs rather than the indices in
-        // the list. To make it easier for us, we'll translate those stable IDs to vector indices.
-        // These two hashmaps are keyed by the port's stable ID, and the value is a pair containing
-        // the port's index in the input/output port vector, and the stable ID of its in-place pair
-        // port.
-        let mut input_stable_index_pairs: HashMap<u32, (usize, u32)> = HashMap::new();
-        let mut output_stable_index_pairs: HashMap<u32, (usize, u32)> = HashMap::new();
-
-        for i in 0..num_inputs {
-            let mut info: clap_audio_port_info = unsafe { std::mem::zeroed() };
-            let success = unsafe_clap_call! { audio_ports=>get(plugin, i, true, &mut info) };
-            if !success {
-                anyhow::bail!(
-                    "Plugin returned an error when querying input audio port {i} ({num_inputs} \
-                     total input ports)."
-                );
-            }
+        let config = unsafe { query_config(self.plugin.as_ptr(), self.audio_ports.as_ptr()) }?;
 
-            is_audio_port_type_consistent(&info).with_context(|| {
-                format!(
-                    "Inconsistent channel count for output port {i} ({num_outputs} total output \
-                     ports)"
-                )
-            })?;
+        // The host compares this against the new configuration when the plugin calls
+        // `clap_host_audio_ports::rescan()`
+        *self.plugin.state.audio_port_config.lock() = Some(config.clone());
 
-            // We'll convert these stable IDs to vector indices later
-            if input_stable_index_pairs.contains_key(&info.id) {
-                anyhow::bail!(
-                    "The stable ID of input audio port {i} ({}) is a duplicate.",
-                    info.id
-                );
-            }
-            input_stable_index_pairs.insert(info.id, (i as usize, info.in_place_pair));
-
-            config.inputs.push(AudioPort {
-                num_channels: info.channel_count,
-                // These are reconstructed from `input_stable_index_pairs` and
-                // `output_stable_index_pairs` later
-                in_place_pair_idx: None,
-            });
+        Ok(config)
+    }
+}
+
+/// Query and check the audio port configuration for a plugin. This is the implementation of
+/// [`AudioPorts::config()`], and it is also used by the host to handle
+/// `clap_host_audio_ports::rescan()` calls.
+///
+/// # Safety
+///
+/// `plugin` must point to an initialized plugin instance, and `audio_ports` must point to that
+/// plugin's `clap_plugin_audio_ports` struct.
+pub unsafe fn query_config(
+    plugin: *const clap_plugin,
+    audio_ports: *const clap_plugin_audio_ports,
+) -> Result<AudioPortConfig> {
+    let mut config = AudioPortConfig::default();
+
+    // TODO: Refactor this to reduce the duplication a little without hurting the human readable error messages
+    let num_inputs = unsafe_clap_call! { audio_ports=>count(plugin, true) };
+    let num_outputs = unsafe_clap_call! { audio_ports=>count(plugin, false) };
+
+    // Audio ports have a stable ID attribute that can be used to connect input and output ports
+    // so the host can do in-place processing. This uses stable IDs rather than the indices in
+    // the list. To make it easier for us, we'll translate those stable IDs to vector indices.
+    // These two hashmaps are keyed by the port's stable ID, and the value is a pair containing
+    // the port's index in the input/output port vector, and the stable ID of its in-place pair
+    // port.
+    let mut input_stable_index_pairs: HashMap<u32, (usize, u32)> = HashMap::new();
+    let mut output_stable_index_pairs: HashMap<u32, (usize, u32)> = HashMap::new();
+
+    for i in 0..num_inputs {
+        let mut info: clap_audio_port_info = unsafe { std::mem::zeroed() };
+        let success = unsafe_clap_call! { audio_ports=>get(plugin, i, true, &mut info) };
+        if !success {
+            anyhow::bail!(
+                "Plugin returned an error when querying input audio port {i} ({num_inputs} \
+                 total input ports)."
+            );
         }
 
-        for i in 0..num_outputs {
-            let mut info: clap_audio_port_info = unsafe { std::mem::zeroed() };
-            let success = unsafe_clap_call! { audio_ports=>get(plugin, i, false, &mut info) };
-            if !success {
-                anyhow::bail!(
-                    "Plugin returned an error when querying output audio port {i} ({num_outputs} \
-                     total output ports)."
-                );
-            }
+        is_audio_port_type_consistent(&info).with_context(|| {
+            format!(
+                "Inconsistent channel count for output port {i} ({num_outputs} total output \
+                 ports)"
+            )
+        })?;
 
-            is_audio_port_type_consistent(&info).with_context(|| {
-                format!(
-                    "Inconsistent channel count for output port {i} ({num_outputs} total output \
-                     ports)"
-                )
-            })?;
+        // We'll convert these stable IDs to vector indices later
+        if input_stable_index_pairs.contains_key(&info.id) {
+            anyhow::bail!(
+                "The stable ID of input audio port {i} ({}) is a duplicate.",
+                info.id
+            );
+        }
+        input_stable_index_pairs.insert(info.id, (i as usize, info.in_place_pair));
 
-            if output_stable_index_pairs.contains_key(&info.id) {
-                anyhow::bail!(
-                    "The stable ID of output audio port {i} ({}) is a duplicate.",
-                    info.id
-                );
-            }
-            output_stable_index_pairs.insert(info.id, (i as usize, info.in_place_pair));
+        config.inputs.push(AudioPort {
+            name: util::c_char_slice_to_string(&info.name)
+                .with_context(|| format!("Could not read the name for input audio port {i}"))?,
+            flags: info.flags,
+            num_channels: info.channel_count,
+            port_type: port_type(&info),
+            // These are reconstructed from `input_stable_index_pairs` and
+            // `output_stable_index_pairs` later
+            in_place_pair_idx: None,
+        });
+    }
 
-            config.outputs.push(AudioPort {
-                num_channels: info.channel_count,
-                in_place_pair_idx: None,
-            });
+    for i in 0..num_outputs {
+        let mut info: clap_audio_port_info = unsafe { std::mem::zeroed() };
+        let success = unsafe_clap_call! { audio_ports=>get(plugin, i, false, &mut info) };
+        if !success {
+            anyhow::bail!(
+                "Plugin returned an error when querying output audio port {i} ({num_outputs} \
+                 total output ports)."
+            );
+        }
+
+        is_audio_port_type_consistent(&info).with_context(|| {
+            format!(
+                "Inconsistent channel count for output port {i} ({num_outputs} total output \
+                 ports)"
+            )
+        })?;
+
+        if output_stable_index_pairs.contains_key(&info.id) {
+            anyhow::bail!(
+                "The stable ID of output audio port {i} ({}) is a duplicate.",
+                info.id
+            );
         }
+        output_stable_index_pairs.insert(info.id, (i as usize, info.in_place_pair));
 
-        // Now we need to convert the stable in-place pair indices to vector indices
-        for (input_stable_id, (input_port_idx, pair_stable_id)) in input_stable_index_pairs
+        config.outputs.push(AudioPort {
+            name: util::c_char_slice_to_string(&info.name)
+                .with_context(|| format!("Could not read the name for output audio port {i}"))?,
+            flags: info.flags,
+            num_channels: info.channel_count,
+            port_type: port_type(&info),
+            in_place_pair_idx: None,
+        });
+    }
+
+    // Now we need to convert the stable in-place pair indices to vector indices
+    for (input_stable_id, (input_port_idx, pair_stable_id)) in input_stable_index_pairs
+        .iter()
+        .filter(|(_, (_, pair_stable_id))| *pair_stable_id != CLAP_INVALID_ID)
+    {
+        match output_stable_index_pairs
             .iter()
-            .filter(|(_, (_, pair_stable_id))| *pair_stable_id != CLAP_INVALID_ID)
+            .find(|(output_stable_id, (_, _))| *output_stable_id == pair_stable_id)
         {
-            match output_stable_index_pairs
-                .iter()
-                .find(|(output_stable_id, (_, _))| *output_stable_id == pair_stable_id)
+            // This relation should be symmetrical
+            Some((_, (pair_output_port_idx, output_pair_stable_id)))
+                if output_pair_stable_id == input_stable_id =>
             {
-                // This relation should be symmetrical
-                Some((_, (pair_output_port_idx, output_pair_stable_id)))
-                    if output_pair_stable_id == input_stable_id =>
-                {
-                    config.inputs[*input_port_idx].in_place_pair_idx = Some(*pair_output_port_idx);
-                    config.inputs[*pair_output_port_idx].in_place_pair_idx = Some(*input_port_idx);
-                }
-                Some((output_stable_id, (pair_output_port_idx, output_pair_stable_id))) => {
-                    anyhow::bail!(
-                        "Input port {input_port_idx} with stable ID {input_stable_id} is \
-                         connected to output port {pair_output_port_idx} with stable ID \
-                         {output_stable_id} through an in-place pair, but the relation is not \
-                         symmetrical. The output port reports to have an in-place pair with \
-                         stable ID {output_pair_stable_id}."
-                    )
-                }
-                None => anyhow::bail!(
-                    "Input port {input_port_idx} with stable ID {input_stable_id} claims to be \
-                     connected to an output port with stable ID {pair_stable_id} through an \
-                     in-place pair, but this port does not exist."
-                ),
+                config.inputs[*input_port_idx].in_place_pair_idx = Some(*pair_output_port_idx);
+                config.inputs[*pair_output_port_idx].in_place_pair_idx = Some(*input_port_idx);
             }
+            Some((output_stable_id, (pair_output_port_idx, output_pair_stable_id))) => {
+                anyhow::bail!(
+                    "Input port {input_port_idx} with stable ID {input_stable_id} is \
+                     connected to output port {pair_output_port_idx} with stable ID \
+                     {output_stable_id} through an in-place pair, but the relation is not \
+                     symmetrical. The output port reports to have an in-place pair with \
+                     stable ID {output_pair_stable_id}."
+                )
+            }
+            None => anyhow::bail!(
+                "Input port {input_port_idx} with stable ID {input_stable_id} claims to be \
+                 connected to an output port with stable ID {pair_stable_id} through an \
+                 in-place pair, but this port does not exist."
+            ),
         }
+    }
 
-        // This needs to be repeated for output ports that are connected to input ports in case an
-        // output port has a stable ID pair but the corresponding input port does not
-        for (output_stable_id, (output_port_idx, pair_stable_id)) in output_stable_index_pairs
+    // This needs to be repeated for output ports that are connected to input ports in case an
+    // output port has a stable ID pair but the corresponding input port does not
+    for (output_stable_id, (output_port_idx, pair_stable_id)) in output_stable_index_pairs
+        .iter()
+        .filter(|(_, (_, pair_stable_id))| *pair_stable_id != CLAP_INVALID_ID)
+    {
+        match input_stable_index_pairs
             .iter()
-            .filter(|(_, (_, pair_stable_id))| *pair_stable_id != CLAP_INVALID_ID)
+            .find(|(input_stable_id, (_, _))| *input_stable_id == pair_stable_id)
         {
-            match input_stable_index_pairs
-                .iter()
-                .find(|(input_stable_id, (_, _))| *input_stable_id == pair_stable_id)
+            Some((_, (pair_input_port_idx, input_pair_stable_id)))
+                if input_pair_stable_id == output_stable_id =>
             {
-                Some((_, (pair_input_port_idx, input_pair_stable_id)))
-                    if input_pair_stable_id == output_stable_id =>
-                {
-                    // We should have already done this. If this is not the case, then this is an
-                    // error in the validator
-                    assert_eq!(
-                        config.inputs[*output_port_idx].in_place_pair_idx,
-                        Some(*pair_input_port_idx)
-                    );
-                    assert_eq!(
-                        config.inputs[*pair_input_port_idx].in_place_pair_idx,
-                        Some(*output_port_idx)
-                    );
-                }
-                Some((input_stable_id, (pair_input_port_idx, input_pair_stable_id))) => {
-                    anyhow::bail!(
-                        "Output port {output_port_idx} with stable ID {output_stable_id} is \
-                         connected to input port {pair_input_port_idx} with stable ID \
-                         {input_stable_id} through an in-place pair, but the relation is not \
-                         symmetrical. The input port reports to have an in-place pair with stable \
-                         ID {input_pair_stable_id}."
-                    )
-                }
-                None => anyhow::bail!(
-                    "Output port {output_port_idx} with stable ID {output_stable_id} claims to be \
-                     connected to an input port with stable ID {pair_stable_id} through an \
-                     in-place pair, but this port does not exist."
-                ),
+                // We should have already done this. If this is not the case, then this is an
+                // error in the validator
+                assert_eq!(
+                    config.inputs[*output_port_idx].in_place_pair_idx,
+                    Some(*pair_input_port_idx)
+                );
+                assert_eq!(
+                    config.inputs[*pair_input_port_idx].in_place_pair_idx,
+                    Some(*output_port_idx)
+                );
             }
+            Some((input_stable_id, (pair_input_port_idx, input_pair_stable_id))) => {
+                anyhow::bail!(
+                    "Output port {output_port_idx} with stable ID {output_stable_id} is \
+                     connected to input port {pair_input_port_idx} with stable ID \
+                     {input_stable_id} through an in-place pair, but the relation is not \
+                     symmetrical. The input port reports to have an in-place pair with stable \
+                     ID {input_pair_stable_id}."
+                )
+            }
+            None => anyhow::bail!(
+                "Output port {output_port_idx} with stable ID {output_stable_id} claims to be \
+                 connected to an input port with stable ID {pair_stable_id} through an \
+                 in-place pair, but this port does not exist."
+            ),
         }
+    }
 
-        Ok(config)
+    Ok(config)
+}
+
+/// Get an audio port's type string, if it is set.
+fn port_type(info: &clap_audio_port_info) -> Option<String> {
+    if info.port_type.is_null() {
+        None
+    } else {
+        Some(
+            unsafe { CStr::from_ptr(info.port_type) }
+                .to_string_lossy()
+                .into_owned(),
+        )
     }
 }
 
@@ -279,3 +339,229 @@ impl AudioPortConfig {
         (input_buffers, output_buffers)
     }
 }
+
+/// Check whether the plugin may call `clap_host_audio_ports::rescan()` with `flags` in its current
+/// state. All flags except for `CLAP_AUDIO_PORTS_RESCAN_NAMES` may only be used while the plugin is
+/// deactivated, and the plugin should not use flags the host reported as unsupported through
+/// `clap_host_audio_ports::is_rescan_flag_supported()`.
+pub fn check_rescan_flags(flags: u32, supported_flags: u32, status: PluginStatus) -> Result<()> {
+    if (flags & !RESCAN_FLAGS) != 0 {
+        anyhow::bail!(
+            "The plugin called 'clap_host_audio_ports::rescan()' with flags {flags:#x}, which \
+             contains unknown flags."
+        );
+    }
+    if (flags & !supported_flags) != 0 {
+        anyhow::bail!(
+            "The plugin called 'clap_host_audio_ports::rescan()' with flags {flags:#x}, but the \
+             host does not support flags {:#x}. The plugin should check this using \
+             'clap_host_audio_ports::is_rescan_flag_supported()'.",
+            flags & !supported_flags
+        );
+    }
+    if (flags & DEACTIVATED_RESCAN_FLAGS) != 0 && status > PluginStatus::Deactivated {
+        anyhow::bail!(
+            "The plugin called 'clap_host_audio_ports::rescan()' with flags {flags:#x} while it \
+             was in the '{status:?}' state. Only 'CLAP_AUDIO_PORTS_RESCAN_NAMES' may be used \
+             while the plugin is active."
+        );
+    }
+
+    Ok(())
+}
+
+/// Check whether the changes between `old_config` and `new_config` are covered by the flags the
+/// plugin passed to `clap_host_audio_ports::rescan()`. `flags` should be zero if the plugin did not
+/// call the function at all.
+pub fn check_rescan_changes(
+    flags: u32,
+    old_config: &AudioPortConfig,
+    new_config: &AudioPortConfig,
+) -> Result<()> {
+    // This flag allows the plugin to change everything about its audio ports
+    if (flags & CLAP_AUDIO_PORTS_RESCAN_LIST) != 0 {
+        return Ok(());
+    }
+
+    if old_config.inputs.len() != new_config.inputs.len()
+        || old_config.outputs.len() != new_config.outputs.len()
+    {
+        anyhow::bail!(
+            "The plugin's number of audio ports changed from {} inputs and {} outputs to {} \
+             inputs and {} outputs without calling 'clap_host_audio_ports::rescan()' with \
+             'CLAP_AUDIO_PORTS_RESCAN_LIST'.",
+            old_config.inputs.len(),
+            old_config.outputs.len(),
+            new_config.inputs.len(),
+            new_config.outputs.len()
+        );
+    }
+
+    let old_ports = old_config.inputs.iter().chain(&old_config.outputs);
+    let new_ports = new_config.inputs.iter().chain(&new_config.outputs);
+    for (port_idx, (old_port, new_port)) in old_ports.zip(new_ports).enumerate() {
+        let port_name = if port_idx < old_config.inputs.len() {
+            format!("Input audio port {port_idx}")
+        } else {
+            format!("Output audio port {}", port_idx - old_config.inputs.len())
+        };
+
+        if old_port.name != new_port.name && (flags & CLAP_AUDIO_PORTS_RESCAN_NAMES) == 0 {
+            anyhow::bail!(
+                "{port_name}'s name changed from '{}' to '{}' without calling \
+                 'clap_host_audio_ports::rescan()' with 'CLAP_AUDIO_PORTS_RESCAN_NAMES'.",
+                old_port.name,
+                new_port.name
+            );
+        }
+        if old_port.flags != new_port.flags && (flags & CLAP_AUDIO_PORTS_RESCAN_FLAGS) == 0 {
+            anyhow::bail!(
+                "{port_name}'s flags changed from {:#x} to {:#x} without calling \
+                 'clap_host_audio_ports::rescan()' with 'CLAP_AUDIO_PORTS_RESCAN_FLAGS'.",
+                old_port.flags,
+                new_port.flags
+            );
+        }
+        if old_port.num_channels != new_port.num_channels
+            && (flags & CLAP_AUDIO_PORTS_RESCAN_CHANNEL_COUNT) == 0
+        {
+            anyhow::bail!(
+                "{port_name}'s channel count changed from {} to {} without calling \
+                 'clap_host_audio_ports::rescan()' with 'CLAP_AUDIO_PORTS_RESCAN_CHANNEL_COUNT'.",
+                old_port.num_channels,
+                new_port.num_channels
+            );
+        }
+        if old_port.in_place_pair_idx != new_port.in_place_pair_idx
+            && (flags & CLAP_AUDIO_PORTS_RESCAN_IN_PLACE_PAIR) == 0
+        {
+            anyhow::bail!(
+                "{port_name}'s in-place pair changed without calling \
+                 'clap_host_audio_ports::rescan()' with 'CLAP_AUDIO_PORTS_RESCAN_IN_PLACE_PAIR'."
+            );
+        }
+        if old_port.port_type != new_port.port_type
+            && (flags & CLAP_AUDIO_PORTS_RESCAN_PORT_TYPE) == 0
+        {
+            anyhow::bail!(
+                "{port_name}'s port type changed from {:?} to {:?} without calling \
+                 'clap_host_audio_ports::rescan()' with 'CLAP_AUDIO_PORTS_RESCAN_PORT_TYPE'.",
+                old_port.port_type,
+                new_port.port_type
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap_sys::ext::audio_ports::CLAP_AUDIO_PORT_IS_MAIN;
+
+    fn stereo_config() -> AudioPortConfig {
+        let port = AudioPort {
+            name: String::from("Main"),
+            flags: CLAP_AUDIO_PORT_IS_MAIN,
+            num_channels: 2,
+            port_type: Some(String::from("stereo")),
+            in_place_pair_idx: None,
+        };
+
+        AudioPortConfig {
+            inputs: vec![port.clone()],
+            outputs: vec![port],
+        }
+    }
+
+    #[test]
+    fn rejects_unsupported_flags() {
+        let supported_flags = RESCAN_FLAGS & !CLAP_AUDIO_PORTS_RESCAN_PORT_TYPE;
+
+        assert!(check_rescan_flags(
+            CLAP_AUDIO_PORTS_RESCAN_CHANNEL_COUNT,
+            supported_flags,
+            PluginStatus::Deactivated
+        )
+        .is_ok());
+        assert!(check_rescan_flags(
+            CLAP_AUDIO_PORTS_RESCAN_PORT_TYPE,
+            supported_flags,
+            PluginStatus::Deactivated
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn unchanged_config_needs_no_flags() {
+        assert!(check_rescan_changes(0, &stereo_config(), &stereo_config()).is_ok());
+    }
+
+    #[test]
+    fn name_changes_require_names_flag() {
+        let mut new_config = stereo_config();
+        new_config.outputs[0].name = String::from("Output");
+
+        assert!(check_rescan_changes(0, &stereo_config(), &new_config).is_err());
+        assert!(
+            check_rescan_changes(CLAP_AUDIO_PORTS_RESCAN_FLAGS, &stereo_config(), &new_config)
+                .is_err()
+        );
+        assert!(
+            check_rescan_changes(CLAP_AUDIO_PORTS_RESCAN_NAMES, &stereo_config(), &new_config)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn flag_changes_require_flags_flag() {
+        let mut new_config = stereo_config();
+        new_config.inputs[0].flags = 0;
+
+        assert!(check_rescan_changes(0, &stereo_config(), &new_config).is_err());
+        assert!(
+            check_rescan_changes(CLAP_AUDIO_PORTS_RESCAN_NAMES, &stereo_config(), &new_config)
+                .is_err()
+        );
+        assert!(
+            check_rescan_changes(CLAP_AUDIO_PORTS_RESCAN_FLAGS, &stereo_config(), &new_config)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn port_type_changes_require_port_type_flag() {
+        let mut new_config = stereo_config();
+        new_config.inputs[0].port_type = None;
+
+        assert!(check_rescan_changes(0, &stereo_config(), &new_config).is_err());
+        assert!(check_rescan_changes(
+            CLAP_AUDIO_PORTS_RESCAN_CHANNEL_COUNT,
+            &stereo_config(),
+            &new_config
+        )
+        .is_err());
+        assert!(check_rescan_changes(
+            CLAP_AUDIO_PORTS_RESCAN_PORT_TYPE,
+            &stereo_config(),
+            &new_config
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn list_flag_allows_all_changes() {
+        let mut new_config = stereo_config();
+        new_config.inputs.clear();
+        new_config.outputs[0].name = String::from("Output");
+        new_config.outputs[0].num_channels = 1;
+        new_config.outputs[0].port_type = Some(String::from("mono"));
+
+        assert!(check_rescan_changes(0, &stereo_config(), &new_config).is_err());
+        assert!(
+            check_rescan_changes(CLAP_AUDIO_PORTS_RESCAN_LIST, &stereo_config(), &new_config)
+                .is_ok()
+        );
+    }
+}
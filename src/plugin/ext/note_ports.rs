@@ -3,17 +3,22 @@
 use anyhow::Result;
 use clap_sys::ext::note_ports::{
     clap_note_dialect, clap_note_port_info, clap_plugin_note_ports, CLAP_EXT_NOTE_PORTS,
+    CLAP_NOTE_PORTS_RESCAN_ALL, CLAP_NOTE_PORTS_RESCAN_NAMES,
 };
+use clap_sys::plugin::clap_plugin;
 use std::collections::HashSet;
 use std::ffi::CStr;
 use std::mem;
 use std::ptr::NonNull;
 
-use crate::plugin::instance::Plugin;
+use crate::plugin::instance::{Plugin, PluginStatus};
 use crate::util::unsafe_clap_call;
 
 use super::Extension;
 
+/// All `CLAP_NOTE_PORTS_RESCAN_*` flags.
+const RESCAN_FLAGS: u32 = CLAP_NOTE_PORTS_RESCAN_ALL | CLAP_NOTE_PORTS_RESCAN_NAMES;
+
 /// Abstraction for the `note-ports` extension covering the main thread functionality.
 #[derive(Debug)]
 pub struct NotePorts<'a> {
@@ -22,7 +27,7 @@ pub struct NotePorts<'a> {
 }
 
 /// The note port configuration for a plugin.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct NotePortConfig {
     /// Configuration for the plugin's input note ports.
     pub inputs: Vec<NotePort>,
@@ -31,7 +36,7 @@ pub struct NotePortConfig {
 }
 
 /// The configuration for a single note port.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct NotePort {
     /// The preferred dialect for this note port. This should only ever contain a single value.
     pub prefered_dialect: clap_note_dialect,
@@ -57,101 +62,173 @@ impl NotePorts<'_> {
     /// Get the note port configuration for this plugin. This also checks whether the dialect types
     /// are consistent.
     pub fn config(&self) -> Result<NotePortConfig> {
-        let mut config = NotePortConfig::default();
-
-        let note_ports = self.note_ports.as_ptr();
-        let plugin = self.plugin.as_ptr();
-        let num_inputs = unsafe_clap_call! { note_ports=>count(plugin, true) };
-        let num_outputs = unsafe_clap_call! { note_ports=>count(plugin, false) };
-
-        // We don't need the port's stable IDs, but we'll still verify that they're unique
-        let mut input_stable_indices: HashSet<u32> = HashSet::new();
-        let mut output_stable_indices: HashSet<u32> = HashSet::new();
-
-        for i in 0..num_inputs {
-            let mut info: clap_note_port_info = unsafe { std::mem::zeroed() };
-            let success = unsafe_clap_call! { note_ports=>get(plugin, i, true, &mut info) };
-            if !success {
-                anyhow::bail!(
-                    "Plugin returned an error when querying input note port {i} ({num_inputs} \
-                     total input ports)."
-                );
-            }
-
-            let num_preferred_dialects = info.preferred_dialect.count_ones();
-            if num_preferred_dialects != 1 {
-                anyhow::bail!(
-                    "Plugin prefers {num_preferred_dialects} dialects for input note port {i}."
-                );
-            }
-
-            if (info.supported_dialects & info.preferred_dialect) == 0 {
-                anyhow::bail!(
-                    "Plugin prefers note dialect {:#b} for input note port {i} which is not \
-                     contained within the supported note dialects field ({:#b}).",
-                    info.preferred_dialect,
-                    info.supported_dialects
-                );
-            }
-
-            if !input_stable_indices.insert(info.id) {
-                anyhow::bail!(
-                    "The stable ID of input note port {i} ({}) is a duplicate.",
-                    info.id
-                );
-            }
-
-            config.inputs.push(NotePort {
-                prefered_dialect: info.preferred_dialect,
-                supported_dialects: (0..(mem::size_of::<clap_note_dialect>() * 8) - 1)
-                    .map(|bit| 1 << bit)
-                    .filter(|flag| (info.supported_dialects & flag) != 0)
-                    .collect(),
-            });
+        let config = unsafe { query_config(self.plugin.as_ptr(), self.note_ports.as_ptr()) }?;
+
+        // The host compares this against the new configuration when the plugin calls
+        // `clap_host_note_ports::rescan()`
+        *self.plugin.state.note_port_config.lock() = Some(config.clone());
+
+        Ok(config)
+    }
+}
+
+/// Query and check the note port configuration for a plugin. This is the implementation of
+/// [`NotePorts::config()`], and it is also used by the host to handle
+/// `clap_host_note_ports::rescan()` calls.
+///
+/// # Safety
+///
+/// `plugin` must point to an initialized plugin instance, and `note_ports` must point to that
+/// plugin's `clap_plugin_note_ports` struct.
+pub unsafe fn query_config(
+    plugin: *const clap_plugin,
+    note_ports: *const clap_plugin_note_ports,
+) -> Result<NotePortConfig> {
+    let mut config = NotePortConfig::default();
+
+    let num_inputs = unsafe_clap_call! { note_ports=>count(plugin, true) };
+    let num_outputs = unsafe_clap_call! { note_ports=>count(plugin, false) };
+
+    // We don't need the port's stable IDs, but we'll still verify that they're unique
+    let mut input_stable_indices: HashSet<u32> = HashSet::new();
+    let mut output_stable_indices: HashSet<u32> = HashSet::new();
+
+    for i in 0..num_inputs {
+        let mut info: clap_note_port_info = unsafe { std::mem::zeroed() };
+        let success = unsafe_clap_call! { note_ports=>get(plugin, i, true, &mut info) };
+        if !success {
+            anyhow::bail!(
+                "Plugin returned an error when querying input note port {i} ({num_inputs} \
+                 total input ports)."
+            );
         }
 
-        for i in 0..num_outputs {
-            let mut info: clap_note_port_info = unsafe { std::mem::zeroed() };
-            let success = unsafe_clap_call! { note_ports=>get(plugin, i, true, &mut info) };
-            if !success {
-                anyhow::bail!(
-                    "Plugin returned an error when querying output note port {i} ({num_outputs} \
-                     total output ports)."
-                );
-            }
-
-            let num_preferred_dialects = info.preferred_dialect.count_ones();
-            if num_preferred_dialects != 1 {
-                anyhow::bail!(
-                    "Plugin prefers {num_preferred_dialects} dialects for output note port {i}."
-                );
-            }
-
-            if (info.supported_dialects & info.preferred_dialect) == 0 {
-                anyhow::bail!(
-                    "Plugin prefers note dialect {:#b} for output note port {i} which is not \
-                     contained within the supported note dialects field ({:#b}).",
-                    info.preferred_dialect,
-                    info.supported_dialects
-                );
-            }
-
-            if !output_stable_indices.insert(info.id) {
-                anyhow::bail!(
-                    "The stable ID of output note port {i} ({}) is a duplicate.",
-                    info.id
-                );
-            }
-
-            config.outputs.push(NotePort {
-                prefered_dialect: info.preferred_dialect,
-                supported_dialects: (0..(mem::size_of::<clap_note_dialect>() * 8) - 1)
-                    .map(|bit| 1 << bit)
-                    .filter(|flag| (info.supported_dialects & flag) != 0)
-                    .collect(),
-            });
+        let num_preferred_dialects = info.preferred_dialect.count_ones();
+        if num_preferred_dialects != 1 {
+            anyhow::bail!(
+                "Plugin prefers {num_preferred_dialects} dialects for input note port {i}."
+            );
         }
 
-        Ok(config)
+        if (info.supported_dialects & info.preferred_dialect) == 0 {
+            anyhow::bail!(
+                "Plugin prefers note dialect {:#b} for input note port {i} which is not \
+                 contained within the supported note dialects field ({:#b}).",
+                info.preferred_dialect,
+                info.supported_dialects
+            );
+        }
+
+        if !input_stable_indices.insert(info.id) {
+            anyhow::bail!(
+                "The stable ID of input note port {i} ({}) is a duplicate.",
+                info.id
+            );
+        }
+
+        config.inputs.push(NotePort {
+            prefered_dialect: info.preferred_dialect,
+            supported_dialects: (0..(mem::size_of::<clap_note_dialect>() * 8) - 1)
+                .map(|bit| 1 << bit)
+                .filter(|flag| (info.supported_dialects & flag) != 0)
+                .collect(),
+        });
+    }
+
+    for i in 0..num_outputs {
+        let mut info: clap_note_port_info = unsafe { std::mem::zeroed() };
+        let success = unsafe_clap_call! { note_ports=>get(plugin, i, true, &mut info) };
+        if !success {
+            anyhow::bail!(
+                "Plugin returned an error when querying output note port {i} ({num_outputs} \
+                 total output ports)."
+            );
+        }
+
+        let num_preferred_dialects = info.preferred_dialect.count_ones();
+        if num_preferred_dialects != 1 {
+            anyhow::bail!(
+                "Plugin prefers {num_preferred_dialects} dialects for output note port {i}."
+            );
+        }
+
+        if (info.supported_dialects & info.preferred_dialect) == 0 {
+            anyhow::bail!(
+                "Plugin prefers note dialect {:#b} for output note port {i} which is not \
+                 contained within the supported note dialects field ({:#b}).",
+                info.preferred_dialect,
+                info.supported_dialects
+            );
+        }
+
+        if !output_stable_indices.insert(info.id) {
+            anyhow::bail!(
+                "The stable ID of output note port {i} ({}) is a duplicate.",
+                info.id
+            );
+        }
+
+        config.outputs.push(NotePort {
+            prefered_dialect: info.preferred_dialect,
+            supported_dialects: (0..(mem::size_of::<clap_note_dialect>() * 8) - 1)
+                .map(|bit| 1 << bit)
+                .filter(|flag| (info.supported_dialects & flag) != 0)
+                .collect(),
+        });
+    }
+
+    Ok(config)
+}
+
+/// Check whether the plugin may call `clap_host_note_ports::rescan()` with `flags` in its current
+/// state. `CLAP_NOTE_PORTS_RESCAN_ALL` may only be used while the plugin is deactivated.
+pub fn check_rescan_flags(flags: u32, status: PluginStatus) -> Result<()> {
+    if (flags & !RESCAN_FLAGS) != 0 {
+        anyhow::bail!(
+            "The plugin called 'clap_host_note_ports::rescan()' with flags {flags:#x}, which \
+             contains unknown flags."
+        );
     }
+    if (flags & CLAP_NOTE_PORTS_RESCAN_ALL) != 0 && status > PluginStatus::Deactivated {
+        anyhow::bail!(
+            "The plugin called 'clap_host_note_ports::rescan()' with \
+             'CLAP_NOTE_PORTS_RESCAN_ALL' while it was in the '{status:?}' state. This flag may \
+             only be used while the plugin is deactivated."
+        );
+    }
+
+    Ok(())
+}
+
+/// Check whether the changes between `old_config` and `new_config` are covered by the flags the
+/// plugin passed to `clap_host_note_ports::rescan()`. `flags` should be zero if the plugin did not
+/// call the function at all.
+pub fn check_rescan_changes(
+    flags: u32,
+    old_config: &NotePortConfig,
+    new_config: &NotePortConfig,
+) -> Result<()> {
+    // This flag allows the plugin to change everything about its note ports
+    if (flags & CLAP_NOTE_PORTS_RESCAN_ALL) != 0 || old_config == new_config {
+        return Ok(());
+    }
+
+    if old_config.inputs.len() != new_config.inputs.len()
+        || old_config.outputs.len() != new_config.outputs.len()
+    {
+        anyhow::bail!(
+            "The plugin's number of note ports changed from {} inputs and {} outputs to {} inputs \
+             and {} outputs without calling 'clap_host_note_ports::rescan()' with \
+             'CLAP_NOTE_PORTS_RESCAN_ALL'.",
+            old_config.inputs.len(),
+            old_config.outputs.len(),
+            new_config.inputs.len(),
+            new_config.outputs.len()
+        );
+    }
+
+    anyhow::bail!(
+        "The supported or preferred note dialects of the plugin's note ports changed without \
+         calling 'clap_host_note_ports::rescan()' with 'CLAP_NOTE_PORTS_RESCAN_ALL'."
+    );
 }
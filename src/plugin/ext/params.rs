@@ -3,15 +3,18 @@
 use anyhow::{Context, Result};
 use clap_sys::events::{clap_input_events, clap_output_events};
 use clap_sys::ext::params::{
-    clap_param_info, clap_param_info_flags, clap_plugin_params, CLAP_EXT_PARAMS,
-    CLAP_PARAM_IS_AUTOMATABLE, CLAP_PARAM_IS_AUTOMATABLE_PER_CHANNEL,
+    clap_param_clear_flags, clap_param_info, clap_param_info_flags, clap_param_rescan_flags,
+    clap_plugin_params, CLAP_EXT_PARAMS, CLAP_PARAM_CLEAR_ALL, CLAP_PARAM_CLEAR_AUTOMATIONS,
+    CLAP_PARAM_CLEAR_MODULATIONS, CLAP_PARAM_IS_AUTOMATABLE, CLAP_PARAM_IS_AUTOMATABLE_PER_CHANNEL,
     CLAP_PARAM_IS_AUTOMATABLE_PER_KEY, CLAP_PARAM_IS_AUTOMATABLE_PER_NOTE_ID,
-    CLAP_PARAM_IS_AUTOMATABLE_PER_PORT, CLAP_PARAM_IS_BYPASS, CLAP_PARAM_IS_MODULATABLE,
-    CLAP_PARAM_IS_MODULATABLE_PER_CHANNEL, CLAP_PARAM_IS_MODULATABLE_PER_KEY,
-    CLAP_PARAM_IS_MODULATABLE_PER_NOTE_ID, CLAP_PARAM_IS_MODULATABLE_PER_PORT,
-    CLAP_PARAM_IS_STEPPED,
+    CLAP_PARAM_IS_AUTOMATABLE_PER_PORT, CLAP_PARAM_IS_BYPASS, CLAP_PARAM_IS_HIDDEN,
+    CLAP_PARAM_IS_MODULATABLE, CLAP_PARAM_IS_MODULATABLE_PER_CHANNEL,
+    CLAP_PARAM_IS_MODULATABLE_PER_KEY, CLAP_PARAM_IS_MODULATABLE_PER_NOTE_ID,
+    CLAP_PARAM_IS_MODULATABLE_PER_PORT, CLAP_PARAM_IS_PERIODIC, CLAP_PARAM_IS_READONLY,
+    CLAP_PARAM_IS_STEPPED, CLAP_PARAM_RESCAN_ALL, CLAP_PARAM_RESCAN_INFO,
 };
 use clap_sys::id::clap_id;
+use clap_sys::plugin::clap_plugin;
 use clap_sys::string_sizes::CLAP_NAME_SIZE;
 use std::collections::BTreeMap;
 use std::ffi::{c_void, CStr, CString};
@@ -27,6 +30,29 @@ use crate::util::{self, c_char_slice_to_string, unsafe_clap_call};
 
 pub type ParamInfo = BTreeMap<clap_id, Param>;
 
+/// The parameter flags that may only change when the plugin calls `clap_host_params::rescan()`
+/// with `CLAP_PARAM_RESCAN_INFO`.
+const RESCAN_INFO_FLAGS: clap_param_info_flags = CLAP_PARAM_IS_PERIODIC | CLAP_PARAM_IS_HIDDEN;
+/// The parameter flags that may only change when the plugin calls `clap_host_params::rescan()`
+/// with `CLAP_PARAM_RESCAN_ALL`. These are the flags listed in the flag's documentation. Changes to
+/// flags not listed for either `CLAP_PARAM_RESCAN_INFO` or `CLAP_PARAM_RESCAN_ALL` are not checked.
+const RESCAN_ALL_FLAGS: clap_param_info_flags = CLAP_PARAM_IS_AUTOMATABLE_PER_NOTE_ID
+    | CLAP_PARAM_IS_AUTOMATABLE_PER_KEY
+    | CLAP_PARAM_IS_AUTOMATABLE_PER_CHANNEL
+    | CLAP_PARAM_IS_AUTOMATABLE_PER_PORT
+    | CLAP_PARAM_IS_MODULATABLE_PER_NOTE_ID
+    | CLAP_PARAM_IS_MODULATABLE_PER_KEY
+    | CLAP_PARAM_IS_MODULATABLE_PER_CHANNEL
+    | CLAP_PARAM_IS_MODULATABLE_PER_PORT
+    | CLAP_PARAM_IS_READONLY
+    | CLAP_PARAM_IS_BYPASS
+    | CLAP_PARAM_IS_STEPPED
+    | CLAP_PARAM_IS_MODULATABLE;
+
+/// All flags that can be passed to `clap_host_params::clear()`.
+const CLEAR_FLAGS: clap_param_clear_flags =
+    CLAP_PARAM_CLEAR_ALL | CLAP_PARAM_CLEAR_AUTOMATIONS | CLAP_PARAM_CLEAR_MODULATIONS;
+
 /// Abstraction for the `params` extension covering the main thread functionality.
 #[derive(Debug)]
 pub struct Params<'a> {
@@ -51,6 +77,8 @@ impl<'a> Extension<&'a Plugin<'a>> for Params<'a> {
 #[derive(Debug, Clone)]
 pub struct Param {
     pub name: String,
+    /// The module path the parameter is in, delimited by slashes. This may be empty.
+    pub module: String,
     /// This should be provided to the plugin when sending automation or modulation events for this
     /// parameter.
     pub cookie: *mut c_void,
@@ -62,6 +90,10 @@ pub struct Param {
     pub flags: clap_param_info_flags,
 }
 
+// The cookie is an opaque pointer that's only ever passed back to the plugin, so this can safely be
+// stored on the host
+unsafe impl Send for Param {}
+
 impl Params<'_> {
     /// Used by the status assertion macros.
     fn status(&self) -> PluginStatus {
@@ -138,208 +170,378 @@ impl Params<'_> {
     /// index, or if a parameter's minimum value is higher than the maximum value. This uses a
     /// BTreeMap to ensure the order is consistent between runs.
     pub fn info(&self) -> Result<ParamInfo> {
-        let mut result = BTreeMap::new();
+        let info = unsafe { query_info(self.plugin.as_ptr(), self.params.as_ptr()) }?;
 
-        let params = self.params.as_ptr();
-        let plugin = self.plugin.as_ptr();
-        let num_params = unsafe_clap_call! { params=>count(plugin) };
-
-        // Right now this is only used to make sure the plugin doesn't have multiple bypass parameters
-        let mut bypass_parameter_id = None;
-        for i in 0..num_params {
-            let mut info: clap_param_info = unsafe { std::mem::zeroed() };
-            let success = unsafe_clap_call! { params=>get_info(plugin, i, &mut info) };
-            if !success {
-                anyhow::bail!(
-                    "Plugin returned an error when querying parameter {i} ({num_params} total \
-                     parameters)."
-                );
-            }
+        // The host compares this against the new parameter information when the plugin calls
+        // `clap_host_params::rescan()`
+        *self.plugin.state.param_info.lock() = Some(info.clone());
 
-            let name = util::c_char_slice_to_string(&info.name).with_context(|| {
-                format!(
-                    "Could not read the name for parameter with stable ID {}",
-                    info.id
-                )
-            })?;
-
-            // We don't use the module string, but we'll still check it for consistency. Basically
-            // anything goes here as long as there are no trailing, leading, or multiple subsequent
-            // slashes.
-            let module = util::c_char_slice_to_string(&info.name).with_context(|| {
-                format!(
-                    "Could not read the module name for parameter '{}' (stable ID {})",
-                    &name, info.id
-                )
-            })?;
-            if module.starts_with('/') {
-                anyhow::bail!(
-                    "The module name for parameter '{}' (stable ID {}) starts with a leading \
-                     slash: '{}'.",
-                    &name,
-                    info.id,
-                    module
-                )
-            } else if module.ends_with('/') {
-                anyhow::bail!(
-                    "The module name for parameter '{}' (stable ID {}) ends with a trailing \
-                     slash: '{}'.",
-                    &name,
-                    info.id,
-                    module
-                )
-            } else if module.contains("//") {
-                anyhow::bail!(
-                    "The module name for parameter '{}' (stable ID {}) contains multiple \
-                     subsequent slashes: '{}'.",
-                    &name,
-                    info.id,
-                    module
-                )
-            }
+        Ok(info)
+    }
+
+    /// Perform a parameter flush.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the plugin is active.
+    pub fn flush(
+        &self,
+        input_events: &Pin<Box<EventQueue<clap_input_events>>>,
+        output_events: &Pin<Box<EventQueue<clap_output_events>>>,
+    ) {
+        // This may only be called on the audio thread when the plugin is active. This object is the
+        // main thread interface for the parameters extension.
+        assert_plugin_state_lt!(self, PluginStatus::Activated);
+
+        unsafe {
+            flush(
+                self.plugin.as_ptr(),
+                self.params.as_ptr(),
+                input_events,
+                output_events,
+            )
+        };
+    }
+}
+
+impl Param {
+    /// Whether this parameter is stepped.
+    pub fn stepped(&self) -> bool {
+        (self.flags & CLAP_PARAM_IS_STEPPED) != 0
+    }
+}
+
+/// Call the plugin's `clap_plugin_params::flush()` function. This is the implementation of
+/// [`Params::flush()`], and it is also used by the host to handle
+/// `clap_host_params::request_flush()` calls.
+///
+/// # Safety
+///
+/// `plugin` must point to an initialized plugin instance, and `params` must point to that
+/// plugin's `clap_plugin_params` struct. This must be called from the main thread while the plugin
+/// is deactivated.
+pub unsafe fn flush(
+    plugin: *const clap_plugin,
+    params: *const clap_plugin_params,
+    input_events: &Pin<Box<EventQueue<clap_input_events>>>,
+    output_events: &Pin<Box<EventQueue<clap_output_events>>>,
+) {
+    unsafe_clap_call! {
+        params=>flush(
+            plugin,
+            input_events.vtable(),
+            output_events.vtable(),
+        )
+    };
+}
+
+/// Query and check the parameter information for a plugin. This is the implementation of
+/// [`Params::info()`], and it is also used by the host to handle `clap_host_params::rescan()`
+/// calls.
+///
+/// # Safety
+///
+/// `plugin` must point to an initialized plugin instance, and `params` must point to that
+/// plugin's `clap_plugin_params` struct.
+pub unsafe fn query_info(
+    plugin: *const clap_plugin,
+    params: *const clap_plugin_params,
+) -> Result<ParamInfo> {
+    let mut result = BTreeMap::new();
+
+    let num_params = unsafe_clap_call! { params=>count(plugin) };
+
+    // Right now this is only used to make sure the plugin doesn't have multiple bypass parameters
+    let mut bypass_parameter_id = None;
+    for i in 0..num_params {
+        let mut info: clap_param_info = unsafe { std::mem::zeroed() };
+        let success = unsafe_clap_call! { params=>get_info(plugin, i, &mut info) };
+        if !success {
+            anyhow::bail!(
+                "Plugin returned an error when querying parameter {i} ({num_params} total \
+                 parameters)."
+            );
+        }
+
+        let name = util::c_char_slice_to_string(&info.name).with_context(|| {
+            format!(
+                "Could not read the name for parameter with stable ID {}",
+                info.id
+            )
+        })?;
 
-            let range = info.min_value..=info.max_value;
-            if info.min_value > info.max_value {
+        // We don't use the module string, but we'll still check it for consistency. Basically
+        // anything goes here as long as there are no trailing, leading, or multiple subsequent
+        // slashes.
+        let module = util::c_char_slice_to_string(&info.module).with_context(|| {
+            format!(
+                "Could not read the module name for parameter '{}' (stable ID {})",
+                &name, info.id
+            )
+        })?;
+        if module.starts_with('/') {
+            anyhow::bail!(
+                "The module name for parameter '{}' (stable ID {}) starts with a leading \
+                 slash: '{}'.",
+                &name,
+                info.id,
+                &module
+            )
+        } else if module.ends_with('/') {
+            anyhow::bail!(
+                "The module name for parameter '{}' (stable ID {}) ends with a trailing \
+                 slash: '{}'.",
+                &name,
+                info.id,
+                &module
+            )
+        } else if module.contains("//") {
+            anyhow::bail!(
+                "The module name for parameter '{}' (stable ID {}) contains multiple \
+                 subsequent slashes: '{}'.",
+                &name,
+                info.id,
+                &module
+            )
+        }
+
+        let range = info.min_value..=info.max_value;
+        if info.min_value > info.max_value {
+            anyhow::bail!(
+                "Parameter '{}' (stable ID {}) has a minimum value ({:?}) that's higher than \
+                 it's maximum value ({:?}).",
+                &name,
+                info.id,
+                info.min_value,
+                info.max_value
+            )
+        }
+        if !range.contains(&info.default_value) {
+            anyhow::bail!(
+                "Parameter '{}' (stable ID {}) has a default value ({:?}) that falls outside \
+                 of its value range ({:?}).",
+                &name,
+                info.id,
+                info.default_value,
+                &range
+            )
+        }
+        if (info.flags & CLAP_PARAM_IS_STEPPED) != 0 {
+            if info.min_value != info.min_value.trunc() {
                 anyhow::bail!(
-                    "Parameter '{}' (stable ID {}) has a minimum value ({:?}) that's higher than \
-                     it's maximum value ({:?}).",
+                    "Parameter '{}' (stable ID {}) is a stepped parameter, but its minimum \
+                     value ({:?}) is not an integer.",
                     &name,
                     info.id,
                     info.min_value,
-                    info.max_value
                 )
             }
-            if !range.contains(&info.default_value) {
+            if info.max_value != info.max_value.trunc() {
                 anyhow::bail!(
-                    "Parameter '{}' (stable ID {}) has a default value ({:?}) that falls outside \
-                     of its value range ({:?}).",
+                    "Parameter '{}' (stable ID {}) is a stepped parameter, but its maximum \
+                     value ({:?}) is not an integer.",
                     &name,
                     info.id,
-                    info.default_value,
-                    &range
+                    info.max_value,
                 )
             }
-            if (info.flags & CLAP_PARAM_IS_STEPPED) != 0 {
-                if info.min_value != info.min_value.trunc() {
-                    anyhow::bail!(
-                        "Parameter '{}' (stable ID {}) is a stepped parameter, but its minimum \
-                         value ({:?}) is not an integer.",
-                        &name,
-                        info.id,
-                        info.min_value,
-                    )
-                }
-                if info.max_value != info.max_value.trunc() {
-                    anyhow::bail!(
-                        "Parameter '{}' (stable ID {}) is a stepped parameter, but its maximum \
-                         value ({:?}) is not an integer.",
-                        &name,
-                        info.id,
-                        info.max_value,
-                    )
-                }
-            }
-            if (info.flags & CLAP_PARAM_IS_BYPASS) != 0 {
-                match bypass_parameter_id {
-                    Some(bypass_parameter_id) => anyhow::bail!(
-                        "The plugin has multiple bypass parameters (stable indices {} and {}).",
-                        bypass_parameter_id,
-                        info.id
-                    ),
-                    None => bypass_parameter_id = Some(info.id),
-                }
-
-                if (info.flags & CLAP_PARAM_IS_STEPPED) == 0 {
-                    anyhow::bail!(
-                        "Parameter '{}' (stable ID {}) is a bypass parameter, but it is not \
-                         stepped.",
-                        &name,
-                        info.id
-                    )
-                }
-            }
-
-            // The last check here makes sure that per-X automatable or modulatable parameters are
-            // also _just_ automatable/modulatable. This is technically allowed, but it is almost
-            // certainly a bug.
-            if (info.flags & CLAP_PARAM_IS_AUTOMATABLE) == 0
-                && (info.flags
-                    & (CLAP_PARAM_IS_AUTOMATABLE_PER_NOTE_ID
-                        | CLAP_PARAM_IS_AUTOMATABLE_PER_KEY
-                        | CLAP_PARAM_IS_AUTOMATABLE_PER_CHANNEL
-                        | CLAP_PARAM_IS_AUTOMATABLE_PER_PORT))
-                    != 0
-            {
-                anyhow::bail!(
-                    "Parameter '{}' (stable ID {}) is automatable per note ID, key, channel, or \
-                     port, but does not have CLAP_PARAM_IS_AUTOMATABLE. This is likely a bug.",
-                    &name,
+        }
+        if (info.flags & CLAP_PARAM_IS_BYPASS) != 0 {
+            match bypass_parameter_id {
+                Some(bypass_parameter_id) => anyhow::bail!(
+                    "The plugin has multiple bypass parameters (stable indices {} and {}).",
+                    bypass_parameter_id,
                     info.id
-                )
+                ),
+                None => bypass_parameter_id = Some(info.id),
             }
-            if (info.flags & CLAP_PARAM_IS_MODULATABLE) == 0
-                && (info.flags
-                    & (CLAP_PARAM_IS_MODULATABLE_PER_NOTE_ID
-                        | CLAP_PARAM_IS_MODULATABLE_PER_KEY
-                        | CLAP_PARAM_IS_MODULATABLE_PER_CHANNEL
-                        | CLAP_PARAM_IS_MODULATABLE_PER_PORT))
-                    != 0
-            {
+
+            if (info.flags & CLAP_PARAM_IS_STEPPED) == 0 {
                 anyhow::bail!(
-                    "Parameter '{}' (stable ID {}) is modulatable per note ID, key, channel, or \
-                     port, but does not have CLAP_PARAM_IS_MODULATABLE. This is likely a bug.",
+                    "Parameter '{}' (stable ID {}) is a bypass parameter, but it is not \
+                     stepped.",
                     &name,
                     info.id
                 )
             }
+        }
 
-            let processed_info = Param {
-                name,
-                cookie: info.cookie,
-                range,
-                default: info.default_value,
-                flags: info.flags,
-            };
-            if result.insert(info.id, processed_info).is_some() {
-                anyhow::bail!(
-                    "The plugin contains multiple parameters with stable ID {}.",
-                    info.id
-                );
-            }
+        // The last check here makes sure that per-X automatable or modulatable parameters are
+        // also _just_ automatable/modulatable. This is technically allowed, but it is almost
+        // certainly a bug.
+        if (info.flags & CLAP_PARAM_IS_AUTOMATABLE) == 0
+            && (info.flags
+                & (CLAP_PARAM_IS_AUTOMATABLE_PER_NOTE_ID
+                    | CLAP_PARAM_IS_AUTOMATABLE_PER_KEY
+                    | CLAP_PARAM_IS_AUTOMATABLE_PER_CHANNEL
+                    | CLAP_PARAM_IS_AUTOMATABLE_PER_PORT))
+                != 0
+        {
+            anyhow::bail!(
+                "Parameter '{}' (stable ID {}) is automatable per note ID, key, channel, or \
+                 port, but does not have CLAP_PARAM_IS_AUTOMATABLE. This is likely a bug.",
+                &name,
+                info.id
+            )
+        }
+        if (info.flags & CLAP_PARAM_IS_MODULATABLE) == 0
+            && (info.flags
+                & (CLAP_PARAM_IS_MODULATABLE_PER_NOTE_ID
+                    | CLAP_PARAM_IS_MODULATABLE_PER_KEY
+                    | CLAP_PARAM_IS_MODULATABLE_PER_CHANNEL
+                    | CLAP_PARAM_IS_MODULATABLE_PER_PORT))
+                != 0
+        {
+            anyhow::bail!(
+                "Parameter '{}' (stable ID {}) is modulatable per note ID, key, channel, or \
+                 port, but does not have CLAP_PARAM_IS_MODULATABLE. This is likely a bug.",
+                &name,
+                info.id
+            )
         }
 
-        Ok(result)
+        let processed_info = Param {
+            name,
+            module,
+            cookie: info.cookie,
+            range,
+            default: info.default_value,
+            flags: info.flags,
+        };
+        if result.insert(info.id, processed_info).is_some() {
+            anyhow::bail!(
+                "The plugin contains multiple parameters with stable ID {}.",
+                info.id
+            );
+        }
     }
 
-    /// Perform a parameter flush.
-    ///
-    /// # Panics
-    ///
-    /// Panics if the plugin is active.
-    pub fn flush(
-        &self,
-        input_events: &Pin<Box<EventQueue<clap_input_events>>>,
-        output_events: &Pin<Box<EventQueue<clap_output_events>>>,
-    ) {
-        // This may only be called on the audio thread when the plugin is active. This object is the
-        // main thread interface for the parameters extension.
-        assert_plugin_state_lt!(self, PluginStatus::Activated);
+    Ok(result)
+}
 
-        let params = self.params.as_ptr();
-        let plugin = self.plugin.as_ptr();
-        unsafe_clap_call! {
-            params=>flush(
-                plugin,
-                input_events.vtable(),
-                output_events.vtable(),
-            )
-        };
+/// Check whether the plugin may call `clap_host_params::rescan()` with `flags` in its current
+/// state. `CLAP_PARAM_RESCAN_ALL` may only be used while the plugin is deactivated.
+pub fn check_rescan_flags(flags: clap_param_rescan_flags, status: PluginStatus) -> Result<()> {
+    if (flags & CLAP_PARAM_RESCAN_ALL) != 0 && status > PluginStatus::Deactivated {
+        anyhow::bail!(
+            "The plugin called 'clap_host_params::rescan()' with 'CLAP_PARAM_RESCAN_ALL' while it \
+             was in the '{status:?}' state. This flag may only be used while the plugin is \
+             deactivated."
+        );
     }
+
+    Ok(())
 }
 
-impl Param {
-    /// Whether this parameter is stepped.
-    pub fn stepped(&self) -> bool {
-        (self.flags & CLAP_PARAM_IS_STEPPED) != 0
+/// Check whether `flags` is a valid combination of `CLAP_PARAM_CLEAR_*` flags for
+/// `clap_host_params::clear()`.
+pub fn check_clear_flags(flags: clap_param_clear_flags) -> Result<()> {
+    if flags == 0 {
+        anyhow::bail!("The plugin called 'clap_host_params::clear()' without any flags.");
+    }
+    if (flags & !CLEAR_FLAGS) != 0 {
+        anyhow::bail!(
+            "The plugin called 'clap_host_params::clear()' with flags {flags:#x}, which contains \
+             unknown flags."
+        );
+    }
+
+    Ok(())
+}
+
+/// Check whether the changes between `old_info` and `new_info` are allowed by the flags the plugin
+/// passed to `clap_host_params::rescan()`. See the documentation on the `CLAP_PARAM_RESCAN_*`
+/// flags for the changes each flag allows. `flags` should be zero if the plugin did not call the
+/// function at all.
+pub fn check_rescan_changes(
+    flags: clap_param_rescan_flags,
+    old_info: &ParamInfo,
+    new_info: &ParamInfo,
+) -> Result<()> {
+    // This flag invalidates everything the host knows about the parameters
+    if (flags & CLAP_PARAM_RESCAN_ALL) != 0 {
+        return Ok(());
+    }
+
+    let added_param_ids: Vec<clap_id> = new_info
+        .keys()
+        .filter(|param_id| !old_info.contains_key(param_id))
+        .copied()
+        .collect();
+    let removed_param_ids: Vec<clap_id> = old_info
+        .keys()
+        .filter(|param_id| !new_info.contains_key(param_id))
+        .copied()
+        .collect();
+    if !added_param_ids.is_empty() || !removed_param_ids.is_empty() {
+        anyhow::bail!(
+            "Parameters were added or removed without calling 'clap_host_params::rescan()' with \
+             'CLAP_PARAM_RESCAN_ALL'. Added parameter IDs: {added_param_ids:?}, removed parameter \
+             IDs: {removed_param_ids:?}."
+        );
     }
+
+    for (param_id, old_param) in old_info {
+        let new_param = &new_info[param_id];
+
+        let mut changes_requiring_all = Vec::new();
+        if old_param.range != new_param.range {
+            changes_requiring_all.push(format!(
+                "the value range changed from {:?} to {:?}",
+                old_param.range, new_param.range
+            ));
+        }
+        if old_param.cookie != new_param.cookie {
+            changes_requiring_all.push(String::from("the cookie changed"));
+        }
+        if ((old_param.flags ^ new_param.flags) & RESCAN_ALL_FLAGS) != 0 {
+            changes_requiring_all.push(format!(
+                "the flags changed from {:#x} to {:#x}",
+                old_param.flags, new_param.flags
+            ));
+        }
+        if !changes_requiring_all.is_empty() {
+            anyhow::bail!(
+                "For parameter '{}' (stable ID {}) {} without calling \
+                 'clap_host_params::rescan()' with 'CLAP_PARAM_RESCAN_ALL'.",
+                &new_param.name,
+                param_id,
+                changes_requiring_all.join(", ")
+            );
+        }
+
+        if (flags & CLAP_PARAM_RESCAN_INFO) == 0 {
+            let mut changes_requiring_info = Vec::new();
+            if old_param.name != new_param.name {
+                changes_requiring_info.push(format!(
+                    "the name changed from '{}' to '{}'",
+                    old_param.name, new_param.name
+                ));
+            }
+            if old_param.module != new_param.module {
+                changes_requiring_info.push(format!(
+                    "the module changed from '{}' to '{}'",
+                    old_param.module, new_param.module
+                ));
+            }
+            if ((old_param.flags ^ new_param.flags) & RESCAN_INFO_FLAGS) != 0 {
+                changes_requiring_info.push(format!(
+                    "the periodic or hidden flags changed from {:#x} to {:#x}",
+                    old_param.flags & RESCAN_INFO_FLAGS,
+                    new_param.flags & RESCAN_INFO_FLAGS
+                ));
+            }
+            if !changes_requiring_info.is_empty() {
+                anyhow::bail!(
+                    "For parameter '{}' (stable ID {}) {} without calling \
+                     'clap_host_params::rescan()' with 'CLAP_PARAM_RESCAN_INFO'.",
+                    &new_param.name,
+                    param_id,
+                    changes_requiring_info.join(", ")
+                );
+            }
+        }
+    }
+
+    Ok(())
 }
@@ -18,6 +18,41 @@ pub struct PresetLoad<'a> {
     preset_load: NonNull<clap_plugin_preset_load>,
 }
 
+/// A notification sent by the plugin through the host's `preset-load` extension. These are recorded
+/// by the host so they can be compared to the presets the host asked the plugin to load.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PresetLoadNotification {
+    /// The plugin called `clap_host_preset_load::loaded()`.
+    Loaded {
+        location: LocationValue,
+        load_key: Option<String>,
+    },
+    /// The plugin called `clap_host_preset_load::on_error()`.
+    Error {
+        location: LocationValue,
+        load_key: Option<String>,
+    },
+}
+
+impl std::fmt::Display for PresetLoadNotification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (function_name, location, load_key) = match self {
+            PresetLoadNotification::Loaded { location, load_key } => {
+                ("clap_host_preset_load::loaded()", location, load_key)
+            }
+            PresetLoadNotification::Error { location, load_key } => {
+                ("clap_host_preset_load::on_error()", location, load_key)
+            }
+        };
+
+        write!(f, "'{function_name}' for {location}")?;
+        match load_key {
+            Some(load_key) => write!(f, " with load key '{load_key}'"),
+            None => write!(f, " with no load key"),
+        }
+    }
+}
+
 impl<'a> Extension<&'a Plugin<'a>> for PresetLoad<'a> {
     const EXTENSION_ID: &'static CStr = CLAP_EXT_PRESET_LOAD;
 
@@ -1,17 +1,32 @@
 //! Data structures and utilities for hosting plugins.
 
 use anyhow::{Context, Result};
+use clap_sys::events::clap_input_events;
 use clap_sys::ext::audio_ports::{clap_host_audio_ports, CLAP_EXT_AUDIO_PORTS};
 use clap_sys::ext::draft::preset_load::{clap_host_preset_load, CLAP_EXT_PRESET_LOAD};
+use clap_sys::ext::log::{
+    clap_host_log, clap_log_severity, CLAP_EXT_LOG, CLAP_LOG_DEBUG, CLAP_LOG_ERROR, CLAP_LOG_FATAL,
+    CLAP_LOG_HOST_MISBEHAVING, CLAP_LOG_INFO, CLAP_LOG_PLUGIN_MISBEHAVING, CLAP_LOG_WARNING,
+};
 use clap_sys::ext::note_ports::{
     clap_host_note_ports, clap_note_dialect, CLAP_EXT_NOTE_PORTS, CLAP_NOTE_DIALECT_CLAP,
     CLAP_NOTE_DIALECT_MIDI, CLAP_NOTE_DIALECT_MIDI_MPE,
 };
 use clap_sys::ext::params::{
     clap_host_params, clap_param_clear_flags, clap_param_rescan_flags, CLAP_EXT_PARAMS,
+    CLAP_PARAM_CLEAR_ALL,
+};
+use clap_sys::ext::posix_fd_support::clap_posix_fd_flags;
+#[cfg(target_os = "linux")]
+use clap_sys::ext::posix_fd_support::{
+    clap_host_posix_fd_support, clap_plugin_posix_fd_support, CLAP_EXT_POSIX_FD_SUPPORT,
+    CLAP_POSIX_FD_ERROR, CLAP_POSIX_FD_READ, CLAP_POSIX_FD_WRITE,
 };
 use clap_sys::ext::state::{clap_host_state, CLAP_EXT_STATE};
 use clap_sys::ext::thread_check::{clap_host_thread_check, CLAP_EXT_THREAD_CHECK};
+use clap_sys::ext::timer_support::{
+    clap_host_timer_support, clap_plugin_timer_support, CLAP_EXT_TIMER_SUPPORT,
+};
 use clap_sys::factory::draft::preset_discovery::clap_preset_discovery_location_kind;
 use clap_sys::host::clap_host;
 use clap_sys::id::clap_id;
@@ -19,20 +34,34 @@ use clap_sys::plugin::clap_plugin;
 use clap_sys::version::CLAP_VERSION;
 use crossbeam::atomic::AtomicCell;
 use crossbeam::channel;
-use parking_lot::Mutex;
-use std::cell::RefCell;
-use std::collections::HashMap;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::ffi::{c_void, CStr, CString};
 use std::os::raw::c_char;
 use std::pin::Pin;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::thread::ThreadId;
-
-use crate::plugin::instance::{PluginHandle, PluginStatus};
+use std::time::{Duration, Instant};
+
+use crate::plugin::ext::audio_ports::{self, AudioPortConfig};
+use crate::plugin::ext::note_ports::{self, NotePortConfig};
+use crate::plugin::ext::params::{self, ParamInfo};
+use crate::plugin::ext::preset_load::PresetLoadNotification;
+use crate::plugin::instance::process::EventQueue;
+use crate::plugin::instance::{pause_audit, PluginHandle, PluginStatus};
 use crate::plugin::preset_discovery::LocationValue;
 use crate::util::{self, check_null_ptr, unsafe_clap_call};
 
+/// The shortest period the host uses for timers registered through
+/// `clap_host_timer_support::register_timer()`. Hosts are allowed to adjust the requested period.
+const MIN_TIMER_PERIOD: Duration = Duration::from_millis(1);
+/// How often the file descriptors registered through `clap_host_posix_fd_support::register_fd()`
+/// are polled while [`Host::handle_callbacks_blocking()`] is waiting for callbacks.
+#[cfg(target_os = "linux")]
+const POSIX_FD_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
 /// An abstraction for a CLAP plugin host.
 ///
 /// - It handles callback requests made by the plugin, and it checks whether the calling thread
@@ -52,15 +81,20 @@ use crate::util::{self, check_null_ptr, unsafe_clap_call};
 pub struct Host {
     /// The ID of the main thread.
     main_thread_id: ThreadId,
+    /// Configures how the host responds to the plugin's queries. See [`HostSettings`].
+    settings: HostSettings,
     /// A description of the first error encountered during a callback by this `Host`, if any. This
     /// is primarily used to check that the plugin called all host callbacks from the correct thread
     /// after the rest of the test has succeeded.
-    callback_error: RefCell<Option<String>>,
+    ///
+    /// This and the other fields use locks instead of `RefCell`s because multiple audio threads may
+    /// call back into the host at the same time.
+    callback_error: Mutex<Option<String>>,
 
     /// These are the plugin instances taht were registered on this host. They're added here when
     /// the `Plugin` object is created, and they're removed when the object is dropped. This is used
     /// to keep track of audio threads and pending callbacks.
-    instances: RefCell<HashMap<PluginHandle, Pin<Arc<InstanceState>>>>,
+    instances: RwLock<HashMap<PluginHandle, Pin<Arc<InstanceState>>>>,
 
     /// Allows waking up the main thread for callbacks while running
     /// [`handle_callbacks_blocking()`][Self::handle_callbacks_blocking()]. Other threads can also
@@ -70,13 +104,65 @@ pub struct Host {
     /// [`handle_callbacks_blocking()`][Self::handle_callbacks_blocking()].
     callback_task_receiver: channel::Receiver<CallbackTask>,
 
+    /// Used to poll the file descriptors the plugin instances registered through
+    /// `clap_host_posix_fd_support::register_fd()`. This is `None` if the epoll instance could not
+    /// be created, in which case the host does not expose the extension.
+    #[cfg(target_os = "linux")]
+    epoll: Option<util::epoll::Epoll>,
+    /// The flags every plugin instance registered a file descriptor with. Multiple instances of a
+    /// plugin may register the same file descriptor, for instance when the plugin uses a single
+    /// event loop for all of its instances. The file descriptor is added to the epoll instance for
+    /// the first registration, it's watched for the union of all instances' flags, and it's only
+    /// removed again after the last instance unregistered it.
+    #[cfg(target_os = "linux")]
+    posix_fd_registrations: Mutex<HashMap<i32, Vec<clap_posix_fd_flags>>>,
+
     // These are the vtables for the extensions supported by the host
     clap_host_audio_ports: clap_host_audio_ports,
+    clap_host_log: clap_host_log,
     clap_host_note_ports: clap_host_note_ports,
     clap_host_params: clap_host_params,
+    #[cfg(target_os = "linux")]
+    clap_host_posix_fd_support: clap_host_posix_fd_support,
     clap_host_preset_load: clap_host_preset_load,
     clap_host_state: clap_host_state,
     clap_host_thread_check: clap_host_thread_check,
+    clap_host_timer_support: clap_host_timer_support,
+}
+
+/// Settings for a [`Host`] that change how it responds to the plugin. These are set through the
+/// validator's command line options and passed to [`Host::new()`].
+#[derive(Debug, Clone)]
+pub struct HostSettings {
+    /// The `CLAP_AUDIO_PORTS_RESCAN_*` flags the host reports as supported through
+    /// `clap_host_audio_ports::is_rescan_flag_supported()`. The host will also reject calls to
+    /// `clap_host_audio_ports::rescan()` using the other flags. By default all flags are supported.
+    pub supported_audio_ports_rescan_flags: u32,
+    /// The buffer the hosts add the messages logged through `clap_host_log::log()` to. Every test
+    /// should use a new buffer so the messages can be included in that test's result.
+    pub plugin_log: PluginLog,
+}
+
+impl Default for HostSettings {
+    fn default() -> Self {
+        Self {
+            supported_audio_ports_rescan_flags: audio_ports::RESCAN_FLAGS,
+            plugin_log: PluginLog::default(),
+        }
+    }
+}
+
+/// The messages the plugin instances logged through `clap_host_log::log()`. Clones of this object
+/// refer to the same buffer, so the hosts created during a test can share it with the code that
+/// creates the test's result.
+#[derive(Debug, Clone, Default)]
+pub struct PluginLog(Arc<Mutex<Vec<PluginLogMessage>>>);
+
+impl PluginLog {
+    /// Take all messages logged so far.
+    pub fn take(&self) -> Vec<PluginLogMessage> {
+        std::mem::take(&mut *self.0.lock())
+    }
 }
 
 /// Runtime information about a plugin instance. This keeps track of pending callbacks and things
@@ -118,6 +204,135 @@ pub struct InstanceState {
     /// [`ProcessingTest::run`][crate::testa::plugin::processing::ProcessingTest::run] function to
     /// deactivate and reactivate.
     pub requested_restart: AtomicBool,
+    /// Whether the plugin has called `clap_host::request_process()` and expects the host to resume
+    /// calling `clap_plugin::process()` after it returned `CLAP_PROCESS_SLEEP`.
+    ///
+    /// This flag is reset at the start of the `ProcessingTest::run*` functions, and
+    /// [`ProcessingTest::run`][crate::tests::plugin::processing::ProcessingTest::run] uses it to
+    /// wake up a sleeping plugin.
+    pub requested_process: AtomicBool,
+    /// The total number of times the plugin has called `clap_host::request_process()`. This is
+    /// incremented after [`requested_process`][Self::requested_process] has been set, so
+    /// [`ProcessingTest::run`][crate::tests::plugin::processing::ProcessingTest::run] can use it to
+    /// check that every request made while the plugin was sleeping woke the plugin up.
+    pub num_process_requests: AtomicUsize,
+    /// Whether the plugin has called `clap_host_params::request_flush()` and expects the host to
+    /// call `clap_plugin_params::flush()` or `clap_plugin::process()`. While the plugin is
+    /// deactivated this is handled on the main thread by
+    /// [`Host::handle_callbacks_once()`][Host::handle_callbacks_once()].
+    pub requested_flush: AtomicBool,
+
+    /// The plugin's parameter information as last seen by the host. This is updated whenever the
+    /// parameters are queried through
+    /// [`Params::info()`][crate::plugin::ext::params::Params::info()] and when the plugin calls
+    /// `clap_host_params::rescan()`, so the host can check whether the changes made in between
+    /// match the rescan flags.
+    pub param_info: Mutex<Option<ParamInfo>>,
+    /// All flags the plugin has passed to `clap_host_params::rescan()` so far, combined. Tests can
+    /// reset this to check whether the plugin requested a rescan after some operation.
+    pub param_rescan_flags: AtomicU32,
+    /// The flags passed to `clap_host_params::rescan()` since the host last queried the plugin's
+    /// parameters, combined. The plugin should not be called back from within `rescan()`, so the
+    /// parameters are queried again in [`Host::handle_callbacks_once()`] instead.
+    pending_param_rescan_flags: AtomicU32,
+    /// The references the host holds to the plugin's parameters, like automation lanes and
+    /// modulation. These are stored as a combination of `CLAP_PARAM_CLEAR_AUTOMATIONS` and
+    /// `CLAP_PARAM_CLEAR_MODULATIONS` flags per parameter ID, and the plugin can remove them by
+    /// calling `clap_host_params::clear()`. Tests can add references to check whether the plugin
+    /// clears them when it removes parameters.
+    pub param_references: Mutex<BTreeMap<clap_id, clap_param_clear_flags>>,
+    /// The IDs of all parameters that have been removed during a call to
+    /// `clap_host_params::rescan()`. The plugin may still clear references to these parameters.
+    removed_param_ids: Mutex<BTreeSet<clap_id>>,
+    /// The IDs the plugin passed to `clap_host_params::clear()` that did not belong to a current or
+    /// removed parameter at the time. Plugins may clear references to IDs they're about to reuse
+    /// before calling `clap_host_params::rescan()`, so these are checked after the next rescan.
+    unknown_cleared_param_ids: Mutex<BTreeSet<clap_id>>,
+    /// The plugin's audio port configuration as last seen by the host. Like
+    /// [`param_info`][Self::param_info], this is used to check whether the plugin's changes match
+    /// the flags passed to `clap_host_audio_ports::rescan()`.
+    pub audio_port_config: Mutex<Option<AudioPortConfig>>,
+    /// The flags passed to `clap_host_audio_ports::rescan()` since the host last queried the
+    /// plugin's audio ports, combined. Like with
+    /// [`pending_param_rescan_flags`][Self::pending_param_rescan_flags], the audio ports are
+    /// queried again in [`Host::handle_callbacks_once()`].
+    pending_audio_ports_rescan_flags: AtomicU32,
+    /// The plugin's note port configuration as last seen by the host. This is used to check the
+    /// changes made before calling `clap_host_note_ports::rescan()`.
+    pub note_port_config: Mutex<Option<NotePortConfig>>,
+    /// The flags passed to `clap_host_note_ports::rescan()` since the host last queried the
+    /// plugin's note ports, combined.
+    pending_note_ports_rescan_flags: AtomicU32,
+    /// The times at which the plugin called `clap_host_state::mark_dirty()`. Tests can clear this
+    /// to check whether or how often the plugin marked its state as dirty after some operation.
+    pub dirty_marks: Mutex<Vec<Instant>>,
+    /// The `clap_host_preset_load::loaded()` and `clap_host_preset_load::on_error()` calls made by
+    /// the plugin, in order. Tests can take these to check whether the plugin responded to a
+    /// `clap_plugin_preset_load::from_location()` call with the correct notification.
+    pub preset_load_notifications: Mutex<Vec<PresetLoadNotification>>,
+    /// The timers the plugin has registered through `clap_host_timer_support::register_timer()`,
+    /// indexed by their IDs. These are fired by
+    /// [`Host::handle_callbacks_blocking()`][Host::handle_callbacks_blocking()].
+    pub timers: Mutex<BTreeMap<clap_id, Timer>>,
+    /// The number of timers the plugin has registered so far. This is also used as the ID for the
+    /// next timer.
+    pub num_registered_timers: AtomicU32,
+    /// The number of times `clap_plugin_timer_support::on_timer()` has been called.
+    pub num_timer_calls: AtomicUsize,
+    /// The file descriptors the plugin has registered through
+    /// `clap_host_posix_fd_support::register_fd()` along with their `CLAP_POSIX_FD_*` flags. These
+    /// are polled by [`Host::handle_callbacks_blocking()`][Host::handle_callbacks_blocking()].
+    pub posix_fds: Mutex<BTreeMap<i32, clap_posix_fd_flags>>,
+    /// The number of file descriptors the plugin has registered so far.
+    pub num_registered_posix_fds: AtomicUsize,
+    /// The number of times `clap_plugin_posix_fd_support::on_fd()` has been called.
+    pub num_posix_fd_calls: AtomicUsize,
+}
+
+/// A timer registered by the plugin through `clap_host_timer_support::register_timer()`.
+#[derive(Debug)]
+pub struct Timer {
+    /// The timer's period. Periods shorter than [`MIN_TIMER_PERIOD`] are rounded up.
+    pub period: Duration,
+    /// When the timer should fire next.
+    next_tick: Instant,
+}
+
+/// A message the plugin logged through `clap_host_log::log()`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PluginLogMessage {
+    pub severity: LogSeverity,
+    /// The kind of thread the plugin called `clap_host_log::log()` from.
+    pub thread: LogThread,
+    pub message: String,
+}
+
+/// The kind of thread a message was logged from. `clap_host_log::log()` is thread-safe, so this is
+/// recorded so messages logged from the audio thread or from the plugin's own threads can be told
+/// apart from the others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogThread {
+    Main,
+    Audio,
+    /// A thread that's neither the main thread nor one of the plugin instances' audio threads,
+    /// usually a thread started by the plugin.
+    Other,
+}
+
+/// The equivalent of `clap_log_severity`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogSeverity {
+    Debug,
+    Info,
+    Warning,
+    Error,
+    Fatal,
+    /// The plugin is reporting that the host, i.e. the validator, is doing something wrong.
+    HostMisbehaving,
+    /// The plugin is reporting that it did something wrong itself.
+    PluginMisbehaving,
 }
 
 /// When the host is handling callbacks in a blocking fashion, other threads can send tasks over the
@@ -134,6 +349,21 @@ pub enum CallbackTask {
     Stop,
 }
 
+/// Convert `CLAP_POSIX_FD_*` flags to the corresponding epoll events. Errors and hangups are always
+/// reported by epoll.
+#[cfg(target_os = "linux")]
+fn posix_fd_flags_to_epoll_events(flags: clap_posix_fd_flags) -> u32 {
+    let mut events = 0;
+    if flags & CLAP_POSIX_FD_READ != 0 {
+        events |= libc::EPOLLIN as u32;
+    }
+    if flags & CLAP_POSIX_FD_WRITE != 0 {
+        events |= libc::EPOLLOUT as u32;
+    }
+
+    events
+}
+
 impl InstanceState {
     /// Construct a new plugin instance object. The [`InstanceState::plugin`] field must be set
     /// later because the `clap_host` struct needs to be passed to `clap_factory::create_plugin()`,
@@ -168,6 +398,28 @@ impl InstanceState {
             audio_thread: AtomicCell::new(None),
             requested_callback: AtomicBool::new(false),
             requested_restart: AtomicBool::new(false),
+            requested_process: AtomicBool::new(false),
+            num_process_requests: AtomicUsize::new(0),
+            requested_flush: AtomicBool::new(false),
+
+            param_info: Mutex::new(None),
+            param_rescan_flags: AtomicU32::new(0),
+            pending_param_rescan_flags: AtomicU32::new(0),
+            param_references: Mutex::new(BTreeMap::new()),
+            removed_param_ids: Mutex::new(BTreeSet::new()),
+            unknown_cleared_param_ids: Mutex::new(BTreeSet::new()),
+            audio_port_config: Mutex::new(None),
+            pending_audio_ports_rescan_flags: AtomicU32::new(0),
+            note_port_config: Mutex::new(None),
+            pending_note_ports_rescan_flags: AtomicU32::new(0),
+            dirty_marks: Mutex::new(Vec::new()),
+            preset_load_notifications: Mutex::new(Vec::new()),
+            timers: Mutex::new(BTreeMap::new()),
+            num_registered_timers: AtomicU32::new(0),
+            num_timer_calls: AtomicUsize::new(0),
+            posix_fds: Mutex::new(BTreeMap::new()),
+            num_registered_posix_fds: AtomicUsize::new(0),
+            num_posix_fd_calls: AtomicUsize::new(0),
         });
 
         // We need to get the pointer to the pinned `InstanceState` into the `clap_host::host_data`
@@ -216,11 +468,228 @@ impl InstanceState {
             .0
             .as_ptr()
     }
+
+    /// Query the plugin's parameters again after it called `clap_host_params::rescan()`, and check
+    /// whether the changes to the parameters match the (combined) flags passed to those calls. The
+    /// flags themselves are checked when `rescan()` is called. Should only be called from the main
+    /// thread.
+    fn rescan_params(&self, flags: clap_param_rescan_flags) -> Result<()> {
+        // The plugin's extensions can only be queried after it has been initialized, and there is
+        // no previous parameter information to compare against at that point anyways
+        if self.status.load() == PluginStatus::Uninitialized {
+            return Ok(());
+        }
+
+        let plugin = self.plugin_ptr();
+        let params = self.plugin_extension(CLAP_EXT_PARAMS, "clap_host_params::rescan()")?;
+        let new_info = unsafe { params::query_info(plugin, params) }.context(
+            "Could not query the plugin's parameters after a call to 'clap_host_params::rescan()'",
+        )?;
+        let mut param_info = self.param_info.lock();
+        if let Some(old_info) = &*param_info {
+            params::check_rescan_changes(flags, old_info, &new_info).with_context(|| {
+                format!("The plugin called 'clap_host_params::rescan()' with flags {flags:#x}")
+            })?;
+
+            self.removed_param_ids.lock().extend(
+                old_info
+                    .keys()
+                    .filter(|param_id| !new_info.contains_key(param_id)),
+            );
+        }
+
+        let unknown_cleared_param_ids: Vec<clap_id> =
+            std::mem::take(&mut *self.unknown_cleared_param_ids.lock())
+                .into_iter()
+                .filter(|param_id| !new_info.contains_key(param_id))
+                .collect();
+        *param_info = Some(new_info);
+
+        if !unknown_cleared_param_ids.is_empty() {
+            anyhow::bail!(
+                "The plugin called 'clap_host_params::clear()' for parameter IDs {:?}, but the \
+                 plugin did not have parameters with those IDs at the time, none had been removed \
+                 during a call to 'clap_host_params::rescan()', and the plugin still does not have \
+                 parameters with those IDs after the next rescan.",
+                unknown_cleared_param_ids
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Query the plugin's audio ports again after it called `clap_host_audio_ports::rescan()`, and
+    /// check whether the changes made to them are covered by the (combined) flags passed to those
+    /// calls. The flags themselves are checked when `rescan()` is called. Should only be called
+    /// from the main thread.
+    fn rescan_audio_ports(&self, flags: u32) -> Result<()> {
+        if self.status.load() == PluginStatus::Uninitialized {
+            return Ok(());
+        }
+
+        let plugin = self.plugin_ptr();
+        let audio_ports =
+            self.plugin_extension(CLAP_EXT_AUDIO_PORTS, "clap_host_audio_ports::rescan()")?;
+        let new_config = unsafe { audio_ports::query_config(plugin, audio_ports) }.context(
+            "The plugin's audio port configuration is inconsistent after a call to \
+             'clap_host_audio_ports::rescan()'",
+        )?;
+        let mut audio_port_config = self.audio_port_config.lock();
+        if let Some(old_config) = &*audio_port_config {
+            let result = audio_ports::check_rescan_changes(flags, old_config, &new_config);
+            result.with_context(|| {
+                format!("The plugin called 'clap_host_audio_ports::rescan()' with flags {flags:#x}")
+            })?;
+        }
+        *audio_port_config = Some(new_config);
+
+        Ok(())
+    }
+
+    /// Query the plugin's note ports again after it called `clap_host_note_ports::rescan()`. This
+    /// works the same way as [`rescan_audio_ports()`][Self::rescan_audio_ports()].
+    fn rescan_note_ports(&self, flags: u32) -> Result<()> {
+        if self.status.load() == PluginStatus::Uninitialized {
+            return Ok(());
+        }
+
+        let plugin = self.plugin_ptr();
+        let note_ports =
+            self.plugin_extension(CLAP_EXT_NOTE_PORTS, "clap_host_note_ports::rescan()")?;
+        let new_config = unsafe { note_ports::query_config(plugin, note_ports) }.context(
+            "The plugin's note port configuration is inconsistent after a call to \
+             'clap_host_note_ports::rescan()'",
+        )?;
+        let mut note_port_config = self.note_port_config.lock();
+        if let Some(old_config) = &*note_port_config {
+            let result = note_ports::check_rescan_changes(flags, old_config, &new_config);
+            result.with_context(|| {
+                format!("The plugin called 'clap_host_note_ports::rescan()' with flags {flags:#x}")
+            })?;
+        }
+        *note_port_config = Some(new_config);
+
+        Ok(())
+    }
+
+    /// Handle a call to `clap_host_params::clear()` by removing the references from
+    /// [`param_references`][Self::param_references] matching `flags`. Returns an error if the
+    /// flags are invalid. If the parameter is not known to the host and it has not been removed
+    /// during a rescan either, then the ID is checked again after the next rescan.
+    fn clear_param(&self, param_id: clap_id, flags: clap_param_clear_flags) -> Result<()> {
+        params::check_clear_flags(flags)?;
+
+        // If the host has never queried the plugin's parameters, then there's nothing to check the
+        // parameter ID against
+        if let Some(param_info) = &*self.param_info.lock() {
+            if !param_info.contains_key(&param_id)
+                && !self.removed_param_ids.lock().contains(&param_id)
+            {
+                log::trace!(
+                    "The plugin called 'clap_host_params::clear()' for unknown parameter ID \
+                     {param_id}, checking whether it exists after the next rescan"
+                );
+                self.unknown_cleared_param_ids.lock().insert(param_id);
+            }
+        }
+
+        let mut param_references = self.param_references.lock();
+        if (flags & CLAP_PARAM_CLEAR_ALL) != 0 {
+            param_references.remove(&param_id);
+        } else if let Some(references) = param_references.get_mut(&param_id) {
+            *references &= !flags;
+            if *references == 0 {
+                param_references.remove(&param_id);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Call the plugin's `clap_plugin_params::flush()` function on the main thread with the events
+    /// from `input_events`. This is how the host handles `clap_host_params::request_flush()` while
+    /// the plugin is deactivated. The events output by the plugin are checked the same way as
+    /// those output during audio processing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the plugin is active.
+    pub fn flush_params(
+        &self,
+        input_events: &Pin<Box<EventQueue<clap_input_events>>>,
+    ) -> Result<()> {
+        // While the plugin is active, the flush needs to happen on the audio thread instead
+        let status = self.status.load();
+        assert!(
+            status > PluginStatus::Uninitialized && status < PluginStatus::Activated,
+            "Tried to flush the plugin's parameters while the plugin was in the '{status:?}' \
+             state. This is a clap-validator bug."
+        );
+
+        let plugin = self.plugin_ptr();
+        let params = self.plugin_extension(CLAP_EXT_PARAMS, "clap_host_params::request_flush()")?;
+        let output_events = EventQueue::new_output();
+        unsafe { params::flush(plugin, params, input_events, &output_events) };
+
+        output_events
+            .check_output_order(None)
+            .context("Error in the events output by 'clap_plugin_params::flush()'")
+    }
+
+    /// Call `clap_plugin_timer_support::on_timer()` for one of the plugin's timers. Should only be
+    /// called from the main thread.
+    fn on_timer(&self, timer_id: clap_id) -> Result<()> {
+        let timer_support = self.plugin_extension::<clap_plugin_timer_support>(
+            CLAP_EXT_TIMER_SUPPORT,
+            "clap_host_timer_support::register_timer()",
+        )?;
+
+        let plugin = self.plugin_ptr();
+        unsafe_clap_call! { timer_support=>on_timer(plugin, timer_id) };
+        self.num_timer_calls.fetch_add(1, Ordering::SeqCst);
+
+        Ok(())
+    }
+
+    /// Call `clap_plugin_posix_fd_support::on_fd()` for one of the plugin's file descriptors.
+    /// Should only be called from the main thread.
+    #[cfg(target_os = "linux")]
+    fn on_fd(&self, fd: i32, flags: clap_posix_fd_flags) -> Result<()> {
+        let posix_fd_support = self.plugin_extension::<clap_plugin_posix_fd_support>(
+            CLAP_EXT_POSIX_FD_SUPPORT,
+            "clap_host_posix_fd_support::register_fd()",
+        )?;
+
+        let plugin = self.plugin_ptr();
+        unsafe_clap_call! { posix_fd_support=>on_fd(plugin, fd, flags) };
+        self.num_posix_fd_calls.fetch_add(1, Ordering::SeqCst);
+
+        Ok(())
+    }
+
+    /// Get the plugin's vtable for the extension with ID `extension_id`. Returns an error
+    /// mentioning `function_name` if the plugin does not implement the extension, since the plugin
+    /// should not have called that host function in that case.
+    fn plugin_extension<T>(&self, extension_id: &CStr, function_name: &str) -> Result<*const T> {
+        let plugin = self.plugin_ptr();
+        let extension = unsafe_clap_call! {
+            plugin=>get_extension(plugin, extension_id.as_ptr())
+        } as *const T;
+        if extension.is_null() {
+            anyhow::bail!(
+                "The plugin called '{function_name}', but it does not implement the '{}' \
+                 extension.",
+                extension_id.to_str().unwrap()
+            );
+        }
+
+        Ok(extension)
+    }
 }
 
 impl Drop for Host {
     fn drop(&mut self) {
-        if let Some(error) = self.callback_error.borrow_mut().take() {
+        if let Some(error) = self.callback_error.get_mut().take() {
             log::error!(
                 "The validator's host has detected a callback error but this error has not been \
                  used as part of the test result. This is a clap-validator bug. The error message \
@@ -231,9 +700,9 @@ impl Drop for Host {
 }
 
 impl Host {
-    /// Initialize a CLAP host. The thread this object is created on will be designated as the main
-    /// thread for the purposes of the thread safety checks.
-    pub fn new() -> Arc<Host> {
+    /// Initialize a CLAP host with the specified settings. The thread this object is created on
+    /// will be designated as the main thread for the purposes of the thread safety checks.
+    pub fn new(settings: &HostSettings) -> Arc<Host> {
         // Normally you'd of course use bounded channel to avoid unnecessary allocations, but since
         // we're a validator it's probably better to not have to deal with the possibility that a
         // queue is full. These are used for handling callbacks on the main thread while the audio
@@ -242,18 +711,39 @@ impl Host {
 
         Arc::new(Host {
             main_thread_id: std::thread::current().id(),
+            settings: HostSettings {
+                supported_audio_ports_rescan_flags: settings.supported_audio_ports_rescan_flags
+                    & audio_ports::RESCAN_FLAGS,
+                ..settings.clone()
+            },
             // If the plugin never makes callbacks from the wrong thread, then this will remain an
             // None`. Otherwise this will be replaced by the first error.
-            callback_error: RefCell::new(None),
+            callback_error: Mutex::new(None),
 
-            instances: RefCell::new(HashMap::new()),
+            instances: RwLock::new(HashMap::new()),
             callback_task_sender,
             callback_task_receiver,
 
+            #[cfg(target_os = "linux")]
+            epoll: match util::epoll::Epoll::new() {
+                Ok(epoll) => Some(epoll),
+                Err(err) => {
+                    log::warn!(
+                        "{err:#}. The host will not support the 'clap.posix-fd-support' extension."
+                    );
+                    None
+                }
+            },
+            #[cfg(target_os = "linux")]
+            posix_fd_registrations: Mutex::new(HashMap::new()),
+
             clap_host_audio_ports: clap_host_audio_ports {
                 is_rescan_flag_supported: Some(Self::ext_audio_ports_is_rescan_flag_supported),
                 rescan: Some(Self::ext_audio_ports_rescan),
             },
+            clap_host_log: clap_host_log {
+                log: Some(Self::ext_log_log),
+            },
             clap_host_note_ports: clap_host_note_ports {
                 supported_dialects: Some(Self::ext_note_ports_supported_dialects),
                 rescan: Some(Self::ext_note_ports_rescan),
@@ -267,6 +757,12 @@ impl Host {
                 clear: Some(Self::ext_params_clear),
                 request_flush: Some(Self::ext_params_request_flush),
             },
+            #[cfg(target_os = "linux")]
+            clap_host_posix_fd_support: clap_host_posix_fd_support {
+                register_fd: Some(Self::ext_posix_fd_support_register_fd),
+                modify_fd: Some(Self::ext_posix_fd_support_modify_fd),
+                unregister_fd: Some(Self::ext_posix_fd_support_unregister_fd),
+            },
             clap_host_state: clap_host_state {
                 mark_dirty: Some(Self::ext_state_mark_dirty),
             },
@@ -274,6 +770,10 @@ impl Host {
                 is_main_thread: Some(Self::ext_thread_check_is_main_thread),
                 is_audio_thread: Some(Self::ext_thread_check_is_audio_thread),
             },
+            clap_host_timer_support: clap_host_timer_support {
+                register_timer: Some(Self::ext_timer_support_register_timer),
+                unregister_timer: Some(Self::ext_timer_support_unregister_timer),
+            },
         })
     }
 
@@ -288,7 +788,7 @@ impl Host {
     ///
     /// Panics if `instance.plugin` is `None`, or if the instance has already been registered.
     pub fn register_instance(&self, instance: Pin<Arc<InstanceState>>) {
-        let previous_instance = self.instances.borrow_mut().insert(
+        let previous_instance = self.instances.write().insert(
             instance.plugin.load().expect(
                 "'InstanceState::plugin' should contain the plugin's handle when registering it \
                  with the host",
@@ -305,7 +805,7 @@ impl Host {
     pub fn unregister_instance(&self, instance: Pin<Arc<InstanceState>>) {
         let removed_instance = self
             .instances
-            .borrow_mut()
+            .write()
             .remove(&instance.plugin.load().expect(
                 "'InstanceState::plugin' should contain the plugin's handle when unregistering it \
                  with the host",
@@ -320,6 +820,20 @@ impl Host {
                  clap-validator bug."
             )
         }
+
+        // The plugin should have unregistered its file descriptors before it was destroyed. These
+        // are kept in the instance's state so tests can check for them, but they should no longer
+        // be polled.
+        #[cfg(target_os = "linux")]
+        for (fd, flags) in removed_instance.posix_fds.lock().iter() {
+            log::warn!(
+                "The plugin did not unregister file descriptor {fd} before it was destroyed"
+            );
+
+            if let Err(err) = self.update_posix_fd_registration(*fd, Some(*flags), None) {
+                log::trace!("{err:#}");
+            }
+        }
     }
 
     /// Handle main thread callbacks until [`CallbackTask::Stop`] is send to
@@ -331,7 +845,23 @@ impl Host {
                 break;
             }
 
-            let task = self.callback_task_receiver.recv().unwrap();
+            // The plugin's timers are also fired from here, and its file descriptors are polled. If
+            // the plugin has any timers or file descriptors, then we need to wake up in time for
+            // the next timer or poll.
+            let next_timer = self.fire_timers();
+            let next_poll = self.poll_posix_fds();
+            let timeout = match (next_timer, next_poll) {
+                (Some(next_timer), Some(next_poll)) => Some(next_timer.min(next_poll)),
+                (next_timer, next_poll) => next_timer.or(next_poll),
+            };
+            let task = match timeout {
+                Some(timeout) => match self.callback_task_receiver.recv_timeout(timeout) {
+                    Ok(task) => task,
+                    Err(channel::RecvTimeoutError::Timeout) => continue,
+                    Err(err @ channel::RecvTimeoutError::Disconnected) => panic!("{err}"),
+                },
+                None => self.callback_task_receiver.recv().unwrap(),
+            };
             if matches!(task, CallbackTask::Stop) {
                 should_stop = true;
             }
@@ -352,10 +882,124 @@ impl Host {
         }
     }
 
+    /// Call `clap_plugin_timer_support::on_timer()` for all of the registered plugin instances'
+    /// timers that are due. Returns the time until the next timer is due, or `None` if there are no
+    /// timers. Should only be called from the main thread.
+    fn fire_timers(&self) -> Option<Duration> {
+        let instances = self.instances.read();
+        let now = Instant::now();
+        for instance in instances.values() {
+            let due_timer_ids: Vec<clap_id> = instance
+                .timers
+                .lock()
+                .iter_mut()
+                .filter(|(_, timer)| timer.next_tick <= now)
+                .map(|(timer_id, timer)| {
+                    timer.next_tick = now + timer.period;
+                    *timer_id
+                })
+                .collect();
+
+            for timer_id in due_timer_ids {
+                // The plugin may unregister its timers from another timer's callback
+                if !instance.timers.lock().contains_key(&timer_id) {
+                    continue;
+                }
+
+                log::trace!("Calling 'clap_plugin_timer_support::on_timer()' for timer {timer_id}");
+                if let Err(err) = instance.on_timer(timer_id) {
+                    self.set_callback_error(format!("{err:#}"));
+                }
+            }
+        }
+
+        let now = Instant::now();
+        instances
+            .values()
+            .filter_map(|instance| {
+                instance
+                    .timers
+                    .lock()
+                    .values()
+                    .map(|timer| timer.next_tick)
+                    .min()
+            })
+            .min()
+            .map(|next_tick| next_tick.saturating_duration_since(now))
+    }
+
+    /// Poll the file descriptors registered by the plugin instances through
+    /// `clap_host_posix_fd_support::register_fd()`, and call
+    /// `clap_plugin_posix_fd_support::on_fd()` for the ones that are ready. Returns the time until
+    /// the file descriptors should be polled again, or `None` if there are no registered file
+    /// descriptors. Should only be called from the main thread.
+    #[cfg(target_os = "linux")]
+    fn poll_posix_fds(&self) -> Option<Duration> {
+        let epoll = self.epoll.as_ref()?;
+        let instances = self.instances.read();
+        if instances
+            .values()
+            .all(|instance| instance.posix_fds.lock().is_empty())
+        {
+            return None;
+        }
+
+        let ready_fds = match epoll.poll() {
+            Ok(ready_fds) => ready_fds,
+            Err(err) => {
+                self.set_callback_error(format!("{err:#}"));
+                return Some(POSIX_FD_POLL_INTERVAL);
+            }
+        };
+        for (fd, events) in ready_fds {
+            let mut ready_flags = 0;
+            if events & libc::EPOLLIN as u32 != 0 {
+                ready_flags |= CLAP_POSIX_FD_READ;
+            }
+            if events & libc::EPOLLOUT as u32 != 0 {
+                ready_flags |= CLAP_POSIX_FD_WRITE;
+            }
+            if events & (libc::EPOLLERR | libc::EPOLLHUP) as u32 != 0 {
+                ready_flags |= CLAP_POSIX_FD_ERROR;
+            }
+
+            for instance in instances.values() {
+                // The plugin may modify or unregister its file descriptors from another file
+                // descriptor's callback. The plugin should only be notified about the events it
+                // asked for.
+                let flags = match instance.posix_fds.lock().get(&fd) {
+                    Some(registered_flags) => ready_flags & registered_flags,
+                    None => continue,
+                };
+                if flags == 0 {
+                    continue;
+                }
+
+                log::trace!(
+                    "Calling 'clap_plugin_posix_fd_support::on_fd()' for file descriptor {fd} \
+                     with flags {flags:#b}"
+                );
+                if let Err(err) = instance.on_fd(fd, flags) {
+                    self.set_callback_error(format!("{err:#}"));
+                }
+            }
+        }
+
+        Some(POSIX_FD_POLL_INTERVAL)
+    }
+
+    /// File descriptors can only be polled on Linux.
+    #[cfg(not(target_os = "linux"))]
+    fn poll_posix_fds(&self) -> Option<Duration> {
+        None
+    }
+
     /// Handle pending main thread callbacks. If a callback results in another callback, this is
     /// allowed to loop up to ten times.
     pub fn handle_callbacks_once(&self) {
-        let instances = self.instances.borrow();
+        // Plugins may query the host while this read lock is held, so this must not be upgraded to
+        // a write lock
+        let instances = self.instances.read();
         for i in 0..10 {
             let mut handled_callback = false;
             for instance in instances.values() {
@@ -372,6 +1016,65 @@ impl Host {
                     unsafe_clap_call! { plugin_ptr=>on_main_thread(plugin_ptr) };
                     handled_callback = true;
                 }
+
+                // While the plugin is active, the next process call also serves as the flush
+                if instance.status.load() == PluginStatus::Deactivated
+                    && instance
+                        .requested_flush
+                        .compare_exchange(true, false, Ordering::SeqCst, Ordering::SeqCst)
+                        .is_ok()
+                {
+                    log::trace!(
+                        "Calling 'clap_plugin_params::flush()' in response to a call to \
+                         'clap_host_params::request_flush()'",
+                    );
+                    if let Err(err) = instance.flush_params(&EventQueue::new_input()) {
+                        self.set_callback_error(format!("{err:#}"));
+                    }
+                    handled_callback = true;
+                }
+
+                let rescan_flags = instance
+                    .pending_param_rescan_flags
+                    .swap(0, Ordering::SeqCst);
+                if rescan_flags != 0 {
+                    log::trace!(
+                        "Querying the plugin's parameters in response to a call to \
+                         'clap_host_params::rescan()' with flags {rescan_flags:#x}",
+                    );
+                    if let Err(err) = instance.rescan_params(rescan_flags) {
+                        self.set_callback_error(format!("{err:#}"));
+                    }
+                    handled_callback = true;
+                }
+
+                let rescan_flags = instance
+                    .pending_audio_ports_rescan_flags
+                    .swap(0, Ordering::SeqCst);
+                if rescan_flags != 0 {
+                    log::trace!(
+                        "Querying the plugin's audio ports in response to a call to \
+                         'clap_host_audio_ports::rescan()' with flags {rescan_flags:#x}",
+                    );
+                    if let Err(err) = instance.rescan_audio_ports(rescan_flags) {
+                        self.set_callback_error(format!("{err:#}"));
+                    }
+                    handled_callback = true;
+                }
+
+                let rescan_flags = instance
+                    .pending_note_ports_rescan_flags
+                    .swap(0, Ordering::SeqCst);
+                if rescan_flags != 0 {
+                    log::trace!(
+                        "Querying the plugin's note ports in response to a call to \
+                         'clap_host_note_ports::rescan()' with flags {rescan_flags:#x}",
+                    );
+                    if let Err(err) = instance.rescan_note_ports(rescan_flags) {
+                        self.set_callback_error(format!("{err:#}"));
+                    }
+                    handled_callback = true;
+                }
             }
 
             if !handled_callback {
@@ -396,7 +1099,7 @@ impl Host {
     /// error if this happened. If there were errors and this function is not called before the
     /// object is destroyed, an error will be logged.
     pub fn callback_error_check(&self) -> Result<()> {
-        match self.callback_error.borrow_mut().take() {
+        match self.callback_error.lock().take() {
             Some(err) => anyhow::bail!(err),
             None => Ok(()),
         }
@@ -404,8 +1107,8 @@ impl Host {
 
     /// Set the callback error field if it does not already contain a value. Earlier errors are not
     /// overwritten.
-    fn set_callback_error(&self, error: impl Into<String>) {
-        let mut callback_error = self.callback_error.borrow_mut();
+    pub(crate) fn set_callback_error(&self, error: impl Into<String>) {
+        let mut callback_error = self.callback_error.lock();
         if callback_error.is_none() {
             *callback_error = Some(error.into());
         }
@@ -458,10 +1161,83 @@ impl Host {
         }
     }
 
+    /// Checks whether `flags` only contains known `CLAP_POSIX_FD_*` flags. If it does not, then an
+    /// error indicating this can be retrieved using
+    /// [`callback_error_check()`][Self::callback_error_check()] and this returns `false`.
+    #[cfg(target_os = "linux")]
+    fn check_posix_fd_flags(&self, function_name: &str, flags: clap_posix_fd_flags) -> bool {
+        let unknown_flags =
+            flags & !(CLAP_POSIX_FD_READ | CLAP_POSIX_FD_WRITE | CLAP_POSIX_FD_ERROR);
+        if unknown_flags != 0 {
+            self.set_callback_error(format!(
+                "'{function_name}' was called with unknown flags {unknown_flags:#b}."
+            ));
+            return false;
+        }
+
+        true
+    }
+
+    /// Update the epoll instance after a plugin instance's registration for `fd` changed from
+    /// `old_flags` to `new_flags`, where `None` means that the instance has not registered the file
+    /// descriptor. The registration is only changed if this succeeds.
+    ///
+    /// # Panics
+    ///
+    /// If the epoll instance does not exist, or if `old_flags` does not match a registration.
+    #[cfg(target_os = "linux")]
+    fn update_posix_fd_registration(
+        &self,
+        fd: i32,
+        old_flags: Option<clap_posix_fd_flags>,
+        new_flags: Option<clap_posix_fd_flags>,
+    ) -> Result<()> {
+        // The host exposes the extension only if the epoll instance exists
+        let epoll = self.epoll.as_ref().unwrap();
+
+        let mut posix_fd_registrations = self.posix_fd_registrations.lock();
+        let mut registrations = posix_fd_registrations.get(&fd).cloned().unwrap_or_default();
+        let was_registered = !registrations.is_empty();
+        if let Some(old_flags) = old_flags {
+            let idx = registrations
+                .iter()
+                .position(|flags| *flags == old_flags)
+                .expect("Unknown file descriptor registration, this is a clap-validator bug");
+            registrations.swap_remove(idx);
+        }
+        if let Some(new_flags) = new_flags {
+            registrations.push(new_flags);
+        }
+
+        let events = registrations.iter().fold(0, |events, flags| {
+            events | posix_fd_flags_to_epoll_events(*flags)
+        });
+        match (was_registered, registrations.is_empty()) {
+            (false, false) => epoll.add(fd, events)?,
+            (true, false) => epoll.modify(fd, events)?,
+            (true, true) => {
+                // If the plugin already closed the file descriptor, then the kernel will have
+                // removed it from the epoll instance
+                if let Err(err) = epoll.delete(fd) {
+                    log::trace!("{err:#}");
+                }
+            }
+            (false, true) => (),
+        }
+
+        if registrations.is_empty() {
+            posix_fd_registrations.remove(&fd);
+        } else {
+            posix_fd_registrations.insert(fd, registrations);
+        }
+
+        Ok(())
+    }
+
     /// Returns whether the thread ID is one of the registered audio threads.
     fn is_audio_thread(&self, thread_id: ThreadId) -> bool {
         self.instances
-            .borrow()
+            .read()
             .values()
             .any(|instance| instance.audio_thread.load() == Some(thread_id))
     }
@@ -470,14 +1246,21 @@ impl Host {
         host: *const clap_host,
         extension_id: *const c_char,
     ) -> *const c_void {
+        let _audit_pause = pause_audit();
         check_null_ptr!(host, (*host).host_data, extension_id);
         let (_, this) = InstanceState::from_clap_host_ptr(host);
 
         // Right now there's no way to have the host only expose certain extensions. We can always
         // add that when test cases need it.
         let extension_id_cstr = CStr::from_ptr(extension_id);
+        #[cfg(target_os = "linux")]
+        if extension_id_cstr == CLAP_EXT_POSIX_FD_SUPPORT && this.epoll.is_some() {
+            return &this.clap_host_posix_fd_support as *const _ as *const c_void;
+        }
         if extension_id_cstr == CLAP_EXT_AUDIO_PORTS {
             &this.clap_host_audio_ports as *const _ as *const c_void
+        } else if extension_id_cstr == CLAP_EXT_LOG {
+            &this.clap_host_log as *const _ as *const c_void
         } else if extension_id_cstr == CLAP_EXT_NOTE_PORTS {
             &this.clap_host_note_ports as *const _ as *const c_void
         } else if extension_id_cstr == CLAP_EXT_PRESET_LOAD {
@@ -488,12 +1271,15 @@ impl Host {
             &this.clap_host_state as *const _ as *const c_void
         } else if extension_id_cstr == CLAP_EXT_THREAD_CHECK {
             &this.clap_host_thread_check as *const _ as *const c_void
+        } else if extension_id_cstr == CLAP_EXT_TIMER_SUPPORT {
+            &this.clap_host_timer_support as *const _ as *const c_void
         } else {
             std::ptr::null()
         }
     }
 
     unsafe extern "C" fn request_restart(host: *const clap_host) {
+        let _audit_pause = pause_audit();
         check_null_ptr!(host, (*host).host_data);
         let (instance, _) = InstanceState::from_clap_host_ptr(host);
 
@@ -504,14 +1290,19 @@ impl Host {
     }
 
     unsafe extern "C" fn request_process(host: *const clap_host) {
+        let _audit_pause = pause_audit();
         check_null_ptr!(host, (*host).host_data);
+        let (instance, _) = InstanceState::from_clap_host_ptr(host);
 
-        // Handling this within the context of the validator would be a bit messy. Do plugins use
-        // this?
-        log::debug!("TODO: Handle 'clap_host::request_process()'");
+        // This may be called from any thread. The multi-iteration `ProcessingTest::run()` function
+        // will resume processing on the next cycle if the plugin is currently sleeping.
+        log::trace!("'clap_host::request_process()' was called by the plugin, setting the flag");
+        instance.requested_process.store(true, Ordering::SeqCst);
+        instance.num_process_requests.fetch_add(1, Ordering::SeqCst);
     }
 
     unsafe extern "C" fn request_callback(host: *const clap_host) {
+        let _audit_pause = pause_audit();
         check_null_ptr!(host, (*host).host_data);
         let (instance, this) = InstanceState::from_clap_host_ptr(host);
 
@@ -525,30 +1316,112 @@ impl Host {
 
     unsafe extern "C" fn ext_audio_ports_is_rescan_flag_supported(
         host: *const clap_host,
-        _flag: u32,
+        flag: u32,
     ) -> bool {
+        let _audit_pause = pause_audit();
         check_null_ptr!(host, (*host).host_data);
         let (_, this) = InstanceState::from_clap_host_ptr(host);
 
         this.assert_main_thread("clap_host_audio_ports::is_rescan_flag_supported()");
-        log::debug!("TODO: Handle 'clap_host_audio_ports::is_rescan_flag_supported()'");
+        if flag.count_ones() != 1 || (flag & !audio_ports::RESCAN_FLAGS) != 0 {
+            this.set_callback_error(format!(
+                "The plugin called 'clap_host_audio_ports::is_rescan_flag_supported()' with \
+                 {flag:#x}, which is not a single 'CLAP_AUDIO_PORTS_RESCAN_*' flag."
+            ));
+            return false;
+        }
 
-        true
+        (flag & this.settings.supported_audio_ports_rescan_flags) != 0
     }
 
-    unsafe extern "C" fn ext_audio_ports_rescan(host: *const clap_host, _flags: u32) {
+    unsafe extern "C" fn ext_audio_ports_rescan(host: *const clap_host, flags: u32) {
+        let _audit_pause = pause_audit();
         check_null_ptr!(host, (*host).host_data);
-        let (_, this) = InstanceState::from_clap_host_ptr(host);
+        let (instance, this) = InstanceState::from_clap_host_ptr(host);
 
-        // TODO: A couple of these flags are only allowed when the plugin is not activated, make
-        //       sure to check for this when implementing this functionality
         this.assert_main_thread("clap_host_audio_ports::rescan()");
-        log::debug!("TODO: Handle 'clap_host_audio_ports::rescan()'");
+        log::trace!(
+            "'clap_host_audio_ports::rescan()' was called by the plugin with flags {flags:#x}"
+        );
+
+        if let Err(err) = audio_ports::check_rescan_flags(
+            flags,
+            this.settings.supported_audio_ports_rescan_flags,
+            instance.status.load(),
+        ) {
+            this.set_callback_error(format!("{err:#}"));
+        }
+
+        // The audio ports are queried again once the plugin has returned from this function.
+        // Querying them from the wrong thread would only cause more problems.
+        if std::thread::current().id() == this.main_thread_id {
+            instance
+                .pending_audio_ports_rescan_flags
+                .fetch_or(flags, Ordering::SeqCst);
+            this.callback_task_sender.send(CallbackTask::Poll).unwrap();
+        }
+    }
+
+    unsafe extern "C" fn ext_log_log(
+        host: *const clap_host,
+        severity: clap_log_severity,
+        msg: *const c_char,
+    ) {
+        let _audit_pause = pause_audit();
+        check_null_ptr!(host, (*host).host_data);
+        let (_, this) = InstanceState::from_clap_host_ptr(host);
+
+        // This function is thread-safe, so it may be called from any thread including the audio
+        // thread. The messages are included in the test's result along with the kind of thread
+        // they were logged from.
+        let current_thread_id = std::thread::current().id();
+        let thread = if current_thread_id == this.main_thread_id {
+            LogThread::Main
+        } else if this.is_audio_thread(current_thread_id) {
+            LogThread::Audio
+        } else {
+            LogThread::Other
+        };
+        let severity = match severity {
+            CLAP_LOG_DEBUG => LogSeverity::Debug,
+            CLAP_LOG_INFO => LogSeverity::Info,
+            CLAP_LOG_WARNING => LogSeverity::Warning,
+            CLAP_LOG_ERROR => LogSeverity::Error,
+            CLAP_LOG_FATAL => LogSeverity::Fatal,
+            CLAP_LOG_HOST_MISBEHAVING => LogSeverity::HostMisbehaving,
+            CLAP_LOG_PLUGIN_MISBEHAVING => LogSeverity::PluginMisbehaving,
+            severity => {
+                this.set_callback_error(format!(
+                    "'clap_host_log::log()' was called with an unknown severity value {severity}."
+                ));
+                return;
+            }
+        };
+        let message = match unsafe { util::cstr_ptr_to_mandatory_string(msg) } {
+            Ok(message) => message,
+            Err(err) => {
+                this.set_callback_error(format!(
+                    "'clap_host_log::log()' was called with an invalid msg parameter: {err:#}"
+                ));
+                return;
+            }
+        };
+
+        log::trace!(
+            "'clap_host_log::log()' was called by the plugin from the {thread:?} thread: \
+             [{severity:?}] {message}"
+        );
+        this.settings.plugin_log.0.lock().push(PluginLogMessage {
+            severity,
+            thread,
+            message,
+        });
     }
 
     unsafe extern "C" fn ext_note_ports_supported_dialects(
         host: *const clap_host,
     ) -> clap_note_dialect {
+        let _audit_pause = pause_audit();
         check_null_ptr!(host, (*host).host_data);
         let (_, this) = InstanceState::from_clap_host_ptr(host);
 
@@ -557,12 +1430,26 @@ impl Host {
         CLAP_NOTE_DIALECT_CLAP | CLAP_NOTE_DIALECT_MIDI | CLAP_NOTE_DIALECT_MIDI_MPE
     }
 
-    unsafe extern "C" fn ext_note_ports_rescan(host: *const clap_host, _flags: u32) {
+    unsafe extern "C" fn ext_note_ports_rescan(host: *const clap_host, flags: u32) {
+        let _audit_pause = pause_audit();
         check_null_ptr!(host, (*host).host_data);
-        let (_, this) = InstanceState::from_clap_host_ptr(host);
+        let (instance, this) = InstanceState::from_clap_host_ptr(host);
 
         this.assert_main_thread("clap_host_note_ports::rescan()");
-        log::debug!("TODO: Handle 'clap_host_note_ports::rescan()'");
+        log::trace!(
+            "'clap_host_note_ports::rescan()' was called by the plugin with flags {flags:#x}"
+        );
+
+        if let Err(err) = note_ports::check_rescan_flags(flags, instance.status.load()) {
+            this.set_callback_error(format!("{err:#}"));
+        }
+
+        if std::thread::current().id() == this.main_thread_id {
+            instance
+                .pending_note_ports_rescan_flags
+                .fetch_or(flags, Ordering::SeqCst);
+            this.callback_task_sender.send(CallbackTask::Poll).unwrap();
+        }
     }
 
     unsafe extern "C" fn ext_preset_load_on_error(
@@ -573,8 +1460,9 @@ impl Host {
         os_error: i32,
         msg: *const c_char,
     ) {
+        let _audit_pause = pause_audit();
         check_null_ptr!(host, (*host).host_data);
-        let (_, this) = InstanceState::from_clap_host_ptr(host);
+        let (instance, this) = InstanceState::from_clap_host_ptr(host);
 
         this.assert_main_thread("clap_host_preset_load::on_error()");
 
@@ -591,12 +1479,26 @@ impl Host {
                     "'clap_host_preset_load::on_error()' called for {location} with load key \
                      {load_key}, OS error code {os_error}, and the following error message: {msg}"
                 ));
+                instance
+                    .preset_load_notifications
+                    .lock()
+                    .push(PresetLoadNotification::Error {
+                        location,
+                        load_key: Some(load_key),
+                    });
             }
             (Ok(location), Ok(None), Ok(msg)) => {
                 this.set_callback_error(format!(
                     "'clap_host_preset_load::on_error()' called for {location} with no load key, \
                      OS error code {os_error}, and the following error message: {msg}"
                 ));
+                instance
+                    .preset_load_notifications
+                    .lock()
+                    .push(PresetLoadNotification::Error {
+                        location,
+                        load_key: None,
+                    });
             }
             (Err(err), _, _) | (_, Err(err), _) | (_, _, Err(err)) => {
                 this.set_callback_error(format!("{err:#}"));
@@ -610,8 +1512,9 @@ impl Host {
         location: *const c_char,
         load_key: *const c_char,
     ) {
+        let _audit_pause = pause_audit();
         check_null_ptr!(host, (*host).host_data);
-        let (_, this) = InstanceState::from_clap_host_ptr(host);
+        let (instance, this) = InstanceState::from_clap_host_ptr(host);
 
         this.assert_main_thread("clap_host_preset_load::loaded()");
 
@@ -620,8 +1523,10 @@ impl Host {
         let load_key = unsafe { util::cstr_ptr_to_optional_string(load_key) }
             .context("'clap_host_preset_load::loaded()' called with an invalid load_key parameter");
         match (location, load_key) {
-            (Ok(_location), Ok(_load_key)) => {
-                log::debug!("TODO: Handle 'clap_host_preset_load::loaded()'");
+            (Ok(location), Ok(load_key)) => {
+                let notification = PresetLoadNotification::Loaded { location, load_key };
+                log::trace!("{notification} was called by the plugin");
+                instance.preset_load_notifications.lock().push(notification);
             }
             (Err(err), _) | (_, Err(err)) => {
                 this.set_callback_error(format!("{err:#}"));
@@ -629,46 +1534,286 @@ impl Host {
         }
     }
 
-    unsafe extern "C" fn ext_params_rescan(
-        host: *const clap_host,
-        _flags: clap_param_rescan_flags,
-    ) {
+    unsafe extern "C" fn ext_params_rescan(host: *const clap_host, flags: clap_param_rescan_flags) {
+        let _audit_pause = pause_audit();
         check_null_ptr!(host, (*host).host_data);
-        let (_, this) = InstanceState::from_clap_host_ptr(host);
+        let (instance, this) = InstanceState::from_clap_host_ptr(host);
 
         this.assert_main_thread("clap_host_params::rescan()");
-        log::debug!("TODO: Handle 'clap_host_params::rescan()'");
+        log::trace!("'clap_host_params::rescan()' was called by the plugin with flags {flags:#x}");
+        instance
+            .param_rescan_flags
+            .fetch_or(flags, Ordering::SeqCst);
+        if let Err(err) = params::check_rescan_flags(flags, instance.status.load()) {
+            this.set_callback_error(format!("{err:#}"));
+        }
+
+        // The parameters are queried again once the plugin has returned from this function.
+        // Querying them from the wrong thread would only cause more problems.
+        if std::thread::current().id() == this.main_thread_id {
+            instance
+                .pending_param_rescan_flags
+                .fetch_or(flags, Ordering::SeqCst);
+            this.callback_task_sender.send(CallbackTask::Poll).unwrap();
+        }
     }
 
     unsafe extern "C" fn ext_params_clear(
         host: *const clap_host,
-        _param_id: clap_id,
-        _flags: clap_param_clear_flags,
+        param_id: clap_id,
+        flags: clap_param_clear_flags,
     ) {
+        let _audit_pause = pause_audit();
         check_null_ptr!(host, (*host).host_data);
-        let (_, this) = InstanceState::from_clap_host_ptr(host);
+        let (instance, this) = InstanceState::from_clap_host_ptr(host);
 
         this.assert_main_thread("clap_host_params::clear()");
-        log::debug!("TODO: Handle 'clap_host_params::clear()'");
+        log::trace!(
+            "'clap_host_params::clear()' was called by the plugin for parameter {param_id} with \
+             flags {flags:#x}"
+        );
+        if let Err(err) = instance.clear_param(param_id, flags) {
+            this.set_callback_error(format!("{err:#}"));
+        }
     }
 
     unsafe extern "C" fn ext_params_request_flush(host: *const clap_host) {
+        let _audit_pause = pause_audit();
         check_null_ptr!(host, (*host).host_data);
-        let (_, this) = InstanceState::from_clap_host_ptr(host);
+        let (instance, this) = InstanceState::from_clap_host_ptr(host);
 
         this.assert_not_audio_thread("clap_host_params::request_flush()");
-        log::debug!("TODO: Handle 'clap_host_params::request_flush()'");
+        log::trace!(
+            "'clap_host_params::request_flush()' was called by the plugin, setting the flag"
+        );
+        instance.requested_flush.store(true, Ordering::SeqCst);
+        this.callback_task_sender.send(CallbackTask::Poll).unwrap();
     }
 
     unsafe extern "C" fn ext_state_mark_dirty(host: *const clap_host) {
+        let _audit_pause = pause_audit();
         check_null_ptr!(host, (*host).host_data);
-        let (_, this) = InstanceState::from_clap_host_ptr(host);
+        let (instance, this) = InstanceState::from_clap_host_ptr(host);
 
+        // The more specific audio thread error takes precedence over the main thread error
+        this.assert_not_audio_thread("clap_host_state::mark_dirty()");
         this.assert_main_thread("clap_host_state::mark_dirty()");
-        log::debug!("TODO: Handle 'clap_host_state::mark_dirty()'");
+
+        log::trace!("'clap_host_state::mark_dirty()' was called by the plugin");
+        instance.dirty_marks.lock().push(Instant::now());
+    }
+
+    unsafe extern "C" fn ext_timer_support_register_timer(
+        host: *const clap_host,
+        period_ms: u32,
+        timer_id: *mut clap_id,
+    ) -> bool {
+        let _audit_pause = pause_audit();
+        check_null_ptr!(host, (*host).host_data);
+        let (instance, this) = InstanceState::from_clap_host_ptr(host);
+
+        this.assert_main_thread("clap_host_timer_support::register_timer()");
+        if timer_id.is_null() {
+            this.set_callback_error(
+                "'clap_host_timer_support::register_timer()' was called with a null pointer for \
+                 the timer ID.",
+            );
+            return false;
+        }
+
+        // The timers are fired through the plugin's timer support extension, so the plugin should
+        // implement it
+        if std::thread::current().id() == this.main_thread_id {
+            if let Err(err) = instance.plugin_extension::<clap_plugin_timer_support>(
+                CLAP_EXT_TIMER_SUPPORT,
+                "clap_host_timer_support::register_timer()",
+            ) {
+                this.set_callback_error(format!("{err:#}"));
+                return false;
+            }
+        }
+
+        let period = Duration::from_millis(period_ms as u64).max(MIN_TIMER_PERIOD);
+        let new_timer_id = instance
+            .num_registered_timers
+            .fetch_add(1, Ordering::SeqCst);
+        log::trace!(
+            "'clap_host_timer_support::register_timer()' was called by the plugin, registering \
+             timer {new_timer_id} with a period of {period_ms} ms"
+        );
+        instance.timers.lock().insert(
+            new_timer_id,
+            Timer {
+                period,
+                next_tick: Instant::now() + period,
+            },
+        );
+        *timer_id = new_timer_id;
+
+        true
+    }
+
+    unsafe extern "C" fn ext_timer_support_unregister_timer(
+        host: *const clap_host,
+        timer_id: clap_id,
+    ) -> bool {
+        let _audit_pause = pause_audit();
+        check_null_ptr!(host, (*host).host_data);
+        let (instance, this) = InstanceState::from_clap_host_ptr(host);
+
+        this.assert_main_thread("clap_host_timer_support::unregister_timer()");
+        log::trace!(
+            "'clap_host_timer_support::unregister_timer()' was called by the plugin for timer \
+             {timer_id}"
+        );
+        if instance.timers.lock().remove(&timer_id).is_some() {
+            true
+        } else {
+            this.set_callback_error(format!(
+                "'clap_host_timer_support::unregister_timer()' was called with timer ID \
+                 {timer_id}, which is not a registered timer."
+            ));
+
+            false
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe extern "C" fn ext_posix_fd_support_register_fd(
+        host: *const clap_host,
+        fd: i32,
+        flags: clap_posix_fd_flags,
+    ) -> bool {
+        let _audit_pause = pause_audit();
+        check_null_ptr!(host, (*host).host_data);
+        let (instance, this) = InstanceState::from_clap_host_ptr(host);
+
+        this.assert_main_thread("clap_host_posix_fd_support::register_fd()");
+        log::trace!(
+            "'clap_host_posix_fd_support::register_fd()' was called by the plugin for file \
+             descriptor {fd} with flags {flags:#b}"
+        );
+        if !this.check_posix_fd_flags("clap_host_posix_fd_support::register_fd()", flags) {
+            return false;
+        }
+
+        // The file descriptors are handled through the plugin's posix fd support extension, so
+        // the plugin should implement it
+        if std::thread::current().id() == this.main_thread_id {
+            if let Err(err) = instance.plugin_extension::<clap_plugin_posix_fd_support>(
+                CLAP_EXT_POSIX_FD_SUPPORT,
+                "clap_host_posix_fd_support::register_fd()",
+            ) {
+                this.set_callback_error(format!("{err:#}"));
+                return false;
+            }
+        }
+
+        let mut posix_fds = instance.posix_fds.lock();
+        if posix_fds.contains_key(&fd) {
+            this.set_callback_error(format!(
+                "'clap_host_posix_fd_support::register_fd()' was called for file descriptor {fd}, \
+                 which has already been registered."
+            ));
+            return false;
+        }
+
+        if let Err(err) = this.update_posix_fd_registration(fd, None, Some(flags)) {
+            this.set_callback_error(format!(
+                "'clap_host_posix_fd_support::register_fd()' was called for file descriptor {fd}, \
+                 which could not be polled: {err:#}"
+            ));
+            return false;
+        }
+
+        posix_fds.insert(fd, flags);
+        instance
+            .num_registered_posix_fds
+            .fetch_add(1, Ordering::SeqCst);
+
+        true
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe extern "C" fn ext_posix_fd_support_modify_fd(
+        host: *const clap_host,
+        fd: i32,
+        flags: clap_posix_fd_flags,
+    ) -> bool {
+        let _audit_pause = pause_audit();
+        check_null_ptr!(host, (*host).host_data);
+        let (instance, this) = InstanceState::from_clap_host_ptr(host);
+
+        this.assert_main_thread("clap_host_posix_fd_support::modify_fd()");
+        log::trace!(
+            "'clap_host_posix_fd_support::modify_fd()' was called by the plugin for file \
+             descriptor {fd} with flags {flags:#b}"
+        );
+        if !this.check_posix_fd_flags("clap_host_posix_fd_support::modify_fd()", flags) {
+            return false;
+        }
+
+        let mut posix_fds = instance.posix_fds.lock();
+        let registered_flags = match posix_fds.get_mut(&fd) {
+            Some(registered_flags) => registered_flags,
+            None => {
+                this.set_callback_error(format!(
+                    "'clap_host_posix_fd_support::modify_fd()' was called for file descriptor \
+                     {fd}, which is not a registered file descriptor."
+                ));
+                return false;
+            }
+        };
+
+        if let Err(err) =
+            this.update_posix_fd_registration(fd, Some(*registered_flags), Some(flags))
+        {
+            this.set_callback_error(format!(
+                "'clap_host_posix_fd_support::modify_fd()' was called for file descriptor {fd}, \
+                 which could not be modified: {err:#}"
+            ));
+            return false;
+        }
+        *registered_flags = flags;
+
+        true
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe extern "C" fn ext_posix_fd_support_unregister_fd(
+        host: *const clap_host,
+        fd: i32,
+    ) -> bool {
+        let _audit_pause = pause_audit();
+        check_null_ptr!(host, (*host).host_data);
+        let (instance, this) = InstanceState::from_clap_host_ptr(host);
+
+        this.assert_main_thread("clap_host_posix_fd_support::unregister_fd()");
+        log::trace!(
+            "'clap_host_posix_fd_support::unregister_fd()' was called by the plugin for file \
+             descriptor {fd}"
+        );
+        let registered_flags = match instance.posix_fds.lock().remove(&fd) {
+            Some(registered_flags) => registered_flags,
+            None => {
+                this.set_callback_error(format!(
+                    "'clap_host_posix_fd_support::unregister_fd()' was called for file descriptor \
+                     {fd}, which is not a registered file descriptor."
+                ));
+                return false;
+            }
+        };
+
+        if let Err(err) = this.update_posix_fd_registration(fd, Some(registered_flags), None) {
+            this.set_callback_error(format!("{err:#}"));
+            return false;
+        }
+
+        true
     }
 
     unsafe extern "C" fn ext_thread_check_is_main_thread(host: *const clap_host) -> bool {
+        let _audit_pause = pause_audit();
         check_null_ptr!(host, (*host).host_data);
         let (_, this) = InstanceState::from_clap_host_ptr(host);
 
@@ -676,6 +1821,7 @@ impl Host {
     }
 
     unsafe extern "C" fn ext_thread_check_is_audio_thread(host: *const clap_host) -> bool {
+        let _audit_pause = pause_audit();
         check_null_ptr!(host, (*host).host_data);
         let (_, this) = InstanceState::from_clap_host_ptr(host);
 
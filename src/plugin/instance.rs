@@ -3,11 +3,14 @@
 use anyhow::Result;
 use clap_sys::factory::plugin_factory::clap_plugin_factory;
 use clap_sys::plugin::clap_plugin;
+use crossbeam::channel;
+use parking_lot::Mutex;
 use std::ffi::CStr;
 use std::marker::PhantomData;
 use std::ops::Deref;
 use std::pin::Pin;
 use std::ptr::NonNull;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 use super::ext::Extension;
@@ -18,6 +21,8 @@ use crate::util::unsafe_clap_call;
 use audio_thread::PluginAudioThread;
 
 pub mod audio_thread;
+#[cfg(target_os = "linux")]
+pub mod audit;
 pub mod process;
 
 /// A `Send+Sync` wrapper around `*const clap_plugin`.
@@ -81,6 +86,25 @@ impl<'lib> Deref for PluginSendWrapper<'lib> {
     }
 }
 
+/// Unblocks the other threads used by [`Plugin::on_audio_thread_pool()`] when one of the pool's
+/// threads panics. Without this the other pool threads and the main thread would keep waiting
+/// forever instead of propagating the panic.
+struct AudioThreadPoolPanicGuard<'a> {
+    iteration_senders: &'a [channel::Sender<Option<usize>>],
+    callback_task_sender: &'a channel::Sender<CallbackTask>,
+}
+
+impl Drop for AudioThreadPoolPanicGuard<'_> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            for sender in self.iteration_senders {
+                let _ = sender.send(None);
+            }
+            let _ = self.callback_task_sender.send(CallbackTask::Stop);
+        }
+    }
+}
+
 impl Drop for Plugin<'_> {
     fn drop(&mut self) {
         // Make sure the plugin is in the correct state before it gets destroyed
@@ -200,42 +224,197 @@ impl<'lib> Plugin<'lib> {
         &'a self,
         f: F,
     ) -> T {
-        assert_plugin_state_eq!(self, PluginStatus::Activated);
+        Plugin::on_audio_threads([(self, f)])
+            .pop()
+            .expect("The audio thread did not return a result")
+    }
 
+    /// The same as [`on_audio_thread()`][Self::on_audio_thread()], but for multiple plugin
+    /// instances at the same time. Every plugin gets its own audio thread, and the closures run
+    /// concurrently. The results are returned in the same order as the plugins. All plugins need to
+    /// share the same [`Host`] so their main-thread callbacks can be handled while the audio
+    /// threads are running.
+    pub fn on_audio_threads<'a, T, F, I>(plugins: I) -> Vec<T>
+    where
+        T: Send,
+        F: FnOnce(PluginAudioThread<'a>) -> T + Send,
+        I: IntoIterator<Item = (&'a Plugin<'a>, F)>,
+    {
+        let plugins: Vec<(&'a Plugin<'a>, F)> = plugins.into_iter().collect();
+        let host = match plugins.first() {
+            Some((plugin, _)) => plugin.host(),
+            None => return Vec::new(),
+        };
+        for (plugin, _) in &plugins {
+            assert_plugin_state_eq!(plugin, PluginStatus::Activated);
+            assert!(
+                std::ptr::eq(plugin.host(), host),
+                "All plugins running on audio threads at the same time need to share a host"
+            );
+        }
+
+        // The main thread should unblock when the last audio thread is done
+        let num_running_threads = AtomicUsize::new(plugins.len());
         crossbeam::scope(|s| {
-            let unsafe_self_wrapper = PluginSendWrapper(self);
-            let callback_task_sender = self.host().callback_task_sender.clone();
-
-            let audio_thread = s
-                .builder()
-                .name(String::from("audio-thread"))
-                .spawn(move |_| {
-                    // SAFETY: We artificially impose `!Send`+`!Sync` requirements on `Plugin` and
-                    //         `PluginAudioThread` to prevent them from being shared with other
-                    //         threads. But we'll need to temporarily lift that restriction in order
-                    //         to create this `PluginAudioThread`.
-                    let this = unsafe { &**unsafe_self_wrapper };
-
-                    // The host may use this to assert that calls are run from an audio thread
-                    this.state
-                        .audio_thread
-                        .store(Some(std::thread::current().id()));
-                    let result = f(PluginAudioThread::new(this));
-                    this.state.audio_thread.store(None);
-
-                    // The main thread should unblock when the audio thread is done
-                    callback_task_sender.send(CallbackTask::Stop).unwrap();
+            let audio_threads: Vec<_> = plugins
+                .into_iter()
+                .map(|(plugin, f)| {
+                    let unsafe_plugin_wrapper = PluginSendWrapper(plugin);
+                    let callback_task_sender = host.callback_task_sender.clone();
+                    let num_running_threads = &num_running_threads;
+
+                    s.builder()
+                        .name(String::from("audio-thread"))
+                        .spawn(move |_| {
+                            // SAFETY: We artificially impose `!Send`+`!Sync` requirements on
+                            //         `Plugin` and `PluginAudioThread` to prevent them from being
+                            //         shared with other threads. But we'll need to temporarily lift
+                            //         that restriction in order to create this
+                            //         `PluginAudioThread`.
+                            let this = unsafe { &**unsafe_plugin_wrapper };
+
+                            let audit = start_audit();
+
+                            // The host may use this to assert that calls are run from an audio
+                            // thread
+                            this.state
+                                .audio_thread
+                                .store(Some(std::thread::current().id()));
+                            let result = f(PluginAudioThread::new(this));
+                            this.state.audio_thread.store(None);
+
+                            if num_running_threads.fetch_sub(1, Ordering::SeqCst) == 1 {
+                                callback_task_sender.send(CallbackTask::Stop).unwrap();
+                            }
+
+                            (result, audit)
+                        })
+                        .expect("Unable to spawn an audio thread")
+                })
+                .collect();
+
+            // Handle callbacks requests on the main thread whle the aduio threads are running
+            host.handle_callbacks_blocking();
+
+            audio_threads
+                .into_iter()
+                .map(|audio_thread| {
+                    let (result, audit) = audio_thread.join().expect("Audio thread panicked");
+                    finish_audit(host, audit);
 
                     result
                 })
-                .expect("Unable to spawn an audio thread");
+                .collect()
+        })
+        .expect("Audio thread panicked")
+    }
 
-            // Handle callbacks requests on the main thread whle the aduio thread is running
-            self.host().handle_callbacks_blocking();
+    /// The same as [`on_audio_thread()`][Self::on_audio_thread()], but processing is moved between
+    /// a pool of `num_threads` audio threads. CLAP allows the host to call the audio thread
+    /// functions from different threads over time, as long as those calls don't overlap. `f` is
+    /// called `num_iterations` times with the index of the iteration, every time on the next thread
+    /// in the pool. The instance's audio thread is updated before every iteration.
+    ///
+    /// Processing is started on the thread running the first iteration, and it is stopped on the
+    /// thread running the last iteration. If starting processing or `f` fails, then processing is
+    /// stopped right away and the error is returned.
+    pub fn on_audio_thread_pool<'a, F>(
+        &'a self,
+        num_threads: usize,
+        num_iterations: usize,
+        f: F,
+    ) -> Result<()>
+    where
+        F: FnMut(&PluginAudioThread<'a>, usize) -> Result<()> + Send,
+    {
+        assert_plugin_state_eq!(self, PluginStatus::Activated);
+        assert!(num_threads > 0 && num_iterations > 0);
 
-            audio_thread.join().expect("Audio thread panicked")
+        let host = self.host();
+        let f = Mutex::new(f);
+        let result = Mutex::new(Ok(()));
+
+        // Every thread has a channel it receives the index of the next iteration to run from. A
+        // `None` value terminates the thread.
+        let (iteration_senders, iteration_receivers): (Vec<_>, Vec<_>) = (0..num_threads)
+            .map(|_| channel::unbounded::<Option<usize>>())
+            .unzip();
+        crossbeam::scope(|s| {
+            let audio_threads: Vec<_> = iteration_receivers
+                .into_iter()
+                .enumerate()
+                .map(|(thread_idx, iteration_receiver)| {
+                    let unsafe_plugin_wrapper = PluginSendWrapper(self);
+                    let callback_task_sender = host.callback_task_sender.clone();
+                    let iteration_senders = &iteration_senders;
+                    let f = &f;
+                    let result = &result;
+
+                    s.builder()
+                        .name(format!("audio-thread-{thread_idx}"))
+                        .spawn(move |_| {
+                            // SAFETY: See `on_audio_threads()`. The iterations are handed off from
+                            //         one thread to the next, so the plugin is never used from
+                            //         more than one of these threads at a time.
+                            let this = unsafe { &**unsafe_plugin_wrapper };
+                            let _panic_guard = AudioThreadPoolPanicGuard {
+                                iteration_senders,
+                                callback_task_sender: &callback_task_sender,
+                            };
+                            let audit = start_audit();
+                            let plugin = PluginAudioThread::new(this);
+
+                            while let Ok(Some(iteration)) = iteration_receiver.recv() {
+                                this.state
+                                    .audio_thread
+                                    .store(Some(std::thread::current().id()));
+                                let iteration_result = if iteration == 0 {
+                                    plugin.start_processing()
+                                } else {
+                                    Ok(())
+                                }
+                                .and_then(|()| (f.lock())(&plugin, iteration));
+
+                                let is_last_iteration =
+                                    iteration_result.is_err() || iteration + 1 == num_iterations;
+                                if is_last_iteration && plugin.status() == PluginStatus::Processing
+                                {
+                                    plugin.stop_processing();
+                                }
+                                this.state.audio_thread.store(None);
+
+                                if let Err(err) = iteration_result {
+                                    *result.lock() = Err(err);
+                                }
+                                if is_last_iteration {
+                                    for sender in iteration_senders {
+                                        sender.send(None).unwrap();
+                                    }
+                                    callback_task_sender.send(CallbackTask::Stop).unwrap();
+                                } else {
+                                    iteration_senders[(thread_idx + 1) % num_threads]
+                                        .send(Some(iteration + 1))
+                                        .unwrap();
+                                }
+                            }
+
+                            audit
+                        })
+                        .expect("Unable to spawn an audio thread")
+                })
+                .collect();
+
+            iteration_senders[0].send(Some(0)).unwrap();
+            host.handle_callbacks_blocking();
+
+            for audio_thread in audio_threads {
+                let audit = audio_thread.join().expect("Audio thread panicked");
+                finish_audit(host, audit);
+            }
         })
-        .expect("Audio thread panicked")
+        .expect("Audio thread panicked");
+
+        result.into_inner()
     }
 
     /// Initialize the plugin. This needs to be called before doing anything else.
@@ -288,3 +467,50 @@ impl<'lib> Plugin<'lib> {
         self.state.status.store(PluginStatus::Deactivated);
     }
 }
+
+/// Start auditing the calling audio thread if the audio thread audit mode is enabled. See
+/// [`audit`].
+#[cfg(target_os = "linux")]
+fn start_audit() -> Option<Result<audit::AudioThreadAudit>> {
+    audit::is_enabled().then(audit::AudioThreadAudit::start)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn start_audit() -> Option<Result<()>> {
+    None
+}
+
+/// Finish an audit started with [`start_audit()`]. This can only be done after the audio thread
+/// has terminated. Violations are reported the same way errors in host callbacks are.
+#[cfg(target_os = "linux")]
+fn finish_audit(host: &Host, audit: Option<Result<audit::AudioThreadAudit>>) {
+    if let Some(Err(err)) = audit.map(|audit| audit.and_then(audit::AudioThreadAudit::finish)) {
+        host.set_callback_error(format!("{err:#}"));
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn finish_audit(_host: &Host, _audit: Option<Result<()>>) {}
+
+/// Call `f`, recording the system calls made in the meantime if the current thread is being
+/// audited. This should only wrap the plugin's own audio thread functions. See [`audit::record()`].
+#[cfg(target_os = "linux")]
+pub fn audited<T>(f: impl FnOnce() -> T) -> T {
+    audit::record(f)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn audited<T>(f: impl FnOnce() -> T) -> T {
+    f()
+}
+
+/// Stop recording system calls on the current thread until the returned guard is dropped. Host
+/// callbacks that may be called while the plugin is processing audio hold on to this guard. See
+/// [`audit::pause_recording()`].
+#[cfg(target_os = "linux")]
+pub fn pause_audit() -> audit::RecordingGuard {
+    audit::pause_recording()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pause_audit() -> impl Sized {}
@@ -16,7 +16,7 @@ use crate::util::unsafe_clap_call;
 
 use super::process::ProcessData;
 use super::{assert_plugin_state_eq, assert_plugin_state_initialized};
-use super::{Plugin, PluginStatus};
+use super::{audited, Plugin, PluginStatus};
 use crate::plugin::ext::Extension;
 
 /// An audio thread equivalent to [`Plugin`]. This version only allows audio thread functions to be
@@ -112,7 +112,7 @@ impl<'a> PluginAudioThread<'a> {
         assert_plugin_state_eq!(self, PluginStatus::Activated);
 
         let plugin = self.as_ptr();
-        if unsafe_clap_call! { plugin=>start_processing(plugin) } {
+        if audited(|| unsafe_clap_call! { plugin=>start_processing(plugin) }) {
             self.state().status.store(PluginStatus::Processing);
             Ok(())
         } else {
@@ -129,7 +129,7 @@ impl<'a> PluginAudioThread<'a> {
 
         let plugin = self.as_ptr();
         let result = process_data.with_clap_process_data(|clap_process_data| {
-            unsafe_clap_call! { plugin=>process(plugin, &clap_process_data) }
+            audited(|| unsafe_clap_call! { plugin=>process(plugin, &clap_process_data) })
         });
 
         match result {
@@ -154,7 +154,7 @@ impl<'a> PluginAudioThread<'a> {
         assert_plugin_state_eq!(self, PluginStatus::Processing);
 
         let plugin = self.as_ptr();
-        unsafe_clap_call! { plugin=>stop_processing(plugin) };
+        audited(|| unsafe_clap_call! { plugin=>stop_processing(plugin) });
 
         self.state().status.store(PluginStatus::Activated);
     }
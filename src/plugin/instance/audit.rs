@@ -0,0 +1,341 @@
+//! Auditing the system calls a plugin makes on the audio thread. When enabled, every potentially
+//! blocking system call the plugin makes from within `clap_plugin::start_processing()`,
+//! `clap_plugin::process()`, and `clap_plugin::stop_processing()` is recorded. System calls made
+//! by the validator itself, including those made in host callbacks, are not recorded. Only
+//! available on Linux.
+
+use anyhow::Result;
+use parking_lot::Mutex;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::util::seccomp::{Syscall, SyscallFilter, SyscallMonitor};
+
+/// Whether the audio thread audit mode is enabled. This is set once at startup with
+/// [`enable()`].
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// The recording flag for the audit running on the current thread, if there is one. This is
+    /// toggled by [`record()`] and [`pause_recording()`].
+    static RECORDING: RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+}
+
+/// `FUTEX_WAIT`, `FUTEX_LOCK_PI`, `FUTEX_WAIT_BITSET`, `FUTEX_WAIT_REQUEUE_PI`, and
+/// `FUTEX_LOCK_PI2`. Futex operations that only wake up other threads are fine.
+const FUTEX_WAIT_OPS: &[u32] = &[0, 6, 9, 11, 13];
+/// Masks out `FUTEX_PRIVATE_FLAG` and `FUTEX_CLOCK_REALTIME`.
+const FUTEX_CMD_MASK: u32 = 0x7f;
+
+/// The system calls that should not be made from the audio thread, and their names. These either
+/// block, wait on other threads, or perform I/O.
+const FORBIDDEN_SYSCALLS: &[(libc::c_long, &str)] = &[
+    (libc::SYS_futex, "futex"),
+    (libc::SYS_nanosleep, "nanosleep"),
+    (libc::SYS_clock_nanosleep, "clock_nanosleep"),
+    #[cfg(target_arch = "x86_64")]
+    (libc::SYS_open, "open"),
+    #[cfg(target_arch = "x86_64")]
+    (libc::SYS_creat, "creat"),
+    (libc::SYS_openat, "openat"),
+    (libc::SYS_close, "close"),
+    (libc::SYS_read, "read"),
+    (libc::SYS_readv, "readv"),
+    (libc::SYS_pread64, "pread64"),
+    (libc::SYS_write, "write"),
+    (libc::SYS_writev, "writev"),
+    (libc::SYS_pwrite64, "pwrite64"),
+    (libc::SYS_fsync, "fsync"),
+    (libc::SYS_fdatasync, "fdatasync"),
+    #[cfg(target_arch = "x86_64")]
+    (libc::SYS_poll, "poll"),
+    (libc::SYS_ppoll, "ppoll"),
+    #[cfg(target_arch = "x86_64")]
+    (libc::SYS_select, "select"),
+    (libc::SYS_pselect6, "pselect6"),
+    #[cfg(target_arch = "x86_64")]
+    (libc::SYS_epoll_wait, "epoll_wait"),
+    (libc::SYS_epoll_pwait, "epoll_pwait"),
+    (libc::SYS_wait4, "wait4"),
+    (libc::SYS_connect, "connect"),
+    (libc::SYS_sendto, "sendto"),
+    (libc::SYS_sendmsg, "sendmsg"),
+    (libc::SYS_recvfrom, "recvfrom"),
+    (libc::SYS_recvmsg, "recvmsg"),
+];
+
+/// An audit running on an audio thread. Created with [`AudioThreadAudit::start()`] on the audio
+/// thread, and finished with [`AudioThreadAudit::finish()`] after the audio thread has terminated.
+#[derive(Debug)]
+pub struct AudioThreadAudit {
+    monitor: SyscallMonitor,
+    recorder: Arc<ViolationRecorder>,
+}
+
+/// Records the forbidden system calls made by the audited thread. This is called from the
+/// monitor's supervisor thread.
+#[derive(Debug)]
+struct ViolationRecorder {
+    /// Threads spawned by the plugin from the audio thread inherit the filter, but only the audio
+    /// thread's own system calls are of interest here.
+    audio_thread_tid: libc::pid_t,
+    /// Whether system calls are currently being recorded. Toggled by [`record()`] and
+    /// [`pause_recording()`].
+    recording: Arc<AtomicBool>,
+    /// The number of times each forbidden system call was made while recording, indexed by a
+    /// human readable description of the call.
+    violations: Mutex<BTreeMap<String, usize>>,
+}
+
+/// Enable the audio thread audit mode for this process.
+pub fn enable() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// Whether the audio thread audit mode has been enabled with [`enable()`].
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+/// Restores the previous recording state of the audit running on the current thread when dropped.
+/// Created by [`pause_recording()`].
+#[must_use]
+pub struct RecordingGuard {
+    /// The current thread's recording flag and its value before the guard was created, or `None`
+    /// if no audit is running on this thread.
+    previous: Option<(Arc<AtomicBool>, bool)>,
+}
+
+impl Drop for RecordingGuard {
+    fn drop(&mut self) {
+        if let Some((flag, recording)) = &self.previous {
+            flag.store(*recording, Ordering::SeqCst);
+        }
+    }
+}
+
+impl RecordingGuard {
+    fn new(recording: bool) -> Self {
+        let previous = RECORDING.with(|flag| {
+            flag.borrow()
+                .as_ref()
+                .map(|flag| (flag.clone(), flag.swap(recording, Ordering::SeqCst)))
+        });
+
+        RecordingGuard { previous }
+    }
+}
+
+/// Record the system calls made while calling `f` for the audit running on the current thread.
+/// This should only wrap the calls into the plugin. Does nothing special if no audit is running on
+/// this thread.
+pub fn record<T>(f: impl FnOnce() -> T) -> T {
+    let _guard = RecordingGuard::new(true);
+    f()
+}
+
+/// Stop recording system calls for the audit running on the current thread until the returned
+/// guard is dropped. This is used in host callbacks so the validator's own system calls made while
+/// the plugin is processing audio are not attributed to the plugin.
+pub fn pause_recording() -> RecordingGuard {
+    RecordingGuard::new(false)
+}
+
+impl AudioThreadAudit {
+    /// Start auditing the calling thread. Recording is initially disabled, and it is enabled with
+    /// [`record()`].
+    pub fn start() -> Result<Self> {
+        let filters: Vec<SyscallFilter> = FORBIDDEN_SYSCALLS
+            .iter()
+            .map(|&(nr, _)| {
+                if nr == libc::SYS_futex {
+                    SyscallFilter {
+                        nr,
+                        arg: Some((1, FUTEX_CMD_MASK, FUTEX_WAIT_OPS)),
+                    }
+                } else {
+                    SyscallFilter::any(nr)
+                }
+            })
+            .collect();
+
+        let recorder = Arc::new(ViolationRecorder::new(unsafe { libc::gettid() }));
+        let monitor = SyscallMonitor::install(&filters, {
+            let recorder = recorder.clone();
+            move |syscall| recorder.record(syscall)
+        })?;
+
+        RECORDING.with(|flag| *flag.borrow_mut() = Some(recorder.recording.clone()));
+
+        Ok(AudioThreadAudit { monitor, recorder })
+    }
+
+    /// Stop the audit and return an error describing the forbidden system calls made while
+    /// recording, if there were any. This must only be called after the audited thread has
+    /// terminated.
+    pub fn finish(self) -> Result<()> {
+        self.monitor.stop();
+
+        let violations = self.recorder.violations.lock();
+        if violations.is_empty() {
+            return Ok(());
+        }
+
+        let summary: Vec<String> = violations
+            .iter()
+            .map(|(description, count)| match count {
+                1 => format!("{description} (1 time)"),
+                n => format!("{description} ({n} times)"),
+            })
+            .collect();
+        anyhow::bail!(
+            "The plugin made potentially blocking system calls on the audio thread while \
+             processing audio: {}.",
+            summary.join(", ")
+        )
+    }
+}
+
+impl ViolationRecorder {
+    fn new(audio_thread_tid: libc::pid_t) -> Self {
+        ViolationRecorder {
+            audio_thread_tid,
+            recording: Arc::new(AtomicBool::new(false)),
+            violations: Mutex::default(),
+        }
+    }
+
+    /// Record an intercepted system call if it was made by the audio thread while recording.
+    fn record(&self, syscall: &Syscall) {
+        // The monitored thread is blocked while this is running, and it never touches
+        // `violations`, so locking it here is fine
+        if syscall.tid == self.audio_thread_tid && self.recording.load(Ordering::SeqCst) {
+            *self
+                .violations
+                .lock()
+                .entry(describe_syscall(syscall))
+                .or_default() += 1;
+        }
+    }
+}
+
+/// Get a human readable description for an intercepted system call.
+fn describe_syscall(syscall: &Syscall) -> String {
+    let name = FORBIDDEN_SYSCALLS
+        .iter()
+        .find(|(nr, _)| *nr == syscall.nr)
+        .map(|(_, name)| *name)
+        .unwrap_or("unknown");
+
+    if syscall.nr == libc::SYS_futex {
+        let op = match syscall.args[1] as u32 & FUTEX_CMD_MASK {
+            0 => "FUTEX_WAIT",
+            6 => "FUTEX_LOCK_PI",
+            9 => "FUTEX_WAIT_BITSET",
+            11 => "FUTEX_WAIT_REQUEUE_PI",
+            13 => "FUTEX_LOCK_PI2",
+            _ => "unknown",
+        };
+
+        format!("{name}({op})")
+    } else {
+        format!("{name}()")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `FUTEX_PRIVATE_FLAG`.
+    const FUTEX_PRIVATE_FLAG: u64 = 128;
+    /// `FUTEX_CLOCK_REALTIME`.
+    const FUTEX_CLOCK_REALTIME: u64 = 256;
+
+    fn syscall(tid: libc::pid_t, nr: libc::c_long, arg1: u64) -> Syscall {
+        Syscall {
+            tid,
+            nr,
+            args: [0, arg1, 0, 0, 0, 0],
+        }
+    }
+
+    #[test]
+    fn describes_futex_waits() {
+        assert_eq!(
+            describe_syscall(&syscall(1, libc::SYS_futex, FUTEX_PRIVATE_FLAG)),
+            "futex(FUTEX_WAIT)"
+        );
+        assert_eq!(
+            describe_syscall(&syscall(
+                1,
+                libc::SYS_futex,
+                9 | FUTEX_PRIVATE_FLAG | FUTEX_CLOCK_REALTIME
+            )),
+            "futex(FUTEX_WAIT_BITSET)"
+        );
+        assert_eq!(
+            describe_syscall(&syscall(1, libc::SYS_futex, 6)),
+            "futex(FUTEX_LOCK_PI)"
+        );
+    }
+
+    #[test]
+    fn describes_other_syscalls() {
+        assert_eq!(
+            describe_syscall(&syscall(1, libc::SYS_clock_nanosleep, 0)),
+            "clock_nanosleep()"
+        );
+        assert_eq!(describe_syscall(&syscall(1, libc::SYS_write, 0)), "write()");
+        assert_eq!(
+            describe_syscall(&syscall(1, libc::SYS_getpid, 0)),
+            "unknown()"
+        );
+    }
+
+    #[test]
+    fn futex_wakes_are_not_intercepted() {
+        // `FUTEX_WAKE`, `FUTEX_REQUEUE`, `FUTEX_CMP_REQUEUE`, `FUTEX_WAKE_OP`, `FUTEX_UNLOCK_PI`,
+        // and `FUTEX_WAKE_BITSET` never block
+        for op in [1, 3, 4, 5, 7, 10] {
+            assert!(
+                !FUTEX_WAIT_OPS.contains(&op),
+                "{op} should not be intercepted"
+            );
+        }
+        for op in [0, 6, 9, 11, 13] {
+            assert!(FUTEX_WAIT_OPS.contains(&op), "{op} should be intercepted");
+        }
+    }
+
+    #[test]
+    fn only_records_the_audio_thread_while_recording() {
+        let recorder = ViolationRecorder::new(42);
+
+        // Not recording yet
+        recorder.record(&syscall(42, libc::SYS_write, 0));
+        assert!(recorder.violations.lock().is_empty());
+
+        recorder.recording.store(true, Ordering::SeqCst);
+        recorder.record(&syscall(42, libc::SYS_write, 0));
+        recorder.record(&syscall(42, libc::SYS_write, 0));
+        recorder.record(&syscall(42, libc::SYS_futex, FUTEX_PRIVATE_FLAG));
+        // Threads spawned from the audio thread inherit the filter, but they should be ignored
+        recorder.record(&syscall(43, libc::SYS_write, 0));
+        recorder.record(&syscall(43, libc::SYS_nanosleep, 0));
+
+        recorder.recording.store(false, Ordering::SeqCst);
+        recorder.record(&syscall(42, libc::SYS_write, 0));
+
+        let violations = recorder.violations.lock();
+        assert_eq!(
+            violations.iter().collect::<Vec<_>>(),
+            [
+                (&String::from("futex(FUTEX_WAIT)"), &1),
+                (&String::from("write()"), &2)
+            ]
+        );
+    }
+}
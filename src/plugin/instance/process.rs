@@ -19,6 +19,7 @@ use rand_pcg::Pcg32;
 use std::ffi::c_void;
 use std::pin::Pin;
 
+use super::pause_audit;
 use crate::util::check_null_ptr;
 
 /// The input and output data for a call to `clap_plugin::process()`.
@@ -415,6 +416,31 @@ impl EventQueue<clap_output_events> {
 
         queue
     }
+
+    /// Check whether the events the plugin has output are in a monotonically increasing order. If
+    /// `num_samples` is set, then the events must also fall within a buffer of that many samples.
+    pub fn check_output_order(&self, num_samples: Option<u32>) -> Result<()> {
+        let mut last_event_time = 0;
+        for event in self.events.lock().iter() {
+            let event_time = event.header().time;
+            if event_time < last_event_time {
+                anyhow::bail!(
+                    "The plugin output an event for sample {event_time} after it had previously \
+                     output an event for sample {last_event_time}."
+                )
+            }
+
+            last_event_time = event_time;
+        }
+
+        match num_samples {
+            Some(num_samples) if last_event_time >= num_samples => anyhow::bail!(
+                "The plugin output an event for sample {last_event_time} but the audio buffer only \
+                 contains {num_samples} samples."
+            ),
+            _ => Ok(()),
+        }
+    }
 }
 
 impl<VTable> EventQueue<VTable> {
@@ -423,6 +449,7 @@ impl<VTable> EventQueue<VTable> {
     }
 
     unsafe extern "C" fn size(list: *const clap_input_events) -> u32 {
+        let _audit_pause = pause_audit();
         check_null_ptr!(list, (*list).ctx);
         let this = &*((*list).ctx as *const Self);
 
@@ -433,6 +460,7 @@ impl<VTable> EventQueue<VTable> {
         list: *const clap_input_events,
         index: u32,
     ) -> *const clap_event_header {
+        let _audit_pause = pause_audit();
         check_null_ptr!(list, (*list).ctx);
         let this = &*((*list).ctx as *const Self);
 
@@ -453,6 +481,7 @@ impl<VTable> EventQueue<VTable> {
         list: *const clap_output_events,
         event: *const clap_event_header,
     ) -> bool {
+        let _audit_pause = pause_audit();
         check_null_ptr!(list, (*list).ctx, event);
         let this = &*((*list).ctx as *const Self);
 
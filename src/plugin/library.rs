@@ -153,6 +153,36 @@ impl PluginLibrary {
         &self.library_path
     }
 
+    /// Call `clap_plugin_entry::init()` again on the already initialized entry point. Hosts may
+    /// load the same library multiple times, so the plugin should reference count these calls.
+    /// Every successful call needs to be balanced by a call to
+    /// [`deinit_entry_point()`][Self::deinit_entry_point()].
+    pub fn init_entry_point(&self) -> Result<()> {
+        let entry_point = get_clap_entry_point(&self.library)
+            .expect("A Plugin was constructed for a plugin with no entry point");
+        let path_cstring = CString::new(
+            self.library_path
+                .as_os_str()
+                .to_str()
+                .context("Path contains invalid UTF-8")?,
+        )
+        .context("Path contains null bytes")?;
+        if unsafe_clap_call! { entry_point=>init(path_cstring.as_ptr()) } {
+            Ok(())
+        } else {
+            anyhow::bail!("'clap_plugin_entry::init({path_cstring:?})' returned false.");
+        }
+    }
+
+    /// Call `clap_plugin_entry::deinit()`. This is normally done when the `PluginLibrary` is
+    /// dropped, so this must only be used to balance calls to
+    /// [`init_entry_point()`][Self::init_entry_point()].
+    pub fn deinit_entry_point(&self) {
+        let entry_point = get_clap_entry_point(&self.library)
+            .expect("A Plugin was constructed for a plugin with no entry point");
+        unsafe_clap_call! { entry_point=>deinit() };
+    }
+
     /// Get the metadata for all plugins stored in this plugin library. Most plugin libraries
     /// contain a single plugin, but this may return metadata for zero or more plugins.
     pub fn metadata(&self) -> Result<PluginLibraryMetadata> {
@@ -12,23 +12,34 @@
 use anyhow::{Context, Result};
 use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 use std::ffi::OsStr;
 use std::fmt::Display;
 use std::fs;
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
 use std::str::FromStr;
 use strum::IntoEnumIterator;
 
+use crate::plugin::host::{LogSeverity, PluginLogMessage};
+use crate::validator::AudioPortsRescanFlag;
 use crate::{util, Verbosity};
 
 mod plugin;
 mod plugin_library;
 pub mod rng;
+mod worker;
 
-pub use plugin::PluginTestCase;
+pub use plugin::{BenchmarkMeasurement, PluginTestCase};
+#[cfg(target_os = "linux")]
+pub use plugin_library::scan_side_effects_to_file;
 pub use plugin_library::PluginLibraryTestCase;
+pub use worker::{WorkerPool, WorkerRequest, WORKER_RESULT_PREFIX};
+
+/// The number of lines of output from the wrapper command that are included in a test's result
+/// when the wrapper reports an error. See [`OutOfProcessSettings::wrapper`].
+const WRAPPER_OUTPUT_LINES: usize = 20;
 
 /// A test case for testing the behavior of a plugin. This `Test` object contains the result of a
 /// test, which is serialized to and from JSON so the test can be run in another process.
@@ -40,6 +51,22 @@ pub struct TestResult {
     pub description: String,
     /// The outcome of the test.
     pub status: TestStatus,
+    /// Performance measurements made by the test. This is only set for benchmarks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub benchmark: Option<Vec<BenchmarkMeasurement>>,
+    /// Files outside of the temporary directory that were written to, created, or removed while
+    /// running the test in a sandbox. These changes have been discarded. This is only set when
+    /// running tests in a sandbox.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sandbox_writes: Vec<PathBuf>,
+    /// Directories that were made read-only in the sandbox because they could not be covered by an
+    /// overlay. Writes to these directories fail, and they are not included in
+    /// [`sandbox_writes`][Self::sandbox_writes]. This is only set when running tests in a sandbox.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sandbox_read_only_dirs: Vec<PathBuf>,
+    /// The messages the plugin logged through `clap_host_log::log()` while running the test.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plugin_log: Vec<PluginLogMessage>,
 }
 
 /// The result of running a test. Skipped and failed test may optionally include an explanation for
@@ -61,6 +88,10 @@ pub enum TestStatus {
     /// for tests involving runtime performance that might otherwise yield different results
     /// depending on the target system.
     Warning { details: Option<String> },
+    /// The test finished running, but the wrapper command the test was run through exited with an
+    /// error. This happens when a tool like valgrind detects a problem. This is only used for
+    /// out-of-process validation with a wrapper command.
+    WrapperFailed { details: String },
 }
 
 /// Stores all of the available tests and their descriptions. Used solely for pretty printing
@@ -72,6 +103,36 @@ pub struct TestList {
     pub plugin_tests: BTreeMap<String, String>,
 }
 
+/// Settings for running a test in a separate process using
+/// [`run_out_of_process()`][TestCase::run_out_of_process()].
+#[derive(Debug, Clone)]
+pub struct OutOfProcessSettings {
+    /// The verbosity level passed to the child process.
+    pub verbosity: Verbosity,
+    /// Hide the plugin's output by redirecting the child process' STDIO streams to `/dev/null`.
+    pub hide_output: bool,
+    /// Send the child process' STDOUT output to this process' STDERR stream instead so it does not
+    /// mix with the validator's own output. Used for the `--stream-json` mode.
+    pub stdout_to_stderr: bool,
+    /// Enable the audio thread audit mode in the child process. Only supported on Linux.
+    pub audit_audio_thread: bool,
+    /// Run the child process in a sandbox without network access, with its home directory
+    /// replaced by a temporary overlay. Only supported on Linux.
+    pub sandbox: bool,
+    /// A command and its arguments to run the child process through, like valgrind or `rr
+    /// record`. The child process is run directly if this is empty. The wrapper's STDERR output is
+    /// captured so it can be included in the test's result.
+    pub wrapper: Vec<String>,
+    /// Enable core dumps for the child process. Core dumps are written to the test's temporary
+    /// files directory. Only supported on Linux.
+    pub core_dumps: bool,
+    /// Stop the child process when it crashes so a debugger can be attached to it. Only supported
+    /// on Linux.
+    pub pause_on_crash: bool,
+    /// The audio port rescan flags the child process' host should report as unsupported.
+    pub reject_audio_ports_rescan_flags: Vec<AudioPortsRescanFlag>,
+}
+
 /// An abstraction for a test case. This mostly exists because we need two separate kinds of tests
 /// (per library and per plugin), and it's good to keep the interface uniform.
 pub trait TestCase<'a>: Display + FromStr + IntoEnumIterator + Sized + 'static {
@@ -88,6 +149,11 @@ pub trait TestCase<'a>: Display + FromStr + IntoEnumIterator + Sized + 'static {
     /// defined in a way that works for all `TestCase`s.
     fn set_out_of_process_args(&self, command: &mut Command, args: Self::TestArgs);
 
+    /// Get a short name for what this test is run on with the specified arguments. This is the
+    /// plugin's ID for plugin tests, and the library's file name for library tests. This is used
+    /// as the name of the directory the test's temporary files are stored in, and in messages.
+    fn test_subject(&self, args: &Self::TestArgs) -> String;
+
     /// Run a test case for a specified arguments in the current, returning the result. If the test
     /// cuases the plugin to segfault, then this will obviously not return. See
     /// [`run_out_of_process()`][Self::run_out_of_process()] for a generic way to run test cases in
@@ -111,8 +177,7 @@ pub trait TestCase<'a>: Display + FromStr + IntoEnumIterator + Sized + 'static {
     fn run_out_of_process(
         &self,
         args: Self::TestArgs,
-        verbosity: Verbosity,
-        hide_output: bool,
+        settings: &OutOfProcessSettings,
     ) -> Result<TestResult> {
         // The idea here is that we'll invoke the same clap-validator binary with a special hidden command
         // that runs a single test. This is the reason why test cases must be convertible to and
@@ -127,34 +192,126 @@ pub trait TestCase<'a>: Display + FromStr + IntoEnumIterator + Sized + 'static {
             .tempfile()
             .context("Could not create a temporary file path")?
             .into_temp_path();
-        let clap_validator_binary =
-            std::env::current_exe().context("Could not find the path to the current executable")?;
-        let mut command = Command::new(clap_validator_binary);
+        // The sandbox needs an empty directory to mount its file system on. This is created here so
+        // it also gets removed when this function exits.
+        let sandbox_dir = if settings.sandbox {
+            Some(tempfile::tempdir().context("Could not create a directory for the sandbox")?)
+        } else {
+            None
+        };
+        // Core dumps are stored alongside the test's other temporary files
+        let test_subject = self.test_subject(&args);
+        let core_dump_dir = if settings.core_dumps {
+            let core_dump_dir = self.temporary_dir(&test_subject);
+            fs::create_dir_all(&core_dump_dir)
+                .context("Could not create the directory for the test's core dumps")?;
 
-        command
-            .arg("--verbosity")
-            .arg(verbosity.to_possible_value().unwrap().get_name())
-            .arg("run-single-test")
-            .args([OsStr::new("--output-file"), output_file_path.as_os_str()]);
+            Some(core_dump_dir)
+        } else {
+            None
+        };
+        let mut command = child_process_command(
+            settings,
+            "run-single-test",
+            sandbox_dir.as_ref().map(|dir| dir.path()),
+        )?;
+        command.args([OsStr::new("--output-file"), output_file_path.as_os_str()]);
+        if let Some(core_dump_dir) = &core_dump_dir {
+            command.args([OsStr::new("--core-dump-dir"), core_dump_dir.as_os_str()]);
+        }
         self.set_out_of_process_args(&mut command, args);
-        if hide_output {
-            command.stdout(Stdio::null());
-            command.stderr(Stdio::null());
+        if !settings.wrapper.is_empty() {
+            command.stderr(Stdio::piped());
+        }
+        // The plugin's output would otherwise end up in between the streamed JSON results
+        if settings.stdout_to_stderr && !settings.hide_output {
+            command.stdout(Stdio::piped());
         }
 
-        let exit_status = command
-            .spawn()
-            .context("Could not call clap-validator for out-of-process validation")?
-            // The docs make it seem like this can only fail if the process isn't running, but if
-            // spawn succeeds then this can never fail:
-            .wait()
+        let mut child = if settings.wrapper.is_empty() {
+            command
+                .spawn()
+                .context("Could not call clap-validator for out-of-process validation")?
+        } else {
+            command.spawn().with_context(|| {
+                format!(
+                    "Could not run the wrapper command '{}'",
+                    settings.wrapper.join(" ")
+                )
+            })?
+        };
+        // The output needs to be read on another thread, or the child process may block when the
+        // pipe's buffer is full
+        let wrapper_output = child.stderr.take().map(|stderr| {
+            let echo = !settings.hide_output;
+            std::thread::spawn(move || capture_output(stderr, echo))
+        });
+        let stdout_redirect = child
+            .stdout
+            .take()
+            .map(|stdout| std::thread::spawn(move || capture_output(stdout, true)));
+        // The docs make it seem like this can only fail if the process isn't running, but if spawn
+        // succeeds then this can never fail:
+        #[cfg(target_os = "linux")]
+        let exit_status = if settings.pause_on_crash {
+            util::crash::wait_reporting_pause(&child, |pid| {
+                log::error!(
+                    "'{self}' crashed while testing '{test_subject}', and the process has been \
+                     paused. Attach a debugger using 'gdb -p {pid}', and resume the process with \
+                     'kill -CONT {pid}' to continue."
+                )
+            })
+        } else {
+            child.wait().map_err(anyhow::Error::from)
+        };
+        #[cfg(not(target_os = "linux"))]
+        let exit_status = child.wait().map_err(anyhow::Error::from);
+        let exit_status = exit_status
             .context("Error while waiting on clap-validator to finish running the test")?;
+        let wrapper_output = wrapper_output
+            .map(|handle| handle.join().expect("The output capturing thread panicked"))
+            .unwrap_or_default();
+        if let Some(handle) = stdout_redirect {
+            handle.join().expect("The output capturing thread panicked");
+        }
+
         if !exit_status.success() {
-            return Ok(TestResult {
-                name: self.to_string(),
-                description: self.description(),
-                status: TestStatus::Crashed {
-                    details: exit_status.to_string(),
+            // If the test still wrote its result, then the test itself finished and it was the
+            // wrapper that reported an error
+            let finished_result: Option<TestResult> = if settings.wrapper.is_empty() {
+                None
+            } else {
+                fs::read_to_string(&output_file_path)
+                    .ok()
+                    .and_then(|json| serde_json::from_str(&json).ok())
+            };
+
+            let mut details = match &finished_result {
+                Some(_) => format!(
+                    "The wrapper command exited with {exit_status} after the test had finished."
+                ),
+                None => crash_details(exit_status, core_dump_dir.as_deref()),
+            };
+            if !wrapper_output.is_empty() {
+                details.push_str(&format!(
+                    "\n\nThe last lines of output were:\n\n{}",
+                    wrapper_output.join("\n")
+                ));
+            }
+
+            return Ok(match finished_result {
+                Some(result) => TestResult {
+                    status: TestStatus::WrapperFailed { details },
+                    ..result
+                },
+                None => TestResult {
+                    name: self.to_string(),
+                    description: self.description(),
+                    status: TestStatus::Crashed { details },
+                    benchmark: None,
+                    sandbox_writes: Vec::new(),
+                    sandbox_read_only_dirs: Vec::new(),
+                    plugin_log: Vec::new(),
                 },
             });
         }
@@ -177,10 +334,7 @@ pub trait TestCase<'a>: Display + FromStr + IntoEnumIterator + Sized + 'static {
     /// `$TMP_DIR/clap-validator/$plugin_id/$test_name/$file_name`. The temporary files directory is
     /// cleared on a new validator run, but the files will persist until then.
     fn temporary_file(&self, plugin_id: &str, name: &str) -> Result<(PathBuf, fs::File)> {
-        let path = util::validator_temp_dir()
-            .join(plugin_id)
-            .join(self.to_string())
-            .join(name);
+        let path = self.temporary_dir(plugin_id).join(name);
         if path.exists() {
             panic!(
                 "Tried to create a temporary file at '{}', but this file already exists. This is \
@@ -197,17 +351,65 @@ pub trait TestCase<'a>: Display + FromStr + IntoEnumIterator + Sized + 'static {
         Ok((path, file))
     }
 
+    /// Get the directory this test case's temporary files are stored in. This is
+    /// `$TMP_DIR/clap-validator/$plugin_id/$test_name`. See
+    /// [`temporary_file()`][Self::temporary_file()].
+    fn temporary_dir(&self, plugin_id: &str) -> PathBuf {
+        util::validator_temp_dir()
+            .join(plugin_id)
+            .join(self.to_string())
+    }
+
     /// Create a [`TestResult`] for this test case. The test status is wrapped in an anyhow
     /// [`Result`] to make writing test cases more ergonomic using the question mark operator. `Err`
     /// values are converted to [`TestStatus::Failed`] statuses containing the full error backtrace.
-    fn create_result(&self, status: Result<TestStatus>) -> TestResult {
+    /// `plugin_log` should contain the messages logged by the plugin instances created during the
+    /// test.
+    fn create_result(
+        &self,
+        status: Result<TestStatus>,
+        plugin_log: Vec<PluginLogMessage>,
+    ) -> TestResult {
         TestResult {
             name: self.to_string(),
             description: self.description(),
             status: status.unwrap_or_else(|err| TestStatus::Failed {
                 details: Some(format!("{err:#}")),
             }),
+            benchmark: None,
+            sandbox_writes: Vec::new(),
+            sandbox_read_only_dirs: Vec::new(),
+            plugin_log,
+        }
+    }
+}
+
+impl TestResult {
+    /// Mark the test as failed if it would otherwise have passed and the plugin logged a message
+    /// with the `CLAP_LOG_PLUGIN_MISBEHAVING` severity. Used for the `--fail-on-plugin-misbehaving`
+    /// option.
+    pub fn fail_on_plugin_misbehaving(&mut self) {
+        let messages: Vec<&str> = self
+            .plugin_log
+            .iter()
+            .filter(|message| message.severity == LogSeverity::PluginMisbehaving)
+            .map(|message| message.message.as_str())
+            .collect();
+        if messages.is_empty()
+            || !matches!(
+                self.status,
+                TestStatus::Success { .. } | TestStatus::Warning { .. }
+            )
+        {
+            return;
         }
+
+        self.status = TestStatus::Failed {
+            details: Some(format!(
+                "The plugin reported that it misbehaved through 'clap_host_log::log()': {}",
+                messages.join("; ")
+            )),
+        };
     }
 }
 
@@ -217,9 +419,10 @@ impl TestStatus {
     pub fn failed_or_warning(&self) -> bool {
         match self {
             TestStatus::Success { .. } | TestStatus::Skipped { .. } => false,
-            TestStatus::Warning { .. } | TestStatus::Crashed { .. } | TestStatus::Failed { .. } => {
-                true
-            }
+            TestStatus::Warning { .. }
+            | TestStatus::Crashed { .. }
+            | TestStatus::Failed { .. }
+            | TestStatus::WrapperFailed { .. } => true,
         }
     }
 
@@ -230,7 +433,9 @@ impl TestStatus {
             | TestStatus::Failed { details }
             | TestStatus::Skipped { details }
             | TestStatus::Warning { details } => details.as_deref(),
-            TestStatus::Crashed { details } => Some(details),
+            TestStatus::Crashed { details } | TestStatus::WrapperFailed { details } => {
+                Some(details)
+            }
         }
     }
 }
@@ -247,3 +452,92 @@ impl Default for TestList {
         }
     }
 }
+
+/// Build the command for running clap-validator's hidden `subcommand` in a child process with the
+/// specified settings. `sandbox_dir` is the directory passed to the child process for the sandbox's
+/// files if [`OutOfProcessSettings::sandbox`] is enabled. The subcommand's own arguments still need
+/// to be added to the returned command.
+fn child_process_command(
+    settings: &OutOfProcessSettings,
+    subcommand: &str,
+    sandbox_dir: Option<&Path>,
+) -> Result<Command> {
+    let clap_validator_binary =
+        std::env::current_exe().context("Could not find the path to the current executable")?;
+    let mut command = match settings.wrapper.split_first() {
+        Some((wrapper, wrapper_args)) => {
+            let mut command = Command::new(wrapper);
+            command.args(wrapper_args).arg(clap_validator_binary);
+            command
+        }
+        None => Command::new(clap_validator_binary),
+    };
+
+    command
+        .arg("--verbosity")
+        .arg(settings.verbosity.to_possible_value().unwrap().get_name())
+        .arg(subcommand);
+    if settings.audit_audio_thread {
+        command.arg("--audit-audio-thread");
+    }
+    if let Some(sandbox_dir) = sandbox_dir {
+        command.args([OsStr::new("--sandbox-dir"), sandbox_dir.as_os_str()]);
+    }
+    if settings.pause_on_crash {
+        command.arg("--pause-on-crash");
+    }
+    if !settings.reject_audio_ports_rescan_flags.is_empty() {
+        let flags: Vec<String> = settings
+            .reject_audio_ports_rescan_flags
+            .iter()
+            .map(|flag| flag.to_possible_value().unwrap().get_name().to_owned())
+            .collect();
+        command
+            .arg("--reject-audio-ports-rescan-flags")
+            .arg(flags.join(","));
+    }
+    if settings.hide_output {
+        command.stdout(Stdio::null());
+        command.stderr(Stdio::null());
+    }
+
+    Ok(command)
+}
+
+/// Describe how a child process running a test crashed. If core dumps were enabled for the child
+/// process, then this also includes the location of the core dump.
+fn crash_details(exit_status: ExitStatus, core_dump_dir: Option<&Path>) -> String {
+    match core_dump_dir {
+        #[cfg(target_os = "linux")]
+        Some(core_dump_dir) => format!(
+            "{exit_status}. {}",
+            util::crash::describe_core_dumps(core_dump_dir)
+        ),
+        _ => exit_status.to_string(),
+    }
+}
+
+/// Read `stream` line by line until it gets closed, optionally echoing the lines to this process'
+/// STDERR stream. Returns the last [`WRAPPER_OUTPUT_LINES`] lines.
+fn capture_output(stream: impl Read, echo: bool) -> Vec<String> {
+    let mut reader = BufReader::new(stream);
+    let mut last_lines = VecDeque::with_capacity(WRAPPER_OUTPUT_LINES);
+    let mut line = Vec::new();
+    while let Ok(num_bytes) = reader.read_until(b'\n', &mut line) {
+        if num_bytes == 0 {
+            break;
+        }
+
+        if echo {
+            let _ = std::io::stderr().write_all(&line);
+        }
+
+        if last_lines.len() == WRAPPER_OUTPUT_LINES {
+            last_lines.pop_front();
+        }
+        last_lines.push_back(String::from_utf8_lossy(&line).trim_end().to_owned());
+        line.clear();
+    }
+
+    last_lines.into()
+}
@@ -5,13 +5,20 @@ use std::path::Path;
 use std::process::Command;
 
 use super::{TestCase, TestResult};
+use crate::plugin::host::{HostSettings, PluginLog};
 use crate::plugin::library::PluginLibrary;
 
+mod benchmark;
 mod descriptor;
+mod lifecycle;
 mod params;
+mod posix_fd;
 mod processing;
 mod state;
+mod threading;
+mod timer;
 
+pub use benchmark::BenchmarkMeasurement;
 pub use processing::ProcessingTest;
 
 /// The tests for individual CLAP plugins. See the module's heading for more information, and the
@@ -30,12 +37,32 @@ pub enum PluginTestCase {
     ProcessNoteOutOfPlaceBasic,
     #[strum(serialize = "process-note-inconsistent")]
     ProcessNoteInconsistent,
+    #[strum(serialize = "process-sleep")]
+    ProcessSleep,
+    #[strum(serialize = "process-benchmark")]
+    ProcessBenchmark,
+    #[strum(serialize = "process-concurrent-instances")]
+    ProcessConcurrentInstances,
+    #[strum(serialize = "process-audio-thread-migration")]
+    ProcessAudioThreadMigration,
+    #[strum(serialize = "process-lifecycle-stress")]
+    ProcessLifecycleStress,
+    #[strum(serialize = "instance-lifecycle-stress")]
+    InstanceLifecycleStress,
+    #[strum(serialize = "instance-resource-leaks")]
+    InstanceResourceLeaks,
     #[strum(serialize = "param-conversions")]
     ParamConversions,
     #[strum(serialize = "param-fuzz-basic")]
     ParamFuzzBasic,
     #[strum(serialize = "param-set-wrong-namespace")]
     ParamSetWrongNamespace,
+    #[strum(serialize = "param-rescan")]
+    ParamRescan,
+    #[strum(serialize = "param-flush")]
+    ParamFlush,
+    #[strum(serialize = "param-clear")]
+    ParamClear,
     #[strum(serialize = "state-invalid")]
     StateInvalid,
     #[strum(serialize = "state-reproducibility-basic")]
@@ -46,12 +73,21 @@ pub enum PluginTestCase {
     StateReproducibilityFlush,
     #[strum(serialize = "state-buffered-streams")]
     StateBufferedStreams,
+    #[strum(serialize = "state-mark-dirty")]
+    StateMarkDirty,
+    #[strum(serialize = "timer-process")]
+    TimerProcess,
+    #[strum(serialize = "timer-unregister")]
+    TimerUnregister,
+    #[strum(serialize = "posix-fd-unregister")]
+    PosixFdUnregister,
 }
 
 impl<'a> TestCase<'a> for PluginTestCase {
-    /// Path to a CLAP plugin library, a loaded CLAP plugin library and the ID of the plugin contained
-    /// within that library that should be tested.
-    type TestArgs = (&'a Path, &'a PluginLibrary, &'a str);
+    /// Path to a CLAP plugin library, a loaded CLAP plugin library, the ID of the plugin contained
+    /// within that library that should be tested, and the settings for the tests' hosts. Child
+    /// processes receive the host settings through their command line options instead.
+    type TestArgs = (&'a Path, &'a PluginLibrary, &'a str, &'a HostSettings);
 
     fn description(&self) -> String {
         match self {
@@ -80,6 +116,66 @@ impl<'a> TestCase<'a> for PluginTestCase {
                  plugin with its default parameter values and tests the output for consistency. \
                  Uses out-of-place audio processing.",
             ),
+            PluginTestCase::ProcessSleep => String::from(
+                "Processes silent audio without any events. After the plugin returns \
+                 'CLAP_PROCESS_SLEEP', 'clap_plugin::process()' is no longer called until the \
+                 plugin calls 'clap_host::request_process()'. Calling that function from the same \
+                 cycle that returned 'CLAP_PROCESS_SLEEP' results in a warning. The output is \
+                 tested for consistency.",
+            ),
+            PluginTestCase::ProcessBenchmark => format!(
+                "Measures the time taken by each 'clap_plugin::process()' call at buffer sizes of \
+                 {:?} samples and sample rates of {:?} Hz, both with silent input and with random \
+                 audio and note events. The mean, 99th percentile, and maximum processing times \
+                 are reported as fractions of the realtime budget. Results in a warning if the \
+                 plugin does not keep up with realtime. Use --no-parallel for more accurate \
+                 measurements.",
+                benchmark::BENCHMARK_BUFFER_SIZES,
+                benchmark::BENCHMARK_SAMPLE_RATES,
+            ),
+            PluginTestCase::ProcessConcurrentInstances => format!(
+                "Creates {} instances of the plugin along with one instance of every other plugin \
+                 in the same library, and has them process random audio, note events, and \
+                 parameter changes concurrently on separate audio threads for {} buffers. Fails \
+                 if the output contains non-finite or subnormal values, or if an instance's \
+                 output differs from processing the same input on its own. The latter is only \
+                 checked if the plugin's output is deterministic.",
+                threading::CONCURRENCY_NUM_INSTANCES,
+                threading::CONCURRENCY_NUM_BLOCKS,
+            ),
+            PluginTestCase::ProcessAudioThreadMigration => format!(
+                "Processes random audio, note events, and parameter changes for {} buffers while \
+                 moving the plugin to the next thread in a pool of {} audio threads after every \
+                 buffer, the same way hosts with worker pools may. Fails if the plugin returns \
+                 errors, if the output contains non-finite or subnormal values, or if the host's \
+                 'thread-check' extension does not identify the current thread as the audio \
+                 thread.",
+                threading::CONCURRENCY_NUM_BLOCKS,
+                threading::MIGRATION_NUM_THREADS,
+            ),
+            PluginTestCase::ProcessLifecycleStress => format!(
+                "Puts the plugin through {} activate, process, and deactivate cycles, each with a \
+                 different random sample rate and minimum and maximum frame count. Within a \
+                 cycle, processing is started and stopped again up to {} times with random \
+                 buffer sizes in the activation's bounds. Fails if the output contains non-finite \
+                 or subnormal values, or if the plugin misbehaves during a host callback.",
+                lifecycle::LIFECYCLE_NUM_CYCLES,
+                lifecycle::LIFECYCLE_MAX_PROCESSING_RUNS,
+            ),
+            PluginTestCase::InstanceLifecycleStress => format!(
+                "Creates, initializes, activates, processes a single buffer with, and destroys {} \
+                 plugin instances in a row to expose crashes and resource leaks related to \
+                 instance creation and destruction.",
+                lifecycle::INSTANCE_LIFECYCLE_NUM_INSTANCES,
+            ),
+            PluginTestCase::InstanceResourceLeaks => format!(
+                "Creates, initializes, activates, processes a single buffer with, and destroys {} \
+                 plugin instances in a row while tracking the process' thread count, open file \
+                 descriptors, and resident set size. Fails if the thread or file descriptor \
+                 counts grow steadily, and results in a warning if the memory usage does. Only \
+                 supported on Linux.",
+                lifecycle::LEAK_NUM_INSTANCES,
+            ),
             PluginTestCase::ParamConversions => String::from(
                 "Asserts that value to string and string to value conversions are supported for \
                  ether all or none of the plugin's parameters, and that conversions between \
@@ -97,6 +193,25 @@ impl<'a> TestCase<'a> for PluginTestCase {
                  a mismatching namespace ID. Asserts that the plugin's parameter values don't \
                  change.",
             ),
+            PluginTestCase::ParamRescan => String::from(
+                "Loads a state with random parameter values into a new plugin instance, first \
+                 while the plugin is deactivated and then while it is activated. The changes to \
+                 the plugin's parameters need to match the flags passed to \
+                 'clap_host_params::rescan()', and parameters may only be added or removed using \
+                 'CLAP_PARAM_RESCAN_ALL' while the plugin is deactivated.",
+            ),
+            PluginTestCase::ParamFlush => String::from(
+                "Sends random parameter values to the plugin through the host's handling of \
+                 'clap_host_params::request_flush()', which calls 'clap_plugin_params::flush()' \
+                 on the main thread. The plugin should either update its parameter values or \
+                 request another flush, and its output events should be in order.",
+            ),
+            PluginTestCase::ParamClear => String::from(
+                "Adds fake automation and modulation references for all of the plugin's \
+                 parameters, and then loads different states into the plugin. If this causes the \
+                 plugin to remove parameters, then it should call 'clap_host_params::clear()' to \
+                 clear the references to those parameters.",
+            ),
             PluginTestCase::StateInvalid => String::from(
                 "The plugin should return false when 'clap_plugin_state::load()' is called with \
                  an empty state.",
@@ -126,10 +241,35 @@ impl<'a> TestCase<'a> for PluginTestCase {
                  when reloading and resaving the state.",
                 PluginTestCase::StateReproducibilityBasic
             ),
+            PluginTestCase::StateMarkDirty => String::from(
+                "Changes the plugin's parameters through both the process and the flush \
+                 functions. The parameter changes are made by the host, so the plugin should not \
+                 repeatedly call 'clap_host_state::mark_dirty()' in response. This results in a \
+                 warning if it does.",
+            ),
+            PluginTestCase::TimerProcess => String::from(
+                "Processes random audio in realtime while the plugin's timers registered through \
+                 'clap_host_timer_support::register_timer()' fire on the main thread.",
+            ),
+            PluginTestCase::TimerUnregister => String::from(
+                "Initializes the plugin, processes a single buffer of audio, and then destroys \
+                 the plugin. All timers the plugin registered must have been unregistered by the \
+                 time 'clap_plugin::destroy()' returns.",
+            ),
+            PluginTestCase::PosixFdUnregister => String::from(
+                "Initializes the plugin, processes a single buffer of audio, and then destroys \
+                 the plugin. All file descriptors the plugin registered through \
+                 'clap_host_posix_fd_support::register_fd()' must have been unregistered by the \
+                 time 'clap_plugin::destroy()' returns. This test is only supported on Linux.",
+            ),
         }
     }
 
-    fn set_out_of_process_args(&self, command: &mut Command, (path, _library, plugin_id): Self::TestArgs) {
+    fn set_out_of_process_args(
+        &self,
+        command: &mut Command,
+        (path, _library, plugin_id, _host_settings): Self::TestArgs,
+    ) {
         let test_name = self.to_string();
 
         command
@@ -144,10 +284,27 @@ impl<'a> TestCase<'a> for PluginTestCase {
             .arg(test_name);
     }
 
-    fn run_in_process(&self, (_path, library, plugin_id): Self::TestArgs) -> TestResult {
+    fn test_subject(
+        &self,
+        (_path, _library, plugin_id, _host_settings): &Self::TestArgs,
+    ) -> String {
+        String::from(*plugin_id)
+    }
+
+    fn run_in_process(
+        &self,
+        (_path, library, plugin_id, host_settings): Self::TestArgs,
+    ) -> TestResult {
+        // The hosts created by the test add the plugin's log messages to this buffer
+        let plugin_log = PluginLog::default();
+        let host_settings = &HostSettings {
+            plugin_log: plugin_log.clone(),
+            ..host_settings.clone()
+        };
+
         let status = match self {
             PluginTestCase::DescriptorConsistency => {
-                descriptor::test_consistency(library, plugin_id)
+                descriptor::test_consistency(library, plugin_id, host_settings)
             }
             PluginTestCase::FeaturesCategories => {
                 descriptor::test_features_categories(library, plugin_id)
@@ -156,34 +313,108 @@ impl<'a> TestCase<'a> for PluginTestCase {
                 descriptor::test_features_duplicates(library, plugin_id)
             }
             PluginTestCase::ProcessAudioOutOfPlaceBasic => {
-                processing::test_process_audio_out_of_place_basic(library, plugin_id)
+                processing::test_process_audio_out_of_place_basic(library, plugin_id, host_settings)
             }
             PluginTestCase::ProcessNoteOutOfPlaceBasic => {
-                processing::test_process_note_out_of_place_basic(library, plugin_id)
+                processing::test_process_note_out_of_place_basic(library, plugin_id, host_settings)
             }
             PluginTestCase::ProcessNoteInconsistent => {
-                processing::test_process_note_inconsistent(library, plugin_id)
+                processing::test_process_note_inconsistent(library, plugin_id, host_settings)
+            }
+            PluginTestCase::ProcessSleep => {
+                processing::test_process_sleep(library, plugin_id, host_settings)
+            }
+            PluginTestCase::ProcessBenchmark => {
+                // This test also returns its measurements, which are stored on the test result
+                return match benchmark::test_process_benchmark(library, plugin_id, host_settings) {
+                    Ok((status, measurements)) => TestResult {
+                        benchmark: Some(measurements),
+                        ..self.create_result(Ok(status), plugin_log.take())
+                    },
+                    Err(err) => self.create_result(Err(err), plugin_log.take()),
+                };
+            }
+            PluginTestCase::ProcessConcurrentInstances => {
+                threading::test_process_concurrent_instances(library, plugin_id, host_settings)
+            }
+            PluginTestCase::ProcessAudioThreadMigration => {
+                threading::test_process_audio_thread_migration(library, plugin_id, host_settings)
+            }
+            PluginTestCase::ProcessLifecycleStress => {
+                lifecycle::test_process_lifecycle_stress(library, plugin_id, host_settings)
+            }
+            PluginTestCase::InstanceLifecycleStress => {
+                lifecycle::test_instance_lifecycle_stress(library, plugin_id, host_settings)
+            }
+            PluginTestCase::InstanceResourceLeaks => {
+                lifecycle::test_instance_resource_leaks(library, plugin_id, host_settings)
+            }
+            PluginTestCase::ParamConversions => {
+                params::test_param_conversions(library, plugin_id, host_settings)
+            }
+            PluginTestCase::ParamFuzzBasic => {
+                params::test_param_fuzz_basic(library, plugin_id, host_settings)
             }
-            PluginTestCase::ParamConversions => params::test_param_conversions(library, plugin_id),
-            PluginTestCase::ParamFuzzBasic => params::test_param_fuzz_basic(library, plugin_id),
             PluginTestCase::ParamSetWrongNamespace => {
-                params::test_param_set_wrong_namespace(library, plugin_id)
+                params::test_param_set_wrong_namespace(library, plugin_id, host_settings)
+            }
+            PluginTestCase::ParamRescan => {
+                params::test_param_rescan(library, plugin_id, host_settings)
+            }
+            PluginTestCase::ParamFlush => {
+                params::test_param_flush(library, plugin_id, host_settings)
+            }
+            PluginTestCase::ParamClear => {
+                params::test_param_clear(library, plugin_id, host_settings)
+            }
+            PluginTestCase::StateInvalid => {
+                state::test_state_invalid(library, plugin_id, host_settings)
             }
-            PluginTestCase::StateInvalid => state::test_state_invalid(library, plugin_id),
             PluginTestCase::StateReproducibilityBasic => {
-                state::test_state_reproducibility_null_cookies(library, plugin_id, false)
+                state::test_state_reproducibility_null_cookies(
+                    library,
+                    plugin_id,
+                    false,
+                    host_settings,
+                )
             }
             PluginTestCase::StateReproducibilityNullCookies => {
-                state::test_state_reproducibility_null_cookies(library, plugin_id, true)
+                state::test_state_reproducibility_null_cookies(
+                    library,
+                    plugin_id,
+                    true,
+                    host_settings,
+                )
             }
             PluginTestCase::StateReproducibilityFlush => {
-                state::test_state_reproducibility_flush(library, plugin_id)
+                state::test_state_reproducibility_flush(library, plugin_id, host_settings)
             }
             PluginTestCase::StateBufferedStreams => {
-                state::test_state_buffered_streams(library, plugin_id)
+                state::test_state_buffered_streams(library, plugin_id, host_settings)
+            }
+            PluginTestCase::StateMarkDirty => {
+                state::test_state_mark_dirty(library, plugin_id, host_settings)
+            }
+            PluginTestCase::TimerProcess => {
+                timer::test_timer_process(library, plugin_id, host_settings)
+            }
+            PluginTestCase::TimerUnregister => {
+                timer::test_timer_unregister(library, plugin_id, host_settings)
+            }
+            PluginTestCase::PosixFdUnregister => {
+                posix_fd::test_posix_fd_unregister(library, plugin_id, host_settings)
             }
         };
 
-        self.create_result(status)
+        self.create_result(status, plugin_log.take())
+    }
+}
+
+impl PluginTestCase {
+    /// Whether this test inspects state shared by the entire process, like the process' thread
+    /// count, file descriptors, or memory usage. Earlier tests run in the same process would affect
+    /// the outcome of these tests, so they're never run in persistent worker processes.
+    pub fn inspects_process_state(&self) -> bool {
+        matches!(self, PluginTestCase::InstanceResourceLeaks)
     }
 }
@@ -0,0 +1,257 @@
+//! Tests that measure a plugin's audio processing performance.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use crate::plugin::ext::audio_ports::{AudioPortConfig, AudioPorts};
+use crate::plugin::ext::note_ports::{NotePortConfig, NotePorts};
+use crate::plugin::host::{Host, HostSettings};
+use crate::plugin::instance::process::{
+    AudioBuffers, OutOfPlaceAudioBuffers, ProcessConfig, ProcessData,
+};
+use crate::plugin::instance::Plugin;
+use crate::plugin::library::PluginLibrary;
+use crate::tests::rng::{new_prng, NoteGenerator};
+use crate::tests::TestStatus;
+
+/// The buffer sizes the plugin is benchmarked at.
+pub const BENCHMARK_BUFFER_SIZES: [usize; 3] = [64, 256, 1024];
+/// The sample rates the plugin is benchmarked at.
+pub const BENCHMARK_SAMPLE_RATES: [f64; 2] = [44_100.0, 96_000.0];
+/// The amount of audio processed for every benchmark configuration, in seconds. The number of
+/// process calls that are measured thus depends on the buffer size and the sample rate.
+pub const BENCHMARK_AUDIO_DURATION_SECS: f64 = 1.0;
+/// The number of process calls made before the measurements start. This gives the plugin a chance
+/// to warm up its caches and to perform any lazy initialization.
+const BENCHMARK_WARMUP_ITERATIONS: usize = 32;
+/// The maximum wall clock time spent measuring a single configuration. This keeps the test from
+/// taking ages for very slow plugins.
+const BENCHMARK_MAX_DURATION: Duration = Duration::from_secs(10);
+
+/// The timing measurements for a single benchmark configuration. All times are expressed as a
+/// fraction of the realtime budget, i.e. the duration of the audio processed in a single
+/// `process()` call. A plugin taking longer than the realtime budget would cause dropouts.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BenchmarkMeasurement {
+    /// The sample rate used for this measurement.
+    pub sample_rate: f64,
+    /// The number of samples processed in each `process()` call.
+    pub buffer_size: usize,
+    /// Whether random note events were sent to the plugin. If this is `false`, then the plugin
+    /// only received silence.
+    pub note_load: bool,
+    /// The number of measured `process()` calls.
+    pub num_process_calls: usize,
+    /// The mean processing time, relative to the realtime budget.
+    pub mean: f64,
+    /// The 99th percentile processing time, relative to the realtime budget.
+    pub p99: f64,
+    /// The maximum processing time, relative to the realtime budget.
+    pub max: f64,
+}
+
+/// The test for `PluginTestCase::ProcessBenchmark`. Returns the test's status along with the
+/// measurements for every configuration.
+pub fn test_process_benchmark(
+    library: &PluginLibrary,
+    plugin_id: &str,
+    host_settings: &HostSettings,
+) -> Result<(TestStatus, Vec<BenchmarkMeasurement>)> {
+    let host = Host::new(host_settings);
+    let plugin = library
+        .create_plugin(plugin_id, host.clone())
+        .context("Could not create the plugin instance")?;
+    plugin.init().context("Error during initialization")?;
+
+    // Note-only plugins can also be benchmarked, so neither of these extensions are required
+    let audio_ports_config = match plugin.get_extension::<AudioPorts>() {
+        Some(audio_ports) => audio_ports
+            .config()
+            .context("Error while querying 'audio-ports' IO configuration")?,
+        None => AudioPortConfig::default(),
+    };
+    let note_ports_config = match plugin.get_extension::<NotePorts>() {
+        Some(note_ports) => Some(
+            note_ports
+                .config()
+                .context("Error while querying 'note-ports' IO configuration")?,
+        )
+        .filter(|config| !config.inputs.is_empty()),
+        None => None,
+    };
+    host.handle_callbacks_once();
+
+    let mut measurements = Vec::new();
+    for sample_rate in BENCHMARK_SAMPLE_RATES {
+        for buffer_size in BENCHMARK_BUFFER_SIZES {
+            measurements.push(
+                benchmark_configuration(
+                    &plugin,
+                    &audio_ports_config,
+                    None,
+                    sample_rate,
+                    buffer_size,
+                )
+                .with_context(|| {
+                    format!(
+                        "Error while benchmarking at {sample_rate} Hz with a buffer size of \
+                         {buffer_size} samples"
+                    )
+                })?,
+            );
+
+            if let Some(note_ports_config) = &note_ports_config {
+                measurements.push(
+                    benchmark_configuration(
+                        &plugin,
+                        &audio_ports_config,
+                        Some(note_ports_config),
+                        sample_rate,
+                        buffer_size,
+                    )
+                    .with_context(|| {
+                        format!(
+                            "Error while benchmarking at {sample_rate} Hz with a buffer size of \
+                             {buffer_size} samples with note events"
+                        )
+                    })?,
+                );
+            }
+        }
+    }
+
+    host.callback_error_check()
+        .context("An error occured during a host callback")?;
+
+    // The 99th percentile is used to decide whether the plugin keeps up with realtime since the
+    // maximum is too noisy to be meaningful
+    let worst = measurements
+        .iter()
+        .max_by(|a, b| a.p99.total_cmp(&b.p99))
+        .expect("No benchmarks were run");
+    let summary = format!(
+        "The worst 99th percentile processing time was {:.1}% of the realtime budget at {} Hz \
+         with a buffer size of {} samples{} (mean {:.1}%, max {:.1}%).",
+        worst.p99 * 100.0,
+        worst.sample_rate,
+        worst.buffer_size,
+        if worst.note_load {
+            " with note events"
+        } else {
+            ""
+        },
+        worst.mean * 100.0,
+        worst.max * 100.0,
+    );
+    let status = if worst.p99 >= 1.0 {
+        TestStatus::Warning {
+            details: Some(format!(
+                "The plugin does not keep up with realtime. {summary}"
+            )),
+        }
+    } else {
+        TestStatus::Success {
+            details: Some(summary),
+        }
+    };
+
+    Ok((status, measurements))
+}
+
+/// Measure the time taken by each `process()` call for a single configuration. The plugin is
+/// activated and deactivated again in the process. If `note_ports_config` is set, then random note
+/// events and audio are sent to the plugin. Otherwise the plugin only receives silence.
+fn benchmark_configuration(
+    plugin: &Plugin,
+    audio_ports_config: &AudioPortConfig,
+    note_ports_config: Option<&NotePortConfig>,
+    sample_rate: f64,
+    buffer_size: usize,
+) -> Result<BenchmarkMeasurement> {
+    let mut prng = new_prng();
+    let mut note_event_rng = note_ports_config.cloned().map(NoteGenerator::new);
+
+    let num_iterations =
+        ((BENCHMARK_AUDIO_DURATION_SECS * sample_rate) / buffer_size as f64).ceil() as usize;
+    let (mut input_buffers, mut output_buffers) = audio_ports_config.create_buffers(buffer_size);
+    let mut audio_buffers = AudioBuffers::OutOfPlace(OutOfPlaceAudioBuffers::new(
+        &mut input_buffers,
+        &mut output_buffers,
+    )?);
+    let mut process_data = ProcessData::new(
+        &mut audio_buffers,
+        ProcessConfig {
+            sample_rate,
+            ..ProcessConfig::default()
+        },
+    );
+
+    plugin.activate(sample_rate, 1, buffer_size)?;
+    let durations = plugin.on_audio_thread(|plugin| -> Result<Vec<Duration>> {
+        plugin.start_processing()?;
+
+        let mut durations = Vec::with_capacity(num_iterations);
+        let mut measurement_start = None;
+        for iteration in 0..BENCHMARK_WARMUP_ITERATIONS + num_iterations {
+            if let Some(note_event_rng) = &mut note_event_rng {
+                note_event_rng.fill_event_queue(
+                    &mut prng,
+                    &process_data.input_events,
+                    buffer_size as u32,
+                )?;
+                process_data.buffers.randomize(&mut prng);
+            }
+
+            let process_start = Instant::now();
+            plugin
+                .process(&mut process_data)
+                .context("Error during audio processing")?;
+            let process_duration = process_start.elapsed();
+
+            if iteration >= BENCHMARK_WARMUP_ITERATIONS {
+                durations.push(process_duration);
+
+                let measurement_start = measurement_start.get_or_insert(process_start);
+                if measurement_start.elapsed() >= BENCHMARK_MAX_DURATION {
+                    log::debug!(
+                        "Stopping the benchmark after {} out of {num_iterations} process calls \
+                         because it took too long",
+                        durations.len()
+                    );
+                    break;
+                }
+            }
+
+            process_data.clear_events();
+            process_data.advance_transport(buffer_size as u32);
+        }
+
+        plugin.stop_processing();
+
+        Ok(durations)
+    })?;
+    plugin.deactivate();
+
+    // Handle callbacks the plugin may have made during deactivate
+    plugin.host().handle_callbacks_once();
+
+    let budget_secs = buffer_size as f64 / sample_rate;
+    let mut factors: Vec<f64> = durations
+        .iter()
+        .map(|duration| duration.as_secs_f64() / budget_secs)
+        .collect();
+    factors.sort_by(f64::total_cmp);
+
+    let p99_idx = ((factors.len() as f64 * 0.99).ceil() as usize).saturating_sub(1);
+    Ok(BenchmarkMeasurement {
+        sample_rate,
+        buffer_size,
+        note_load: note_ports_config.is_some(),
+        num_process_calls: factors.len(),
+        mean: factors.iter().sum::<f64>() / factors.len() as f64,
+        p99: factors[p99_idx],
+        max: *factors.last().unwrap(),
+    })
+}
@@ -7,13 +7,17 @@ use clap_sys::plugin_features::{
 };
 use std::collections::HashSet;
 
-use crate::plugin::host::Host;
+use crate::plugin::host::{Host, HostSettings};
 use crate::plugin::library::PluginLibrary;
 use crate::tests::TestStatus;
 
 /// Verifies that the descriptor stored in the factory and the descriptor stored on the plugin
 /// object are equivalent.
-pub fn test_consistency(library: &PluginLibrary, plugin_id: &str) -> Result<TestStatus> {
+pub fn test_consistency(
+    library: &PluginLibrary,
+    plugin_id: &str,
+    host_settings: &HostSettings,
+) -> Result<TestStatus> {
     let metadata = library.metadata().with_context(|| {
         format!(
             "Could not fetch plugin metadata for '{}'",
@@ -26,7 +30,7 @@ pub fn test_consistency(library: &PluginLibrary, plugin_id: &str) -> Result<Test
         .find(|plugin_meta| plugin_meta.id == plugin_id)
         .expect("Incorrect plugin ID for metadata query, this is a bug in clap-validator");
 
-    let host = Host::new();
+    let host = Host::new(host_settings);
     let plugin = library
         .create_plugin(plugin_id, host)
         .context("Could not create the plugin instance")?;
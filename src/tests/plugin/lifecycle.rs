@@ -0,0 +1,380 @@
+//! Tests that repeatedly cycle the plugin through its lifecycle.
+
+use anyhow::{Context, Result};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand_pcg::Pcg32;
+use std::sync::Arc;
+
+use super::processing::{check_out_of_place_output_consistency, ProcessingTest};
+use crate::plugin::ext::audio_ports::{AudioPortConfig, AudioPorts};
+use crate::plugin::ext::note_ports::{NotePortConfig, NotePorts};
+use crate::plugin::host::{Host, HostSettings};
+use crate::plugin::instance::audio_thread::PluginAudioThread;
+use crate::plugin::instance::process::{
+    AudioBuffers, OutOfPlaceAudioBuffers, ProcessConfig, ProcessData,
+};
+use crate::plugin::instance::Plugin;
+use crate::plugin::library::PluginLibrary;
+use crate::tests::rng::{new_prng, NoteGenerator};
+use crate::tests::TestStatus;
+#[cfg(target_os = "linux")]
+use crate::util::procfs::ResourceUsage;
+
+/// The number of activate-deactivate cycles in the lifecycle stress test.
+pub const LIFECYCLE_NUM_CYCLES: usize = 32;
+/// The maximum number of times processing is started and stopped again within a single
+/// activate-deactivate cycle.
+pub const LIFECYCLE_MAX_PROCESSING_RUNS: usize = 4;
+/// The number of blocks processed every time processing is started.
+const LIFECYCLE_BLOCKS_PER_RUN: usize = 8;
+/// The sample rates randomly chosen from for every cycle.
+const LIFECYCLE_SAMPLE_RATES: [f64; 7] = [
+    22_050.0, 44_100.0, 48_000.0, 88_200.0, 96_000.0, 176_400.0, 192_000.0,
+];
+/// The maximum frame counts randomly chosen from for every cycle. The minimum frame count is
+/// chosen randomly between 1 and the maximum frame count.
+const LIFECYCLE_MAX_FRAME_COUNTS: [usize; 8] = [32, 64, 128, 256, 512, 1024, 2048, 4096];
+
+/// The number of times the plugin instance is created and destroyed again in the instance
+/// lifecycle stress test.
+pub const INSTANCE_LIFECYCLE_NUM_INSTANCES: usize = 256;
+const INSTANCE_LIFECYCLE_BUFFER_SIZE: usize = 256;
+
+/// The number of instances created and destroyed before the leak test starts measuring.
+#[cfg(target_os = "linux")]
+const LEAK_WARMUP_NUM_INSTANCES: usize = 16;
+/// The number of instances created and destroyed while the leak test is measuring.
+pub const LEAK_NUM_INSTANCES: usize = 128;
+/// Growth in resident set size below this many bytes per instance is not reported as a leak.
+#[cfg(target_os = "linux")]
+const LEAK_RSS_TOLERANCE_PER_INSTANCE: u64 = 4096;
+
+/// The test for `PluginTestCase::ProcessLifecycleStress`.
+pub fn test_process_lifecycle_stress(
+    library: &PluginLibrary,
+    plugin_id: &str,
+    host_settings: &HostSettings,
+) -> Result<TestStatus> {
+    let mut prng = new_prng();
+
+    let host = Host::new(host_settings);
+    let plugin = library
+        .create_plugin(plugin_id, host.clone())
+        .context("Could not create the plugin instance")?;
+    plugin.init().context("Error during initialization")?;
+
+    let (audio_ports_config, note_ports_config) = query_io_config(&plugin)?;
+    let mut note_event_rng = note_ports_config.map(NoteGenerator::new);
+    host.handle_callbacks_once();
+
+    for cycle_idx in 0..LIFECYCLE_NUM_CYCLES {
+        let sample_rate = *LIFECYCLE_SAMPLE_RATES.choose(&mut prng).unwrap();
+        let max_frames = *LIFECYCLE_MAX_FRAME_COUNTS.choose(&mut prng).unwrap();
+        let min_frames = prng.gen_range(1..=max_frames);
+        let num_runs = prng.gen_range(1..=LIFECYCLE_MAX_PROCESSING_RUNS);
+
+        let context = || {
+            format!(
+                "Failed during cycle {} out of {LIFECYCLE_NUM_CYCLES} at {sample_rate} Hz with a \
+                 minimum frame count of {min_frames} and a maximum frame count of {max_frames}",
+                cycle_idx + 1,
+            )
+        };
+
+        plugin
+            .activate(sample_rate, min_frames, max_frames)
+            .with_context(context)?;
+        let result = plugin.on_audio_thread(|plugin| -> Result<()> {
+            for run_idx in 0..num_runs {
+                // Every time processing is restarted, a different buffer size within the
+                // activation's bounds is used
+                let buffer_size = prng.gen_range(min_frames..=max_frames);
+                process_random_blocks(
+                    &plugin,
+                    &audio_ports_config,
+                    note_event_rng.as_mut(),
+                    &mut prng,
+                    sample_rate,
+                    buffer_size,
+                )
+                .with_context(|| {
+                    format!(
+                        "Failed after starting processing for the {} time out of {num_runs}, with \
+                         a buffer size of {buffer_size} samples",
+                        ordinal(run_idx + 1)
+                    )
+                })?;
+            }
+
+            Ok(())
+        });
+        plugin.deactivate();
+        host.handle_callbacks_once();
+        result.with_context(context)?;
+
+        host.callback_error_check()
+            .context("An error occured during a host callback")
+            .with_context(context)?;
+    }
+
+    Ok(TestStatus::Success { details: None })
+}
+
+/// The test for `PluginTestCase::InstanceLifecycleStress`.
+pub fn test_instance_lifecycle_stress(
+    library: &PluginLibrary,
+    plugin_id: &str,
+    host_settings: &HostSettings,
+) -> Result<TestStatus> {
+    let mut prng = new_prng();
+
+    let host = Host::new(host_settings);
+    for instance_idx in 0..INSTANCE_LIFECYCLE_NUM_INSTANCES {
+        create_process_destroy(library, plugin_id, &host, &mut prng).with_context(|| {
+            format!(
+                "Failed for instance {} out of {INSTANCE_LIFECYCLE_NUM_INSTANCES}",
+                instance_idx + 1
+            )
+        })?;
+    }
+
+    Ok(TestStatus::Success { details: None })
+}
+
+/// The test for `PluginTestCase::InstanceResourceLeaks`.
+#[cfg(target_os = "linux")]
+pub fn test_instance_resource_leaks(
+    library: &PluginLibrary,
+    plugin_id: &str,
+    host_settings: &HostSettings,
+) -> Result<TestStatus> {
+    let mut prng = new_prng();
+
+    // Plugins may lazily initialize global state for the first instance, and the allocator may
+    // need a couple of rounds before it settles. That should not count as a leak.
+    let host = Host::new(host_settings);
+    for instance_idx in 0..LEAK_WARMUP_NUM_INSTANCES {
+        create_process_destroy(library, plugin_id, &host, &mut prng).with_context(|| {
+            format!(
+                "Failed for warmup instance {} out of {LEAK_WARMUP_NUM_INSTANCES}",
+                instance_idx + 1
+            )
+        })?;
+    }
+
+    // Resource usage is measured at the start, halfway through, and at the end. Only steady
+    // growth over both halves is considered to be a leak.
+    let baseline = ResourceUsage::current()?;
+    let mut halfway = baseline;
+    for instance_idx in 0..LEAK_NUM_INSTANCES {
+        create_process_destroy(library, plugin_id, &host, &mut prng).with_context(|| {
+            format!(
+                "Failed for instance {} out of {LEAK_NUM_INSTANCES}",
+                instance_idx + 1
+            )
+        })?;
+
+        if instance_idx + 1 == LEAK_NUM_INSTANCES / 2 {
+            halfway = ResourceUsage::current()?;
+        }
+    }
+    let end = ResourceUsage::current()?;
+
+    let grows_steadily =
+        |baseline: u64, halfway: u64, end: u64| baseline < halfway && halfway < end;
+    let mut leaks = Vec::new();
+    if grows_steadily(
+        baseline.num_threads as u64,
+        halfway.num_threads as u64,
+        end.num_threads as u64,
+    ) {
+        leaks.push(format!(
+            "the number of threads grew from {} to {} ({:.2} threads per instance)",
+            baseline.num_threads,
+            end.num_threads,
+            (end.num_threads - baseline.num_threads) as f64 / LEAK_NUM_INSTANCES as f64
+        ));
+    }
+    if grows_steadily(
+        baseline.num_fds as u64,
+        halfway.num_fds as u64,
+        end.num_fds as u64,
+    ) {
+        leaks.push(format!(
+            "the number of open file descriptors grew from {} to {} ({:.2} file descriptors per \
+             instance)",
+            baseline.num_fds,
+            end.num_fds,
+            (end.num_fds - baseline.num_fds) as f64 / LEAK_NUM_INSTANCES as f64
+        ));
+    }
+    if !leaks.is_empty() {
+        anyhow::bail!(
+            "After creating and destroying {LEAK_NUM_INSTANCES} instances, {}.",
+            leaks.join(", and ")
+        );
+    }
+
+    // Memory usage is much noisier than the thread and file descriptor counts, so this only
+    // results in a warning
+    let rss_growth_per_instance =
+        end.rss_bytes.saturating_sub(baseline.rss_bytes) / LEAK_NUM_INSTANCES as u64;
+    if grows_steadily(baseline.rss_bytes, halfway.rss_bytes, end.rss_bytes)
+        && rss_growth_per_instance > LEAK_RSS_TOLERANCE_PER_INSTANCE
+    {
+        return Ok(TestStatus::Warning {
+            details: Some(format!(
+                "After creating and destroying {LEAK_NUM_INSTANCES} instances, the resident set \
+                 size grew from {:.1} MiB to {:.1} MiB ({:.1} KiB per instance). This may \
+                 indicate a memory leak.",
+                baseline.rss_bytes as f64 / 1024.0 / 1024.0,
+                end.rss_bytes as f64 / 1024.0 / 1024.0,
+                rss_growth_per_instance as f64 / 1024.0,
+            )),
+        });
+    }
+
+    host.callback_error_check()
+        .context("An error occured during a host callback")?;
+    Ok(TestStatus::Success { details: None })
+}
+
+/// The test for `PluginTestCase::InstanceResourceLeaks`.
+#[cfg(not(target_os = "linux"))]
+pub fn test_instance_resource_leaks(
+    _library: &PluginLibrary,
+    _plugin_id: &str,
+    _host_settings: &HostSettings,
+) -> Result<TestStatus> {
+    Ok(TestStatus::Skipped {
+        details: Some(String::from("This test is only supported on Linux.")),
+    })
+}
+
+/// Create an instance of the plugin, initialize it, activate it, process a single buffer of random
+/// audio and note events, and destroy it again.
+fn create_process_destroy(
+    library: &PluginLibrary,
+    plugin_id: &str,
+    host: &Arc<Host>,
+    prng: &mut Pcg32,
+) -> Result<()> {
+    let plugin = library
+        .create_plugin(plugin_id, host.clone())
+        .context("Could not create the plugin instance")?;
+    plugin.init().context("Error during initialization")?;
+
+    let (audio_ports_config, note_ports_config) = query_io_config(&plugin)?;
+    let mut note_event_rng = note_ports_config.map(NoteGenerator::new);
+    host.handle_callbacks_once();
+
+    let (mut input_buffers, mut output_buffers) =
+        audio_ports_config.create_buffers(INSTANCE_LIFECYCLE_BUFFER_SIZE);
+    ProcessingTest::new_out_of_place(&plugin, &mut input_buffers, &mut output_buffers)?.run_once(
+        ProcessConfig::default(),
+        |process_data| {
+            if let Some(note_event_rng) = note_event_rng.as_mut() {
+                note_event_rng.fill_event_queue(
+                    prng,
+                    &process_data.input_events,
+                    INSTANCE_LIFECYCLE_BUFFER_SIZE as u32,
+                )?;
+            }
+            process_data.buffers.randomize(prng);
+
+            Ok(())
+        },
+    )?;
+
+    drop(plugin);
+    host.callback_error_check()
+        .context("An error occured during a host callback")
+}
+
+/// Query the plugin's audio and note port configurations. Neither extension is required, and the
+/// note port configuration is only returned if the plugin has note inputs.
+fn query_io_config(plugin: &Plugin) -> Result<(AudioPortConfig, Option<NotePortConfig>)> {
+    let audio_ports_config = match plugin.get_extension::<AudioPorts>() {
+        Some(audio_ports) => audio_ports
+            .config()
+            .context("Error while querying 'audio-ports' IO configuration")?,
+        None => AudioPortConfig::default(),
+    };
+    let note_ports_config = plugin
+        .get_extension::<NotePorts>()
+        .map(|note_ports| note_ports.config())
+        .transpose()
+        .context("Error while querying 'note-ports' IO configuration")?
+        .filter(|config| !config.inputs.is_empty());
+
+    Ok((audio_ports_config, note_ports_config))
+}
+
+/// Start processing, process [`LIFECYCLE_BLOCKS_PER_RUN`] blocks of random audio and note events
+/// with the specified buffer size, and stop processing again. The output is checked for
+/// consistency after every block.
+fn process_random_blocks(
+    plugin: &PluginAudioThread,
+    audio_ports_config: &AudioPortConfig,
+    mut note_event_rng: Option<&mut NoteGenerator>,
+    prng: &mut Pcg32,
+    sample_rate: f64,
+    buffer_size: usize,
+) -> Result<()> {
+    let (mut input_buffers, mut output_buffers) = audio_ports_config.create_buffers(buffer_size);
+    let mut audio_buffers = AudioBuffers::OutOfPlace(OutOfPlaceAudioBuffers::new(
+        &mut input_buffers,
+        &mut output_buffers,
+    )?);
+    let mut process_data = ProcessData::new(
+        &mut audio_buffers,
+        ProcessConfig {
+            sample_rate,
+            ..ProcessConfig::default()
+        },
+    );
+
+    plugin.start_processing()?;
+    for block_idx in 0..LIFECYCLE_BLOCKS_PER_RUN {
+        if let Some(note_event_rng) = note_event_rng.as_mut() {
+            note_event_rng.fill_event_queue(
+                prng,
+                &process_data.input_events,
+                buffer_size as u32,
+            )?;
+        }
+        process_data.buffers.randomize(prng);
+
+        let original_input_buffers = process_data.buffers.inputs_ref().to_owned();
+        plugin
+            .process(&mut process_data)
+            .context("Error during audio processing")?;
+        check_out_of_place_output_consistency(&process_data, &original_input_buffers)
+            .with_context(|| {
+                format!(
+                    "Failed during processing cycle {} out of {LIFECYCLE_BLOCKS_PER_RUN}",
+                    block_idx + 1
+                )
+            })?;
+
+        process_data.clear_events();
+        process_data.advance_transport(buffer_size as u32);
+    }
+    plugin.stop_processing();
+
+    Ok(())
+}
+
+/// Format a number as an English ordinal, e.g. `2nd`.
+fn ordinal(n: usize) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+
+    format!("{n}{suffix}")
+}
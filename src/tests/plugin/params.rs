@@ -2,19 +2,27 @@
 
 use anyhow::{Context, Result};
 use clap_sys::events::CLAP_EVENT_PARAM_VALUE;
+use clap_sys::ext::params::{
+    CLAP_PARAM_CLEAR_AUTOMATIONS, CLAP_PARAM_CLEAR_MODULATIONS, CLAP_PARAM_RESCAN_ALL,
+};
 use clap_sys::id::clap_id;
 use rand::Rng;
+use rand_pcg::Pcg32;
 use serde::Serialize;
 use std::collections::BTreeMap;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
 
 use super::processing::ProcessingTest;
+use super::state::format_mismatching_values;
 use super::PluginTestCase;
 use crate::plugin::ext::audio_ports::{AudioPortConfig, AudioPorts};
 use crate::plugin::ext::note_ports::NotePorts;
-use crate::plugin::ext::params::Params;
+use crate::plugin::ext::params::{self, Params};
+use crate::plugin::ext::state::State;
 use crate::plugin::ext::Extension;
-use crate::plugin::host::Host;
-use crate::plugin::instance::process::{Event, ProcessConfig};
+use crate::plugin::host::{Host, HostSettings};
+use crate::plugin::instance::process::{Event, EventQueue, ProcessConfig};
 use crate::plugin::library::PluginLibrary;
 use crate::tests::rng::{new_prng, NoteGenerator, ParamFuzzer};
 use crate::tests::{TestCase, TestStatus};
@@ -27,6 +35,11 @@ pub const FUZZ_NUM_PERMUTATIONS: usize = 50;
 /// allows the plugin's state to settle in before moving to the next set of parameter values.
 pub const FUZZ_RUNS_PER_PERMUTATION: usize = 5;
 
+/// The tolerance used when comparing parameter values in the flush test, relative to the
+/// parameter's range. Plugins may store parameter values with a lower precision or in a different
+/// representation, so exact comparisons would be too strict.
+const PARAM_VALUE_TOLERANCE: f64 = 1e-5;
+
 /// The file name we'll use to dump the previous parameter values when a fuzzing test fails.
 const PREVIOUS_PARAM_VALUES_FILE_NAME: &str = "param-values-previous.json";
 /// The file name we'll use to dump the current parameter values when a fuzzing test fails.
@@ -42,10 +55,14 @@ struct ParamValue<'a> {
 }
 
 /// The test for `ProcessingTest::ParamConversions`.
-pub fn test_param_conversions(library: &PluginLibrary, plugin_id: &str) -> Result<TestStatus> {
+pub fn test_param_conversions(
+    library: &PluginLibrary,
+    plugin_id: &str,
+    host_settings: &HostSettings,
+) -> Result<TestStatus> {
     let mut prng = new_prng();
 
-    let host = Host::new();
+    let host = Host::new(host_settings);
     let plugin = library
         .create_plugin(plugin_id, host.clone())
         .context("Could not create the plugin instance")?;
@@ -187,10 +204,14 @@ pub fn test_param_conversions(library: &PluginLibrary, plugin_id: &str) -> Resul
 }
 
 /// The test for `ProcessingTest::ParamFuzzBasic`.
-pub fn test_param_fuzz_basic(library: &PluginLibrary, plugin_id: &str) -> Result<TestStatus> {
+pub fn test_param_fuzz_basic(
+    library: &PluginLibrary,
+    plugin_id: &str,
+    host_settings: &HostSettings,
+) -> Result<TestStatus> {
     let mut prng = new_prng();
 
-    let host = Host::new();
+    let host = Host::new(host_settings);
     let plugin = library
         .create_plugin(plugin_id, host.clone())
         .context("Could not create the plugin instance")?;
@@ -332,10 +353,11 @@ pub fn test_param_fuzz_basic(library: &PluginLibrary, plugin_id: &str) -> Result
 pub fn test_param_set_wrong_namespace(
     library: &PluginLibrary,
     plugin_id: &str,
+    host_settings: &HostSettings,
 ) -> Result<TestStatus> {
     let mut prng = new_prng();
 
-    let host = Host::new();
+    let host = Host::new(host_settings);
     let plugin = library
         .create_plugin(plugin_id, host.clone())
         .context("Could not create the plugin instance")?;
@@ -414,3 +436,386 @@ pub fn test_param_set_wrong_namespace(
         })
     }
 }
+
+/// The test for `ProcessingTest::ParamRescan`.
+pub fn test_param_rescan(
+    library: &PluginLibrary,
+    plugin_id: &str,
+    host_settings: &HostSettings,
+) -> Result<TestStatus> {
+    let mut prng = new_prng();
+
+    let host = Host::new(host_settings);
+    let plugin = library
+        .create_plugin(plugin_id, host.clone())
+        .context("Could not create the plugin instance")?;
+    plugin.init().context("Error during initialization")?;
+
+    let params = match plugin.get_extension::<Params>() {
+        Some(params) => params,
+        None => {
+            return Ok(TestStatus::Skipped {
+                details: Some(format!(
+                    "The plugin does not implement the '{}' extension.",
+                    Params::EXTENSION_ID.to_str().unwrap(),
+                )),
+            })
+        }
+    };
+    let state = match plugin.get_extension::<State>() {
+        Some(state) => state,
+        None => {
+            return Ok(TestStatus::Skipped {
+                details: Some(format!(
+                    "The plugin does not implement the '{}' extension.",
+                    State::EXTENSION_ID.to_str().unwrap()
+                )),
+            })
+        }
+    };
+    host.handle_callbacks_once();
+
+    let random_state = save_random_state(library, plugin_id, &host, &mut prng)?;
+
+    // The host checks the changes whenever the plugin calls `clap_host_params::rescan()`. This
+    // test additionally makes sure the parameters don't change without such a call.
+    let initial_state = state.save()?;
+    let initial_param_infos = params
+        .info()
+        .context("Failure while fetching the plugin's parameters")?;
+    plugin.state.param_rescan_flags.store(0, Ordering::SeqCst);
+    state.load(&random_state)?;
+    host.handle_callbacks_once();
+    host.callback_error_check()
+        .context("An error occured during a host callback")?;
+
+    let loaded_param_infos = params
+        .info()
+        .context("Failure while fetching the plugin's parameters after loading the state")?;
+    params::check_rescan_changes(
+        plugin.state.param_rescan_flags.load(Ordering::SeqCst),
+        &initial_param_infos,
+        &loaded_param_infos,
+    )
+    .context("Loading a state while the plugin was deactivated changed its parameters")?;
+
+    // While the plugin is activated it may not add or remove parameters. It should instead request
+    // a restart and wait until it has been deactivated.
+    plugin.activate(44_100.0, 1, BUFFER_SIZE)?;
+    host.handle_callbacks_once();
+
+    plugin.state.param_rescan_flags.store(0, Ordering::SeqCst);
+    state.load(&initial_state)?;
+    host.handle_callbacks_once();
+    host.callback_error_check()
+        .context("An error occured during a host callback")?;
+
+    let activated_param_infos = params
+        .info()
+        .context("Failure while fetching the plugin's parameters after loading the state")?;
+    params::check_rescan_changes(
+        plugin.state.param_rescan_flags.load(Ordering::SeqCst) & !CLAP_PARAM_RESCAN_ALL,
+        &loaded_param_infos,
+        &activated_param_infos,
+    )
+    .context("Loading a state while the plugin was activated changed its parameters")?;
+
+    plugin.deactivate();
+    host.handle_callbacks_once();
+
+    host.callback_error_check()
+        .context("An error occured during a host callback")?;
+
+    Ok(TestStatus::Success { details: None })
+}
+
+/// The test for `PluginTestCase::ParamFlush`.
+pub fn test_param_flush(
+    library: &PluginLibrary,
+    plugin_id: &str,
+    host_settings: &HostSettings,
+) -> Result<TestStatus> {
+    let mut prng = new_prng();
+
+    let host = Host::new(host_settings);
+    let plugin = library
+        .create_plugin(plugin_id, host.clone())
+        .context("Could not create the plugin instance")?;
+    plugin.init().context("Error during initialization")?;
+
+    let params = match plugin.get_extension::<Params>() {
+        Some(params) => params,
+        None => {
+            return Ok(TestStatus::Skipped {
+                details: Some(format!(
+                    "The plugin does not implement the '{}' extension.",
+                    Params::EXTENSION_ID.to_str().unwrap(),
+                )),
+            })
+        }
+    };
+    host.handle_callbacks_once();
+
+    let param_infos = params
+        .info()
+        .context("Failure while fetching the plugin's parameters")?;
+    if param_infos.is_empty() {
+        return Ok(TestStatus::Skipped {
+            details: Some(String::from("The plugin does not have any parameters.")),
+        });
+    }
+
+    let initial_param_values: BTreeMap<clap_id, f64> = param_infos
+        .keys()
+        .map(|param_id| params.get(*param_id).map(|value| (*param_id, value)))
+        .collect::<Result<BTreeMap<clap_id, f64>>>()?;
+
+    // The parameter changes are sent through the same path the host uses to handle
+    // `clap_host_params::request_flush()`. The plugin may also request another flush in response,
+    // in which case that flush is performed when handling the callbacks.
+    let param_fuzzer = ParamFuzzer::new(&param_infos);
+    let random_param_set_events: Vec<Event> =
+        param_fuzzer.randomize_params_at(&mut prng, 0).collect();
+    if random_param_set_events.is_empty() {
+        return Ok(TestStatus::Skipped {
+            details: Some(String::from(
+                "The plugin does not have any automatable parameters.",
+            )),
+        });
+    }
+
+    // Values that happen to match the parameter's current value can't be distinguished from the
+    // plugin ignoring the event, so those are not checked
+    let expected_param_values: BTreeMap<clap_id, f64> = random_param_set_events
+        .iter()
+        .filter_map(|event| match event {
+            Event::ParamValue(event)
+                if !param_values_match(
+                    &param_infos[&event.param_id],
+                    event.value,
+                    initial_param_values[&event.param_id],
+                ) =>
+            {
+                Some((event.param_id, event.value))
+            }
+            Event::ParamValue(_) => None,
+            event => panic!("Unexpected event {event:?}, this is a clap-validator bug"),
+        })
+        .collect();
+
+    let input_events = EventQueue::new_input();
+    *input_events.events.lock() = random_param_set_events;
+    plugin.state.requested_flush.store(false, Ordering::SeqCst);
+    plugin
+        .state
+        .flush_params(&input_events)
+        .context("Error while flushing the plugin's parameters")?;
+    let requested_flush = plugin.state.requested_flush.load(Ordering::SeqCst);
+    host.handle_callbacks_once();
+    host.callback_error_check()
+        .context("An error occured during a host callback")?;
+
+    let actual_param_values: BTreeMap<clap_id, f64> = expected_param_values
+        .keys()
+        .map(|param_id| params.get(*param_id).map(|value| (*param_id, value)))
+        .collect::<Result<BTreeMap<clap_id, f64>>>()?;
+    let mismatching_param_values: BTreeMap<clap_id, f64> = actual_param_values
+        .into_iter()
+        .filter(|(param_id, actual_value)| {
+            !param_values_match(
+                &param_infos[param_id],
+                expected_param_values[param_id],
+                *actual_value,
+            )
+        })
+        .collect();
+    if !mismatching_param_values.is_empty() && !requested_flush {
+        return Ok(TestStatus::Failed {
+            details: Some(format!(
+                "'clap_plugin_params::flush()' has been called with random parameter values, but \
+                 the plugin's reported parameter values do not match those values and it did not \
+                 call 'clap_host_params::request_flush()'. The mismatching values are {}.",
+                format_mismatching_values(
+                    mismatching_param_values,
+                    &expected_param_values,
+                    &param_infos
+                )
+            )),
+        });
+    }
+
+    Ok(TestStatus::Success { details: None })
+}
+
+/// Check whether two values for a parameter are equal. Stepped parameters are rounded to the
+/// nearest step first, and other values may differ by [`PARAM_VALUE_TOLERANCE`] times the
+/// parameter's range.
+fn param_values_match(param: &params::Param, expected: f64, actual: f64) -> bool {
+    if param.stepped() {
+        expected.round() == actual.round()
+    } else {
+        let range = param.range.end() - param.range.start();
+        (expected - actual).abs() <= range * PARAM_VALUE_TOLERANCE
+    }
+}
+
+/// The test for `PluginTestCase::ParamClear`.
+pub fn test_param_clear(
+    library: &PluginLibrary,
+    plugin_id: &str,
+    host_settings: &HostSettings,
+) -> Result<TestStatus> {
+    let mut prng = new_prng();
+
+    let host = Host::new(host_settings);
+    let plugin = library
+        .create_plugin(plugin_id, host.clone())
+        .context("Could not create the plugin instance")?;
+    plugin.init().context("Error during initialization")?;
+
+    let params = match plugin.get_extension::<Params>() {
+        Some(params) => params,
+        None => {
+            return Ok(TestStatus::Skipped {
+                details: Some(format!(
+                    "The plugin does not implement the '{}' extension.",
+                    Params::EXTENSION_ID.to_str().unwrap(),
+                )),
+            })
+        }
+    };
+    let state = match plugin.get_extension::<State>() {
+        Some(state) => state,
+        None => {
+            return Ok(TestStatus::Skipped {
+                details: Some(format!(
+                    "The plugin does not implement the '{}' extension.",
+                    State::EXTENSION_ID.to_str().unwrap()
+                )),
+            })
+        }
+    };
+    host.handle_callbacks_once();
+
+    let random_state = save_random_state(library, plugin_id, &host, &mut prng)?;
+    let initial_state = state.save()?;
+    let mut param_infos = params
+        .info()
+        .context("Failure while fetching the plugin's parameters")?;
+
+    // The host pretends to have automation lanes and modulation for every parameter. Loading the
+    // random state and then the initial state again gives plugins with a dynamic parameter list
+    // the chance to remove parameters, at which point those references need to be cleared.
+    let all_references = CLAP_PARAM_CLEAR_AUTOMATIONS | CLAP_PARAM_CLEAR_MODULATIONS;
+    let mut removed_param_ids = Vec::new();
+    for (state_name, state_data) in [("random", &random_state), ("initial", &initial_state)] {
+        plugin.state.param_references.lock().extend(
+            param_infos
+                .keys()
+                .map(|param_id| (*param_id, all_references)),
+        );
+
+        state.load(state_data)?;
+        host.handle_callbacks_once();
+        host.callback_error_check()
+            .context("An error occured during a host callback")?;
+
+        let new_param_infos = params.info().with_context(|| {
+            format!(
+                "Failure while fetching the plugin's parameters after loading the {state_name} \
+                 state"
+            )
+        })?;
+        let param_references = plugin.state.param_references.lock();
+        for param_id in param_infos
+            .keys()
+            .filter(|param_id| !new_param_infos.contains_key(param_id))
+        {
+            if let Some(references) = param_references.get(param_id) {
+                let mut reference_kinds = Vec::new();
+                if (references & CLAP_PARAM_CLEAR_AUTOMATIONS) != 0 {
+                    reference_kinds.push("automation");
+                }
+                if (references & CLAP_PARAM_CLEAR_MODULATIONS) != 0 {
+                    reference_kinds.push("modulation");
+                }
+
+                return Ok(TestStatus::Failed {
+                    details: Some(format!(
+                        "Loading the {state_name} state removed the parameter with ID {param_id}, \
+                         but the plugin did not call 'clap_host_params::clear()' to clear the \
+                         host's {} references to it.",
+                        reference_kinds.join(" and ")
+                    )),
+                });
+            }
+
+            removed_param_ids.push(*param_id);
+        }
+        drop(param_references);
+
+        param_infos = new_param_infos;
+    }
+
+    if removed_param_ids.is_empty() {
+        Ok(TestStatus::Skipped {
+            details: Some(String::from(
+                "Loading a state did not cause the plugin to remove any of its parameters.",
+            )),
+        })
+    } else {
+        Ok(TestStatus::Success { details: None })
+    }
+}
+
+/// Save a state with random parameter values using a separate instance of the plugin, so the
+/// parameter values actually change when another instance loads the state. The plugin needs to
+/// support both the `params` and the `state` extensions.
+fn save_random_state(
+    library: &PluginLibrary,
+    plugin_id: &str,
+    host: &Arc<Host>,
+    prng: &mut Pcg32,
+) -> Result<Vec<u8>> {
+    let plugin = library
+        .create_plugin(plugin_id, host.clone())
+        .context("Could not create a second plugin instance")?;
+    plugin
+        .init()
+        .context("Error while initializing the second plugin instance")?;
+
+    let audio_ports_config = match plugin.get_extension::<AudioPorts>() {
+        Some(audio_ports) => audio_ports
+            .config()
+            .context("Error while querying 'audio-ports' IO configuration")?,
+        None => AudioPortConfig::default(),
+    };
+    let params = plugin
+        .get_extension::<Params>()
+        .context("The second plugin instance does not implement the 'params' extension")?;
+    let state = plugin
+        .get_extension::<State>()
+        .context("The second plugin instance does not implement the 'state' extension")?;
+    host.handle_callbacks_once();
+
+    let param_infos = params
+        .info()
+        .context("Failure while fetching the plugin's parameters")?;
+    let param_fuzzer = ParamFuzzer::new(&param_infos);
+    let random_param_set_events: Vec<_> = param_fuzzer.randomize_params_at(prng, 0).collect();
+
+    let (mut input_buffers, mut output_buffers) = audio_ports_config.create_buffers(BUFFER_SIZE);
+    ProcessingTest::new_out_of_place(&plugin, &mut input_buffers, &mut output_buffers)?.run_once(
+        ProcessConfig::default(),
+        move |process_data| {
+            *process_data.input_events.events.lock() = random_param_set_events;
+
+            Ok(())
+        },
+    )?;
+
+    let random_state = state.save()?;
+    host.handle_callbacks_once();
+
+    Ok(random_state)
+}
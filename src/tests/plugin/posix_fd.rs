@@ -0,0 +1,108 @@
+//! Tests involving the file descriptors registered through `clap_host_posix_fd_support`.
+
+#[cfg(target_os = "linux")]
+use anyhow::Context;
+use anyhow::Result;
+#[cfg(target_os = "linux")]
+use std::sync::atomic::Ordering;
+
+#[cfg(target_os = "linux")]
+use super::processing::ProcessingTest;
+#[cfg(target_os = "linux")]
+use crate::plugin::ext::audio_ports::{AudioPortConfig, AudioPorts};
+#[cfg(target_os = "linux")]
+use crate::plugin::host::Host;
+use crate::plugin::host::HostSettings;
+#[cfg(target_os = "linux")]
+use crate::plugin::instance::process::ProcessConfig;
+use crate::plugin::library::PluginLibrary;
+use crate::tests::TestStatus;
+
+/// The buffer size used for the posix fd support tests.
+#[cfg(target_os = "linux")]
+const BUFFER_SIZE: usize = 512;
+
+/// The test for `PluginTestCase::PosixFdUnregister`. The plugin needs to unregister all of its file
+/// descriptors before `clap_plugin::destroy()` returns.
+#[cfg(target_os = "linux")]
+pub fn test_posix_fd_unregister(
+    library: &PluginLibrary,
+    plugin_id: &str,
+    host_settings: &HostSettings,
+) -> Result<TestStatus> {
+    let host = Host::new(host_settings);
+    let plugin = library
+        .create_plugin(plugin_id, host.clone())
+        .context("Could not create the plugin instance")?;
+    plugin.init().context("Error during initialization")?;
+
+    let audio_ports_config = match plugin.get_extension::<AudioPorts>() {
+        Some(audio_ports) => audio_ports
+            .config()
+            .context("Error while querying 'audio-ports' IO configuration")?,
+        None => AudioPortConfig::default(),
+    };
+    host.handle_callbacks_once();
+
+    // Some plugins only register their file descriptors once they're activated. The file
+    // descriptors are polled on the main thread while the audio thread is processing audio.
+    let (mut input_buffers, mut output_buffers) = audio_ports_config.create_buffers(BUFFER_SIZE);
+    ProcessingTest::new_out_of_place(&plugin, &mut input_buffers, &mut output_buffers)?
+        .run_once(ProcessConfig::default(), |_| Ok(()))?;
+
+    host.callback_error_check()
+        .context("An error occured during a host callback")?;
+
+    // The instance's state outlives the plugin, so we can still check the file descriptors after
+    // the plugin has been destroyed
+    let instance_state = plugin.state.clone();
+    drop(plugin);
+
+    host.callback_error_check()
+        .context("An error occured during a host callback")?;
+
+    if instance_state
+        .num_registered_posix_fds
+        .load(Ordering::SeqCst)
+        == 0
+    {
+        return Ok(TestStatus::Skipped {
+            details: Some(String::from(
+                "The plugin did not register any file descriptors.",
+            )),
+        });
+    }
+
+    let remaining_fds: Vec<String> = instance_state
+        .posix_fds
+        .lock()
+        .keys()
+        .map(|fd| fd.to_string())
+        .collect();
+    if !remaining_fds.is_empty() {
+        anyhow::bail!(
+            "The plugin did not unregister the following file descriptors before \
+             'clap_plugin::destroy()' returned: {}",
+            remaining_fds.join(", ")
+        );
+    }
+
+    Ok(TestStatus::Success {
+        details: Some(format!(
+            "'clap_plugin_posix_fd_support::on_fd()' was called {} time(s).",
+            instance_state.num_posix_fd_calls.load(Ordering::SeqCst)
+        )),
+    })
+}
+
+/// The test for `PluginTestCase::PosixFdUnregister`.
+#[cfg(not(target_os = "linux"))]
+pub fn test_posix_fd_unregister(
+    _library: &PluginLibrary,
+    _plugin_id: &str,
+    _host_settings: &HostSettings,
+) -> Result<TestStatus> {
+    Ok(TestStatus::Skipped {
+        details: Some(String::from("This test is only supported on Linux.")),
+    })
+}
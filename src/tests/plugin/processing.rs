@@ -7,7 +7,8 @@ use anyhow::{Context, Result};
 use crate::plugin::ext::audio_ports::{AudioPortConfig, AudioPorts};
 use crate::plugin::ext::note_ports::NotePorts;
 use crate::plugin::ext::Extension;
-use crate::plugin::host::Host;
+use crate::plugin::host::{Host, HostSettings};
+use crate::plugin::instance::audio_thread::ProcessStatus;
 use crate::plugin::instance::process::{
     AudioBuffers, OutOfPlaceAudioBuffers, ProcessConfig, ProcessData,
 };
@@ -22,6 +23,27 @@ pub struct ProcessingTest<'a> {
     audio_buffers: AudioBuffers<'a>,
 }
 
+/// Statistics about the plugin's sleep behavior collected by
+/// [`ProcessingTest::run()`][ProcessingTest::run()].
+#[derive(Debug, Default)]
+pub struct SleepStats {
+    /// The number of times the plugin returned `CLAP_PROCESS_SLEEP`.
+    pub num_sleeps: usize,
+    /// The number of processing cycles that were skipped because the plugin was sleeping.
+    pub num_skipped_cycles: usize,
+    /// The number of times a sleeping plugin was woken up after calling
+    /// `clap_host::request_process()`.
+    pub num_requested_wakeups: usize,
+    /// The number of times the plugin called `clap_host::request_process()` during a processing
+    /// cycle and then returned `CLAP_PROCESS_SLEEP` from that same cycle. These requests are
+    /// discarded.
+    pub num_ignored_requests: usize,
+    /// The number of times the plugin called `clap_host::request_process()` while it was sleeping,
+    /// but the host did not wake it up on the next processing cycle. This should always be zero,
+    /// and anything else indicates a bug in [`ProcessingTest::run()`].
+    pub num_missed_wakeups: usize,
+}
+
 impl<'a> ProcessingTest<'a> {
     /// Construct a new processing test using out-of-place processing. This allocates the CLAP audio
     /// buffer structs needed for the test. Returns an error if the the inner vectors don't all have
@@ -48,12 +70,19 @@ impl<'a> ProcessingTest<'a> {
     ///
     /// Main-thread callbacks that were made to the plugin while the audio thread was active are
     /// handled implicitly.
+    ///
+    /// Like a real host, this stops calling the process function after the plugin returns
+    /// `CLAP_PROCESS_SLEEP`. Processing is resumed once there are input events, the input audio is
+    /// no longer silent, or the plugin calls `clap_host::request_process()` while it is sleeping.
+    /// Requests made during the cycle that returned `CLAP_PROCESS_SLEEP` are discarded. The skipped
+    /// cycles still count towards `num_iters`, and restart requests are still handled during them.
+    /// The returned [`SleepStats`] describe how often this happened.
     pub fn run<Preprocess>(
         &'a mut self,
         num_iters: usize,
         process_config: ProcessConfig,
         mut preprocess: Preprocess,
-    ) -> Result<()>
+    ) -> Result<SleepStats>
     where
         Preprocess: FnMut(&mut ProcessData) -> Result<()> + Send,
     {
@@ -61,6 +90,10 @@ impl<'a> ProcessingTest<'a> {
             .state
             .requested_restart
             .store(false, Ordering::SeqCst);
+        self.plugin
+            .state
+            .requested_process
+            .store(false, Ordering::SeqCst);
 
         let buffer_size = self.audio_buffers.len();
         let mut process_data = ProcessData::new(&mut self.audio_buffers, process_config);
@@ -69,6 +102,7 @@ impl<'a> ProcessingTest<'a> {
         // stopped, deactivated, reactivated, and started again. Because of that, we need to keep
         // track of the number of processed iterations manually instead of using a for loop.
         let mut iters_done = 0;
+        let mut sleep_stats = SleepStats::default();
         while iters_done < num_iters {
             self.plugin
                 .activate(process_config.sample_rate, 1, buffer_size)?;
@@ -76,6 +110,12 @@ impl<'a> ProcessingTest<'a> {
             self.plugin.on_audio_thread(|plugin| -> Result<()> {
                 plugin.start_processing()?;
 
+                // Set when the plugin returns `CLAP_PROCESS_SLEEP`. Restarting the plugin also
+                // wakes it up again. The number of process requests at that point is used to tell
+                // whether the plugin called `clap_host::request_process()` while sleeping.
+                let mut sleeping = false;
+                let mut num_process_requests_before_sleep = 0;
+
                 // This test can be repeated a couple of times
                 // NOTE: We intentionally do not disable denormals here
                 'processing: while iters_done < num_iters {
@@ -83,28 +123,77 @@ impl<'a> ProcessingTest<'a> {
 
                     preprocess(&mut process_data)?;
 
-                    // We'll check that the plugin hasn't modified the input buffers after the
-                    // test
-                    let original_input_buffers = process_data.buffers.inputs_ref().to_owned();
-
-                    plugin
-                        .process(&mut process_data)
-                        .context("Error during audio processing")?;
+                    if sleeping {
+                        // The counter is incremented after the flag is set, so a request counted
+                        // here must also be visible through the flag
+                        let requested_while_sleeping =
+                            plugin.state().num_process_requests.load(Ordering::SeqCst)
+                                != num_process_requests_before_sleep;
+                        let requested_process = plugin
+                            .state()
+                            .requested_process
+                            .swap(false, Ordering::SeqCst);
+                        if requested_while_sleeping && !requested_process {
+                            sleep_stats.num_missed_wakeups += 1;
+                        }
+
+                        if requested_process {
+                            log::trace!(
+                                "Waking up the plugin during processing cycle {} out of {} after \
+                                 a call to 'clap_host::request_process()'",
+                                iters_done,
+                                num_iters
+                            );
+                            sleep_stats.num_requested_wakeups += 1;
+                            sleeping = false;
+                        } else if has_input(&process_data) {
+                            sleeping = false;
+                        }
+                    }
 
-                    // When we add in-place processing this will need some slightly different checks
-                    match process_data.buffers {
-                        AudioBuffers::OutOfPlace(_) => check_out_of_place_output_consistency(
-                            &process_data,
-                            &original_input_buffers,
-                        ),
+                    if sleeping {
+                        sleep_stats.num_skipped_cycles += 1;
+                    } else {
+                        // We'll check that the plugin hasn't modified the input buffers after the
+                        // test
+                        let original_input_buffers = process_data.buffers.inputs_ref().to_owned();
+
+                        let status = plugin
+                            .process(&mut process_data)
+                            .context("Error during audio processing")?;
+                        if let ProcessStatus::Sleep = status {
+                            // A request made during this cycle contradicts the plugin's request to
+                            // sleep, so it should not immediately wake the plugin up again
+                            if plugin
+                                .state()
+                                .requested_process
+                                .swap(false, Ordering::SeqCst)
+                            {
+                                sleep_stats.num_ignored_requests += 1;
+                            }
+
+                            sleep_stats.num_sleeps += 1;
+                            sleeping = true;
+                            num_process_requests_before_sleep =
+                                plugin.state().num_process_requests.load(Ordering::SeqCst);
+                        }
+
+                        // When we add in-place processing this will need some slightly different
+                        // checks
+                        match process_data.buffers {
+                            AudioBuffers::OutOfPlace(_) => check_out_of_place_output_consistency(
+                                &process_data,
+                                &original_input_buffers,
+                            ),
+                        }
+                        .with_context(|| {
+                            format!(
+                                "Failed during processing cycle {} out of {}",
+                                iters_done + 1,
+                                num_iters
+                            )
+                        })?;
                     }
-                    .with_context(|| {
-                        format!(
-                            "Failed during processing cycle {} out of {}",
-                            iters_done + 1,
-                            num_iters
-                        )
-                    })?;
 
                     process_data.clear_events();
                     process_data.advance_transport(buffer_size as u32);
@@ -122,6 +211,13 @@ impl<'a> ProcessingTest<'a> {
                             iters_done + 1,
                             num_iters
                         );
+
+                        // The restart already wakes up the plugin, so a pending process request
+                        // should not be counted against the plugin after the restart
+                        plugin
+                            .state()
+                            .requested_process
+                            .store(false, Ordering::SeqCst);
                         break 'processing;
                     }
                 }
@@ -137,7 +233,7 @@ impl<'a> ProcessingTest<'a> {
         // Handle callbacks the plugin may have made during deactivate
         self.plugin.host().handle_callbacks_once();
 
-        Ok(())
+        Ok(sleep_stats)
     }
 
     /// Run the standard audio processing test for a still **deactivated** plugin. This is identical
@@ -207,10 +303,11 @@ impl<'a> ProcessingTest<'a> {
 pub fn test_process_audio_out_of_place_basic(
     library: &PluginLibrary,
     plugin_id: &str,
+    host_settings: &HostSettings,
 ) -> Result<TestStatus> {
     let mut prng = new_prng();
 
-    let host = Host::new();
+    let host = Host::new(host_settings);
     let plugin = library
         .create_plugin(plugin_id, host.clone())
         .context("Could not create the plugin instance")?;
@@ -257,10 +354,11 @@ pub fn test_process_audio_out_of_place_basic(
 pub fn test_process_note_out_of_place_basic(
     library: &PluginLibrary,
     plugin_id: &str,
+    host_settings: &HostSettings,
 ) -> Result<TestStatus> {
     let mut prng = new_prng();
 
-    let host = Host::new();
+    let host = Host::new(host_settings);
     let plugin = library
         .create_plugin(plugin_id, host.clone())
         .context("Could not create the plugin instance")?;
@@ -329,10 +427,11 @@ pub fn test_process_note_out_of_place_basic(
 pub fn test_process_note_inconsistent(
     library: &PluginLibrary,
     plugin_id: &str,
+    host_settings: &HostSettings,
 ) -> Result<TestStatus> {
     let mut prng = new_prng();
 
-    let host = Host::new();
+    let host = Host::new(host_settings);
     let plugin = library
         .create_plugin(plugin_id, host.clone())
         .context("Could not create the plugin instance")?;
@@ -394,10 +493,95 @@ pub fn test_process_note_inconsistent(
     Ok(TestStatus::Success { details: None })
 }
 
+/// The test for `ProcessingTest::ProcessSleep`. This processes silence without any input events,
+/// so the plugin is free to go to sleep. The processing loop will then only wake the plugin up
+/// again after it calls `clap_host::request_process()`.
+pub fn test_process_sleep(
+    library: &PluginLibrary,
+    plugin_id: &str,
+    host_settings: &HostSettings,
+) -> Result<TestStatus> {
+    let host = Host::new(host_settings);
+    let plugin = library
+        .create_plugin(plugin_id, host.clone())
+        .context("Could not create the plugin instance")?;
+    plugin.init().context("Error during initialization")?;
+
+    let audio_ports_config = match plugin.get_extension::<AudioPorts>() {
+        Some(audio_ports) => audio_ports
+            .config()
+            .context("Error while querying 'audio-ports' IO configuration")?,
+        None => AudioPortConfig::default(),
+    };
+    host.handle_callbacks_once();
+
+    // The buffers are zero-initialized and they're never randomized, so the plugin only ever
+    // receives silence
+    let (mut input_buffers, mut output_buffers) = audio_ports_config.create_buffers(512);
+    let sleep_stats = ProcessingTest::new_out_of_place(
+        &plugin,
+        &mut input_buffers,
+        &mut output_buffers,
+    )?
+    .run(20, ProcessConfig::default(), |_| Ok(()))?;
+
+    host.callback_error_check()
+        .context("An error occured during a host callback")?;
+
+    if sleep_stats.num_missed_wakeups > 0 {
+        anyhow::bail!(
+            "The plugin called 'clap_host::request_process()' while it was sleeping {} time(s) \
+             without being woken up on the next processing cycle. This is a clap-validator bug.",
+            sleep_stats.num_missed_wakeups
+        );
+    }
+    if sleep_stats.num_sleeps == 0 {
+        return Ok(TestStatus::Skipped {
+            details: Some(String::from(
+                "The plugin never returned 'CLAP_PROCESS_SLEEP' while processing silence.",
+            )),
+        });
+    }
+    if sleep_stats.num_ignored_requests > 0 {
+        return Ok(TestStatus::Warning {
+            details: Some(format!(
+                "The plugin called 'clap_host::request_process()' and then returned \
+                 'CLAP_PROCESS_SLEEP' from the same processing cycle {} time(s). These requests \
+                 are not honoured since the plugin asked to be put to sleep. A plugin that needs \
+                 to keep processing should return 'CLAP_PROCESS_CONTINUE' instead.",
+                sleep_stats.num_ignored_requests
+            )),
+        });
+    }
+
+    Ok(TestStatus::Success {
+        details: Some(format!(
+            "The plugin went to sleep {} time(s), {} processing cycle(s) were skipped, and the \
+             plugin was woken up {} time(s) after calling 'clap_host::request_process()'.",
+            sleep_stats.num_sleeps,
+            sleep_stats.num_skipped_cycles,
+            sleep_stats.num_requested_wakeups
+        )),
+    })
+}
+
+/// Whether the next processing cycle contains input events or non-silent input audio. A sleeping
+/// plugin should be woken up in that case.
+fn has_input(process_data: &ProcessData) -> bool {
+    !process_data.input_events.events.lock().is_empty()
+        || process_data
+            .buffers
+            .inputs_ref()
+            .iter()
+            .flatten()
+            .flatten()
+            .any(|sample| *sample != 0.0)
+}
+
 /// The process for consistency. This verifies that the output buffer doesn't contain any NaN,
 /// infinite, or denormal values, that the input buffers have not been modified by the plugin, and
 /// that the output event queue is monotonically ordered.
-fn check_out_of_place_output_consistency(
+pub fn check_out_of_place_output_consistency(
     process_data: &ProcessData,
     original_input_buffers: &[Vec<Vec<f32>>],
 ) -> Result<()> {
@@ -430,25 +614,7 @@ fn check_out_of_place_output_consistency(
     }
 
     // If the plugin output any events, then they should be in a monotonically increasing order
-    let mut last_event_time = 0;
-    for event in process_data.output_events.events.lock().iter() {
-        let event_time = event.header().time;
-        if event_time < last_event_time {
-            anyhow::bail!(
-                "The plugin output an event for sample {event_time} after it had previously \
-                 output an event for sample {last_event_time}."
-            )
-        }
-
-        last_event_time = event_time;
-    }
-
-    if last_event_time >= num_samples {
-        anyhow::bail!(
-            "The plugin output an event for sample {last_event_time} but the audio buffer only \
-             contains {num_samples} samples."
-        )
-    }
-
-    Ok(())
+    process_data
+        .output_events
+        .check_output_order(Some(num_samples))
 }
@@ -9,7 +9,7 @@ use crate::plugin::ext::audio_ports::{AudioPortConfig, AudioPorts};
 use crate::plugin::ext::params::{ParamInfo, Params};
 use crate::plugin::ext::state::State;
 use crate::plugin::ext::Extension;
-use crate::plugin::host::Host;
+use crate::plugin::host::{Host, HostSettings};
 use crate::plugin::instance::process::{Event, EventQueue, ProcessConfig};
 use crate::plugin::library::PluginLibrary;
 use crate::tests::rng::{new_prng, ParamFuzzer};
@@ -24,8 +24,12 @@ const EXPECTED_STATE_FILE_NAME: &str = "state-expected";
 const ACTUAL_STATE_FILE_NAME: &str = "state-actual";
 
 /// The test for `PluginTestCase::StateInvalid`.
-pub fn test_state_invalid(library: &PluginLibrary, plugin_id: &str) -> Result<TestStatus> {
-    let host = Host::new();
+pub fn test_state_invalid(
+    library: &PluginLibrary,
+    plugin_id: &str,
+    host_settings: &HostSettings,
+) -> Result<TestStatus> {
+    let host = Host::new(host_settings);
     let plugin = library
         .create_plugin(plugin_id, host.clone())
         .context("Could not create the plugin instance")?;
@@ -70,10 +74,11 @@ pub fn test_state_reproducibility_null_cookies(
     library: &PluginLibrary,
     plugin_id: &str,
     zero_out_cookies: bool,
+    host_settings: &HostSettings,
 ) -> Result<TestStatus> {
     let mut prng = new_prng();
 
-    let host = Host::new();
+    let host = Host::new(host_settings);
     let plugin = library
         .create_plugin(plugin_id, host.clone())
         .context("Could not create the plugin instance")?;
@@ -249,10 +254,11 @@ pub fn test_state_reproducibility_null_cookies(
 pub fn test_state_reproducibility_flush(
     library: &PluginLibrary,
     plugin_id: &str,
+    host_settings: &HostSettings,
 ) -> Result<TestStatus> {
     let mut prng = new_prng();
 
-    let host = Host::new();
+    let host = Host::new(host_settings);
     let plugin = library
         .create_plugin(plugin_id, host.clone())
         .context("Could not create the plugin instance")?;
@@ -456,10 +462,14 @@ pub fn test_state_reproducibility_flush(
 }
 
 /// The test for `PluginTestCase::StateBufferedStreams`.
-pub fn test_state_buffered_streams(library: &PluginLibrary, plugin_id: &str) -> Result<TestStatus> {
+pub fn test_state_buffered_streams(
+    library: &PluginLibrary,
+    plugin_id: &str,
+    host_settings: &HostSettings,
+) -> Result<TestStatus> {
     let mut prng = new_prng();
 
-    let host = Host::new();
+    let host = Host::new(host_settings);
     let plugin = library
         .create_plugin(plugin_id, host.clone())
         .context("Could not create the plugin instance")?;
@@ -621,7 +631,7 @@ pub fn test_state_buffered_streams(library: &PluginLibrary, plugin_id: &str) ->
 ///
 /// If the parameters in `actual_param_values` don't have corresponding entries in
 /// `expected_param_values` and `param_infos`.
-fn format_mismatching_values(
+pub(super) fn format_mismatching_values(
     actual_param_values: BTreeMap<clap_id, f64>,
     expected_param_values: &BTreeMap<clap_id, f64>,
     param_infos: &ParamInfo,
@@ -643,3 +653,106 @@ fn format_mismatching_values(
         .collect::<Vec<String>>()
         .join(", ")
 }
+
+/// The test for `PluginTestCase::StateMarkDirty`. Parameter changes made by the host already imply
+/// that the plugin's state has changed, so the plugin should not keep calling
+/// `clap_host_state::mark_dirty()` in response to them.
+pub fn test_state_mark_dirty(
+    library: &PluginLibrary,
+    plugin_id: &str,
+    host_settings: &HostSettings,
+) -> Result<TestStatus> {
+    const NUM_ITERS: usize = 10;
+
+    let mut prng = new_prng();
+
+    let host = Host::new(host_settings);
+    let plugin = library
+        .create_plugin(plugin_id, host.clone())
+        .context("Could not create the plugin instance")?;
+    plugin.init().context("Error during initialization")?;
+
+    let audio_ports_config = match plugin.get_extension::<AudioPorts>() {
+        Some(audio_ports) => audio_ports
+            .config()
+            .context("Error while querying 'audio-ports' IO configuration")?,
+        None => AudioPortConfig::default(),
+    };
+    let params = match plugin.get_extension::<Params>() {
+        Some(params) => params,
+        None => {
+            return Ok(TestStatus::Skipped {
+                details: Some(format!(
+                    "The plugin does not implement the '{}' extension.",
+                    Params::EXTENSION_ID.to_str().unwrap(),
+                )),
+            })
+        }
+    };
+    if plugin.get_extension::<State>().is_none() {
+        return Ok(TestStatus::Skipped {
+            details: Some(format!(
+                "The plugin does not implement the '{}' extension.",
+                State::EXTENSION_ID.to_str().unwrap(),
+            )),
+        });
+    }
+    host.handle_callbacks_once();
+
+    let param_infos = params
+        .info()
+        .context("Failure while fetching the plugin's parameters")?;
+    if param_infos.is_empty() {
+        return Ok(TestStatus::Skipped {
+            details: Some(String::from("The plugin does not have any parameters.")),
+        });
+    }
+
+    // Anything the plugin did during initialization doesn't count
+    plugin.state.dirty_marks.lock().clear();
+
+    // The parameters are changed both through the process function and through the flush function
+    let param_fuzzer = ParamFuzzer::new(&param_infos);
+    let mut random_param_set_events = (0..NUM_ITERS)
+        .map(|_| param_fuzzer.randomize_params_at(&mut prng, 0).collect())
+        .collect::<Vec<Vec<Event>>>()
+        .into_iter();
+    let (mut input_buffers, mut output_buffers) = audio_ports_config.create_buffers(512);
+    ProcessingTest::new_out_of_place(&plugin, &mut input_buffers, &mut output_buffers)?.run(
+        NUM_ITERS,
+        ProcessConfig::default(),
+        |process_data| {
+            *process_data.input_events.events.lock() =
+                random_param_set_events.next().unwrap_or_default();
+            process_data.buffers.randomize(&mut prng);
+
+            Ok(())
+        },
+    )?;
+
+    let input_events = EventQueue::new_input();
+    let output_events = EventQueue::new_output();
+    for _ in 0..NUM_ITERS {
+        *input_events.events.lock() = param_fuzzer.randomize_params_at(&mut prng, 0).collect();
+        params.flush(&input_events, &output_events);
+        host.handle_callbacks_once();
+    }
+
+    host.callback_error_check()
+        .context("An error occured during a host callback")?;
+
+    let dirty_marks = plugin.state.dirty_marks.lock().clone();
+    match (dirty_marks.first(), dirty_marks.last()) {
+        (Some(first), Some(last)) if dirty_marks.len() > 1 => Ok(TestStatus::Warning {
+            details: Some(format!(
+                "The plugin called 'clap_host_state::mark_dirty()' {} times over {:.1} ms in \
+                 response to {} rounds of parameter changes made by the host. Parameter changes \
+                 made by the host already cause the state to be considered dirty.",
+                dirty_marks.len(),
+                last.duration_since(*first).as_secs_f64() * 1000.0,
+                NUM_ITERS * 2
+            )),
+        }),
+        _ => Ok(TestStatus::Success { details: None }),
+    }
+}
@@ -0,0 +1,426 @@
+//! Tests involving multiple audio threads, and multiple plugin instances processing audio at the
+//! same time.
+
+use anyhow::{Context, Result};
+use clap_sys::ext::thread_check::{clap_host_thread_check, CLAP_EXT_THREAD_CHECK};
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+use std::sync::Arc;
+
+use super::processing::check_out_of_place_output_consistency;
+use crate::plugin::ext::audio_ports::{AudioPortConfig, AudioPorts};
+use crate::plugin::ext::note_ports::{NotePortConfig, NotePorts};
+use crate::plugin::ext::params::Params;
+use crate::plugin::host::{Host, HostSettings};
+use crate::plugin::instance::audio_thread::PluginAudioThread;
+use crate::plugin::instance::process::{
+    AudioBuffers, Event, OutOfPlaceAudioBuffers, ProcessConfig, ProcessData,
+};
+use crate::plugin::instance::Plugin;
+use crate::plugin::library::PluginLibrary;
+use crate::tests::rng::{new_prng, NoteGenerator, ParamFuzzer};
+use crate::tests::TestStatus;
+use crate::util::unsafe_clap_call;
+
+/// The number of instances of the tested plugin that process audio at the same time in the
+/// concurrency test.
+pub const CONCURRENCY_NUM_INSTANCES: usize = 4;
+/// The number of blocks each instance processes in the concurrency test.
+pub const CONCURRENCY_NUM_BLOCKS: usize = 256;
+/// The parameters are randomized every this many blocks.
+const PARAM_RANDOMIZATION_INTERVAL: usize = 16;
+/// The number of threads the plugin is moved between in the audio thread migration test.
+pub const MIGRATION_NUM_THREADS: usize = 4;
+const BUFFER_SIZE: usize = 512;
+
+/// The audio output of a single plugin instance for all processed blocks, indexed by
+/// `[port][channel][sample]`.
+type RecordedOutput = Vec<Vec<Vec<f32>>>;
+
+/// The inputs for processing a fixed number of blocks with a single plugin instance. These are
+/// generated up front so the exact same inputs can be sent to an instance processing audio on its
+/// own, and to an instance processing audio concurrently with other instances.
+struct InstanceInput {
+    audio_ports_config: AudioPortConfig,
+    note_ports_config: Option<NotePortConfig>,
+    /// The seed for the PRNG used to generate the audio and note events.
+    seed: u64,
+    /// Parameter automation events for every block. Parameters are only randomized every
+    /// [`PARAM_RANDOMIZATION_INTERVAL`] blocks, so most of these will be empty.
+    param_events: Vec<Vec<Event>>,
+}
+
+impl InstanceInput {
+    /// Query the plugin's audio ports, note ports, and parameters, and generate random inputs for
+    /// it. The parameter automation events are generated here because the parameter information
+    /// cannot be shared with the audio thread.
+    fn generate(plugin: &Plugin, prng: &mut Pcg32) -> Result<Self> {
+        let audio_ports_config = match plugin.get_extension::<AudioPorts>() {
+            Some(audio_ports) => audio_ports
+                .config()
+                .context("Error while querying 'audio-ports' IO configuration")?,
+            None => AudioPortConfig::default(),
+        };
+        let note_ports_config = plugin
+            .get_extension::<NotePorts>()
+            .map(|note_ports| note_ports.config())
+            .transpose()
+            .context("Error while querying 'note-ports' IO configuration")?
+            .filter(|config| !config.inputs.is_empty());
+        let param_infos = plugin
+            .get_extension::<Params>()
+            .map(|params| params.info())
+            .transpose()
+            .context("Could not fetch the plugin's parameters")?
+            .unwrap_or_default();
+
+        let param_fuzzer = ParamFuzzer::new(&param_infos);
+        let param_events = (0..CONCURRENCY_NUM_BLOCKS)
+            .map(|block_idx| {
+                if block_idx % PARAM_RANDOMIZATION_INTERVAL == 0 {
+                    param_fuzzer.randomize_params_at(prng, 0).collect()
+                } else {
+                    Vec::new()
+                }
+            })
+            .collect();
+
+        Ok(InstanceInput {
+            audio_ports_config,
+            note_ports_config,
+            seed: prng.gen(),
+            param_events,
+        })
+    }
+
+    /// Process all blocks on the audio thread and record the plugin's output. Returns an error if
+    /// the plugin produced inconsistent output.
+    fn process(&self, plugin: &PluginAudioThread) -> Result<RecordedOutput> {
+        let mut prng = Pcg32::seed_from_u64(self.seed);
+        let mut note_event_rng = self.note_ports_config.clone().map(NoteGenerator::new);
+
+        let (mut input_buffers, mut output_buffers) =
+            self.audio_ports_config.create_buffers(BUFFER_SIZE);
+        let mut audio_buffers = AudioBuffers::OutOfPlace(OutOfPlaceAudioBuffers::new(
+            &mut input_buffers,
+            &mut output_buffers,
+        )?);
+        let mut process_data = ProcessData::new(&mut audio_buffers, ProcessConfig::default());
+
+        let mut recorded_output: RecordedOutput = self
+            .audio_ports_config
+            .outputs
+            .iter()
+            .map(|port| vec![Vec::new(); port.num_channels as usize])
+            .collect();
+
+        plugin.start_processing()?;
+        for block_idx in 0..self.param_events.len() {
+            self.process_block(
+                plugin,
+                &mut process_data,
+                &mut prng,
+                note_event_rng.as_mut(),
+                block_idx,
+            )?;
+
+            for (recorded_port, port) in recorded_output
+                .iter_mut()
+                .zip(process_data.buffers.outputs_ref())
+            {
+                for (recorded_channel, channel) in recorded_port.iter_mut().zip(port) {
+                    recorded_channel.extend_from_slice(channel);
+                }
+            }
+
+            process_data.clear_events();
+            process_data.advance_transport(BUFFER_SIZE as u32);
+        }
+        plugin.stop_processing();
+
+        Ok(recorded_output)
+    }
+
+    /// Fill `process_data` with the inputs for block `block_idx`, process it, and check the
+    /// plugin's output for consistency. The events and transport are not reset afterwards.
+    fn process_block(
+        &self,
+        plugin: &PluginAudioThread,
+        process_data: &mut ProcessData,
+        prng: &mut Pcg32,
+        note_event_rng: Option<&mut NoteGenerator>,
+        block_idx: usize,
+    ) -> Result<()> {
+        *process_data.input_events.events.lock() = self.param_events[block_idx].clone();
+        if let Some(note_event_rng) = note_event_rng {
+            note_event_rng.fill_event_queue(
+                prng,
+                &process_data.input_events,
+                BUFFER_SIZE as u32,
+            )?;
+        }
+        process_data.buffers.randomize(prng);
+
+        let original_input_buffers = process_data.buffers.inputs_ref().to_owned();
+        plugin
+            .process(process_data)
+            .context("Error during audio processing")?;
+        check_out_of_place_output_consistency(process_data, &original_input_buffers).with_context(
+            || {
+                format!(
+                    "Failed during processing cycle {} out of {}",
+                    block_idx + 1,
+                    self.param_events.len()
+                )
+            },
+        )
+    }
+}
+
+/// The test for `PluginTestCase::ProcessConcurrentInstances`.
+pub fn test_process_concurrent_instances(
+    library: &PluginLibrary,
+    plugin_id: &str,
+    host_settings: &HostSettings,
+) -> Result<TestStatus> {
+    let mut prng = new_prng();
+
+    let host = Host::new(host_settings);
+    let mut plugins = Vec::new();
+    let mut plugin_ids = Vec::new();
+    for _ in 0..CONCURRENCY_NUM_INSTANCES {
+        let plugin = library
+            .create_plugin(plugin_id, host.clone())
+            .context("Could not create the plugin instance")?;
+        plugin.init().context("Error during initialization")?;
+
+        plugins.push(plugin);
+        plugin_ids.push(plugin_id.to_owned());
+    }
+
+    // The other plugins in the library are also thrown into the mix since they will likely share
+    // global state with the tested plugin. Problems with those plugins should be reported by their
+    // own tests, so they're simply left out if they cannot be created.
+    let metadata = library
+        .metadata()
+        .context("Could not fetch the plugin library's metadata")?;
+    for other_plugin in metadata.plugins.iter().filter(|p| p.id != plugin_id) {
+        match library.create_plugin(&other_plugin.id, host.clone()) {
+            Ok(plugin) => match plugin.init() {
+                Ok(()) => {
+                    plugins.push(plugin);
+                    plugin_ids.push(other_plugin.id.clone());
+                }
+                Err(err) => log::warn!(
+                    "Could not initialize '{}', leaving it out of the test: {err:#}",
+                    other_plugin.id
+                ),
+            },
+            Err(err) => log::warn!(
+                "Could not create '{}', leaving it out of the test: {err:#}",
+                other_plugin.id
+            ),
+        }
+    }
+
+    let inputs = plugins
+        .iter()
+        .map(|plugin| InstanceInput::generate(plugin, &mut prng))
+        .collect::<Result<Vec<_>>>()?;
+    host.handle_callbacks_once();
+
+    for plugin in &plugins {
+        plugin.activate(ProcessConfig::default().sample_rate, 1, BUFFER_SIZE)?;
+    }
+    let concurrent_results = Plugin::on_audio_threads(
+        plugins
+            .iter()
+            .zip(&inputs)
+            .map(|(plugin, input)| (plugin, move |plugin| input.process(&plugin))),
+    );
+    for plugin in &plugins {
+        plugin.deactivate();
+    }
+    host.handle_callbacks_once();
+
+    let mut concurrent_outputs = Vec::new();
+    for (instance_idx, (result, id)) in concurrent_results.into_iter().zip(&plugin_ids).enumerate()
+    {
+        concurrent_outputs.push(result.with_context(|| {
+            format!(
+                "Error in instance {} ('{id}') while processing concurrently",
+                instance_idx + 1
+            )
+        })?);
+    }
+    drop(plugins);
+
+    host.callback_error_check()
+        .context("An error occured during a host callback")?;
+
+    // Cross-talk between instances can only be detected if processing the same input on a fresh
+    // instance always results in the same output. To test this, the first instance's input is
+    // processed twice.
+    let reference_output = process_solo(library, plugin_id, &host, &inputs[0])?;
+    if process_solo(library, plugin_id, &host, &inputs[0])? != reference_output {
+        return Ok(TestStatus::Success {
+            details: Some(String::from(
+                "The plugin's output is not deterministic, so cross-talk between instances could \
+                 not be checked.",
+            )),
+        });
+    }
+
+    for (instance_idx, (input, concurrent_output)) in inputs
+        .iter()
+        .zip(&concurrent_outputs)
+        .take(CONCURRENCY_NUM_INSTANCES)
+        .enumerate()
+    {
+        let solo_output = if instance_idx == 0 {
+            reference_output.clone()
+        } else {
+            process_solo(library, plugin_id, &host, input)?
+        };
+
+        if let Some((port_idx, channel_idx, sample_idx)) =
+            first_difference(&solo_output, concurrent_output)
+        {
+            anyhow::bail!(
+                "The output of instance {} differs when processing audio concurrently with other \
+                 instances, even though the plugin's output is deterministic when processing on \
+                 its own. The first difference is at sample {sample_idx} of channel \
+                 {channel_idx} on output port {port_idx}. This indicates that plugin instances \
+                 share mutable state.",
+                instance_idx + 1
+            );
+        }
+    }
+
+    host.callback_error_check()
+        .context("An error occured during a host callback")?;
+    Ok(TestStatus::Success { details: None })
+}
+
+/// The test for `PluginTestCase::ProcessAudioThreadMigration`.
+pub fn test_process_audio_thread_migration(
+    library: &PluginLibrary,
+    plugin_id: &str,
+    host_settings: &HostSettings,
+) -> Result<TestStatus> {
+    let mut prng = new_prng();
+
+    let host = Host::new(host_settings);
+    let plugin = library
+        .create_plugin(plugin_id, host.clone())
+        .context("Could not create the plugin instance")?;
+    plugin.init().context("Error during initialization")?;
+
+    let input = InstanceInput::generate(&plugin, &mut prng)?;
+    host.handle_callbacks_once();
+
+    let mut prng = Pcg32::seed_from_u64(input.seed);
+    let mut note_event_rng = input.note_ports_config.clone().map(NoteGenerator::new);
+    let (mut input_buffers, mut output_buffers) =
+        input.audio_ports_config.create_buffers(BUFFER_SIZE);
+    let mut audio_buffers = AudioBuffers::OutOfPlace(OutOfPlaceAudioBuffers::new(
+        &mut input_buffers,
+        &mut output_buffers,
+    )?);
+    let mut process_data = ProcessData::new(&mut audio_buffers, ProcessConfig::default());
+
+    plugin.activate(ProcessConfig::default().sample_rate, 1, BUFFER_SIZE)?;
+    let result = plugin.on_audio_thread_pool(
+        MIGRATION_NUM_THREADS,
+        input.param_events.len(),
+        |plugin, block_idx| {
+            input.process_block(
+                plugin,
+                &mut process_data,
+                &mut prng,
+                note_event_rng.as_mut(),
+                block_idx,
+            )?;
+            check_thread_check_answers(plugin)?;
+
+            process_data.clear_events();
+            process_data.advance_transport(BUFFER_SIZE as u32);
+
+            Ok(())
+        },
+    );
+    plugin.deactivate();
+    host.handle_callbacks_once();
+    result?;
+
+    host.callback_error_check()
+        .context("An error occured during a host callback")?;
+    Ok(TestStatus::Success { details: None })
+}
+
+/// Create a new instance of the plugin, process `input` on it with no other instances processing
+/// audio at the same time, and return the recorded output.
+fn process_solo(
+    library: &PluginLibrary,
+    plugin_id: &str,
+    host: &Arc<Host>,
+    input: &InstanceInput,
+) -> Result<RecordedOutput> {
+    let plugin = library
+        .create_plugin(plugin_id, host.clone())
+        .context("Could not create the plugin instance")?;
+    plugin.init().context("Error during initialization")?;
+    host.handle_callbacks_once();
+
+    plugin.activate(ProcessConfig::default().sample_rate, 1, BUFFER_SIZE)?;
+    let result = plugin
+        .on_audio_thread(|plugin| input.process(&plugin))
+        .context("Error while processing on a single instance")?;
+    plugin.deactivate();
+    host.handle_callbacks_once();
+
+    Ok(result)
+}
+
+/// Find the first sample where two recorded outputs differ. Returns the port, channel, and sample
+/// indices of that sample, or `None` if the outputs are identical.
+fn first_difference(a: &RecordedOutput, b: &RecordedOutput) -> Option<(usize, usize, usize)> {
+    for (port_idx, (port_a, port_b)) in a.iter().zip(b).enumerate() {
+        for (channel_idx, (channel_a, channel_b)) in port_a.iter().zip(port_b).enumerate() {
+            if let Some(sample_idx) = channel_a
+                .iter()
+                .zip(channel_b)
+                .position(|(sample_a, sample_b)| sample_a != sample_b)
+            {
+                return Some((port_idx, channel_idx, sample_idx));
+            }
+        }
+    }
+
+    None
+}
+
+/// Query the host's `clap_host_thread_check` extension from the current audio thread the same way
+/// the plugin would, and check that the current thread is identified as an audio thread. A plugin
+/// that relies on these answers would otherwise assert or misbehave after being moved to another
+/// thread.
+fn check_thread_check_answers(plugin: &PluginAudioThread) -> Result<()> {
+    let host = plugin.state().clap_host_ptr();
+    let thread_check = unsafe_clap_call! {
+        host=>get_extension(host, CLAP_EXT_THREAD_CHECK.as_ptr())
+    } as *const clap_host_thread_check;
+    if thread_check.is_null() {
+        anyhow::bail!("The host's 'thread-check' extension could not be queried");
+    }
+
+    let is_audio_thread = unsafe_clap_call! { thread_check=>is_audio_thread(host) };
+    let is_main_thread = unsafe_clap_call! { thread_check=>is_main_thread(host) };
+    if !is_audio_thread || is_main_thread {
+        anyhow::bail!(
+            "'clap_host_thread_check' returned is_audio_thread() = {is_audio_thread} and \
+             is_main_thread() = {is_main_thread} on the thread currently processing audio"
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,141 @@
+//! Tests involving the plugin's timers registered through `clap_host_timer_support`.
+
+use anyhow::{Context, Result};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use super::processing::ProcessingTest;
+use crate::plugin::ext::audio_ports::{AudioPortConfig, AudioPorts};
+use crate::plugin::host::{Host, HostSettings};
+use crate::plugin::instance::process::ProcessConfig;
+use crate::plugin::library::PluginLibrary;
+use crate::tests::rng::new_prng;
+use crate::tests::TestStatus;
+
+/// The buffer size used for the timer tests.
+const BUFFER_SIZE: usize = 512;
+/// The number of buffers processed in the processing test. These are processed in realtime, so at
+/// 44.1 kHz this takes a bit over a second.
+const PROCESS_NUM_ITERS: usize = 100;
+
+/// The test for `PluginTestCase::TimerProcess`. Processes audio in realtime while the main thread
+/// fires the plugin's timers.
+pub fn test_timer_process(
+    library: &PluginLibrary,
+    plugin_id: &str,
+    host_settings: &HostSettings,
+) -> Result<TestStatus> {
+    let mut prng = new_prng();
+
+    let host = Host::new(host_settings);
+    let plugin = library
+        .create_plugin(plugin_id, host.clone())
+        .context("Could not create the plugin instance")?;
+    plugin.init().context("Error during initialization")?;
+
+    let audio_ports_config = match plugin.get_extension::<AudioPorts>() {
+        Some(audio_ports) => audio_ports
+            .config()
+            .context("Error while querying 'audio-ports' IO configuration")?,
+        None => AudioPortConfig::default(),
+    };
+    host.handle_callbacks_once();
+
+    // The timers are fired from the main thread while the audio thread is running. The audio
+    // thread busy-waits instead of sleeping to not trip the audio thread audit mode.
+    let process_config = ProcessConfig::default();
+    let buffer_duration = Duration::from_secs_f64(BUFFER_SIZE as f64 / process_config.sample_rate);
+    let mut next_deadline = None;
+    let (mut input_buffers, mut output_buffers) = audio_ports_config.create_buffers(BUFFER_SIZE);
+    ProcessingTest::new_out_of_place(&plugin, &mut input_buffers, &mut output_buffers)?.run(
+        PROCESS_NUM_ITERS,
+        process_config,
+        |process_data| {
+            let deadline = next_deadline.unwrap_or_else(Instant::now) + buffer_duration;
+            while Instant::now() < deadline {
+                std::hint::spin_loop();
+            }
+            next_deadline = Some(deadline);
+
+            process_data.buffers.randomize(&mut prng);
+
+            Ok(())
+        },
+    )?;
+
+    host.callback_error_check()
+        .context("An error occured during a host callback")?;
+
+    if plugin.state.num_registered_timers.load(Ordering::SeqCst) == 0 {
+        return Ok(TestStatus::Skipped {
+            details: Some(String::from("The plugin did not register any timers.")),
+        });
+    }
+
+    Ok(TestStatus::Success {
+        details: Some(format!(
+            "'clap_plugin_timer_support::on_timer()' was called {} time(s) while processing audio.",
+            plugin.state.num_timer_calls.load(Ordering::SeqCst)
+        )),
+    })
+}
+
+/// The test for `PluginTestCase::TimerUnregister`. The plugin needs to unregister all of its timers
+/// before `clap_plugin::destroy()` returns.
+pub fn test_timer_unregister(
+    library: &PluginLibrary,
+    plugin_id: &str,
+    host_settings: &HostSettings,
+) -> Result<TestStatus> {
+    let host = Host::new(host_settings);
+    let plugin = library
+        .create_plugin(plugin_id, host.clone())
+        .context("Could not create the plugin instance")?;
+    plugin.init().context("Error during initialization")?;
+
+    let audio_ports_config = match plugin.get_extension::<AudioPorts>() {
+        Some(audio_ports) => audio_ports
+            .config()
+            .context("Error while querying 'audio-ports' IO configuration")?,
+        None => AudioPortConfig::default(),
+    };
+    host.handle_callbacks_once();
+
+    // Some plugins only register their timers once they're activated
+    let (mut input_buffers, mut output_buffers) = audio_ports_config.create_buffers(BUFFER_SIZE);
+    ProcessingTest::new_out_of_place(&plugin, &mut input_buffers, &mut output_buffers)?
+        .run_once(ProcessConfig::default(), |_| Ok(()))?;
+
+    host.callback_error_check()
+        .context("An error occured during a host callback")?;
+
+    // The instance's state outlives the plugin, so we can still check the timers after the plugin
+    // has been destroyed
+    let instance_state = plugin.state.clone();
+    drop(plugin);
+
+    host.callback_error_check()
+        .context("An error occured during a host callback")?;
+
+    if instance_state.num_registered_timers.load(Ordering::SeqCst) == 0 {
+        return Ok(TestStatus::Skipped {
+            details: Some(String::from("The plugin did not register any timers.")),
+        });
+    }
+
+    let remaining_timer_ids: Vec<String> = instance_state
+        .timers
+        .lock()
+        .keys()
+        .map(|timer_id| timer_id.to_string())
+        .collect();
+    if !remaining_timer_ids.is_empty() {
+        anyhow::bail!(
+            "The plugin did not unregister the timers with the following IDs before \
+             'clap_plugin::destroy()' returned: {}",
+            remaining_timer_ids.join(", ")
+        );
+    }
+
+    Ok(TestStatus::Success { details: None })
+}
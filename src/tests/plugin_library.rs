@@ -5,11 +5,22 @@ use std::path::Path;
 use std::process::Command;
 use std::time::Duration;
 
+#[cfg(not(target_os = "linux"))]
+use super::TestStatus;
 use super::{TestCase, TestResult};
+use crate::plugin::host::{HostSettings, PluginLog};
 
+mod entry_point;
 mod factories;
 mod preset_discovery;
 mod scanning;
+#[cfg(target_os = "linux")]
+mod side_effects;
+#[cfg(target_os = "linux")]
+mod unloading;
+
+#[cfg(target_os = "linux")]
+pub use side_effects::scan_to_file as scan_side_effects_to_file;
 
 const SCAN_TIME_LIMIT: Duration = Duration::from_millis(100);
 
@@ -28,15 +39,22 @@ pub enum PluginLibraryTestCase {
     ScanTime,
     #[strum(serialize = "scan-rtld-now")]
     ScanRtldNow,
+    #[strum(serialize = "scan-side-effects")]
+    ScanSideEffects,
     #[strum(serialize = "query-factory-nonexistent")]
     QueryNonexistentFactory,
     #[strum(serialize = "create-id-with-trailing-garbage")]
     CreateIdWithTrailingGarbage,
+    #[strum(serialize = "entry-point-reinit")]
+    EntryPointReinit,
+    #[strum(serialize = "unload-cleanup")]
+    UnloadCleanup,
 }
 
 impl<'a> TestCase<'a> for PluginLibraryTestCase {
-    /// The path to a CLAP plugin library.
-    type TestArgs = &'a Path;
+    /// The path to a CLAP plugin library and the settings for the tests' hosts. Child processes
+    /// receive the host settings through their command line options instead.
+    type TestArgs = (&'a Path, &'a HostSettings);
 
     fn description(&self) -> String {
         match self {
@@ -62,6 +80,13 @@ impl<'a> TestCase<'a> for PluginLibraryTestCase {
                 "Checks whether the plugin loads correctly when loaded using 'dlopen(..., \
                  RTLD_LOCAL | RTLD_NOW)'. Only run on Unix-like platforms.",
             ),
+            PluginLibraryTestCase::ScanSideEffects => String::from(
+                "Loads the plugin library, queries its metadata, and unloads it again in a \
+                 separate process while monitoring its system calls. Results in a warning if \
+                 threads are spawned, if files are written to or created, if network sockets are \
+                 opened, or if large amounts of memory are allocated during the scan. Only \
+                 supported on Linux.",
+            ),
             PluginLibraryTestCase::QueryNonexistentFactory => String::from(
                 "Tries to query a factory from the plugin's entry point with a non-existent ID. \
                  This should return a null pointer.",
@@ -70,10 +95,31 @@ impl<'a> TestCase<'a> for PluginLibraryTestCase {
                 "Attempts to create a plugin instance using an existing plugin ID with some extra \
                  text appended to the end. This should return a null pointer.",
             ),
+            PluginLibraryTestCase::EntryPointReinit => format!(
+                "Calls 'clap_plugin_entry::init()' {} more times on top of the initial call and \
+                 then balances those with 'clap_plugin_entry::deinit()' calls, followed by {} \
+                 cycles of fully deinitializing and reinitializing the entry point. After every \
+                 call, the plugin factory should return the same descriptors as before and it \
+                 should still be possible to create and initialize every plugin.",
+                entry_point::ENTRY_POINT_NESTING_DEPTH,
+                entry_point::ENTRY_POINT_NUM_SEQUENTIAL_CYCLES,
+            ),
+            PluginLibraryTestCase::UnloadCleanup => String::from(
+                "Loads the library, creates and destroys an instance of every plugin, and unloads \
+                 the library again. Fails if threads started by the plugin are still running, or \
+                 if signal handlers or exit handlers still point into the unloaded library. These \
+                 are only warnings if the library stays mapped into memory after unloading. The \
+                 library is then loaded a second time to check that it still works. Only \
+                 supported on Linux.",
+            ),
         }
     }
 
-    fn set_out_of_process_args(&self, command: &mut Command, library_path: Self::TestArgs) {
+    fn set_out_of_process_args(
+        &self,
+        command: &mut Command,
+        (library_path, _host_settings): Self::TestArgs,
+    ) {
         let test_name = self.to_string();
 
         command
@@ -91,27 +137,61 @@ impl<'a> TestCase<'a> for PluginLibraryTestCase {
             .arg(test_name);
     }
 
-    fn run_in_process(&self, library_path: Self::TestArgs) -> TestResult {
+    fn test_subject(&self, (library_path, _host_settings): &Self::TestArgs) -> String {
+        library_path
+            .file_name()
+            .unwrap_or(library_path.as_os_str())
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn run_in_process(&self, (library_path, host_settings): Self::TestArgs) -> TestResult {
+        // The hosts created by the test add the plugin's log messages to this buffer
+        let plugin_log = PluginLog::default();
+        let host_settings = &HostSettings {
+            plugin_log: plugin_log.clone(),
+            ..host_settings.clone()
+        };
+
         let status = match self {
             PluginLibraryTestCase::PresetDiscoveryCrawl => {
-                preset_discovery::test_crawl(library_path, false)
+                preset_discovery::test_crawl(library_path, false, host_settings)
             }
             PluginLibraryTestCase::PresetDiscoveryDescriptorConsistency => {
                 preset_discovery::test_descriptor_consistency(library_path)
             }
             PluginLibraryTestCase::PresetDiscoveryLoad => {
-                preset_discovery::test_crawl(library_path, true)
+                preset_discovery::test_crawl(library_path, true, host_settings)
             }
             PluginLibraryTestCase::ScanTime => scanning::test_scan_time(library_path),
             PluginLibraryTestCase::ScanRtldNow => scanning::test_scan_rtld_now(library_path),
+            #[cfg(target_os = "linux")]
+            PluginLibraryTestCase::ScanSideEffects => {
+                side_effects::test_scan_side_effects(library_path)
+            }
+            #[cfg(not(target_os = "linux"))]
+            PluginLibraryTestCase::ScanSideEffects => Ok(TestStatus::Skipped {
+                details: Some(String::from("This test is only supported on Linux.")),
+            }),
             PluginLibraryTestCase::QueryNonexistentFactory => {
                 factories::test_query_nonexistent_factory(library_path)
             }
             PluginLibraryTestCase::CreateIdWithTrailingGarbage => {
-                factories::test_create_id_with_trailing_garbage(library_path)
+                factories::test_create_id_with_trailing_garbage(library_path, host_settings)
+            }
+            PluginLibraryTestCase::EntryPointReinit => {
+                entry_point::test_entry_point_reinit(library_path, host_settings)
+            }
+            #[cfg(target_os = "linux")]
+            PluginLibraryTestCase::UnloadCleanup => {
+                unloading::test_unload_cleanup(library_path, host_settings)
             }
+            #[cfg(not(target_os = "linux"))]
+            PluginLibraryTestCase::UnloadCleanup => Ok(TestStatus::Skipped {
+                details: Some(String::from("This test is only supported on Linux.")),
+            }),
         };
 
-        self.create_result(status)
+        self.create_result(status, plugin_log.take())
     }
 }
@@ -0,0 +1,121 @@
+//! Tests involving the plugin library's entry point.
+
+use anyhow::{Context, Result};
+use clap_sys::version::clap_version_is_compatible;
+use std::path::Path;
+
+use crate::plugin::host::{Host, HostSettings};
+use crate::plugin::library::{PluginLibrary, PluginMetadata};
+use crate::tests::TestStatus;
+
+/// The number of times `clap_plugin_entry::init()` is called on top of the initial call in the
+/// nested part of the entry point test.
+pub const ENTRY_POINT_NESTING_DEPTH: usize = 3;
+/// The number of times the entry point is fully deinitialized and initialized again in the
+/// sequential part of the entry point test.
+pub const ENTRY_POINT_NUM_SEQUENTIAL_CYCLES: usize = 5;
+
+/// The test for `PluginLibraryTestCase::EntryPointReinit`.
+pub fn test_entry_point_reinit(
+    library_path: &Path,
+    host_settings: &HostSettings,
+) -> Result<TestStatus> {
+    let library = PluginLibrary::load(library_path)
+        .with_context(|| format!("Could not load '{}'", library_path.display()))?;
+
+    let metadata = library
+        .metadata()
+        .context("Could not query the plugin's metadata")?;
+    if !clap_version_is_compatible(metadata.clap_version()) {
+        return Ok(TestStatus::Skipped {
+            details: Some(format!(
+                "'{}' uses an unsupported CLAP version ({}.{}.{})",
+                library_path.display(),
+                metadata.version.0,
+                metadata.version.1,
+                metadata.version.2
+            )),
+        });
+    }
+    let expected_plugins = metadata.plugins;
+
+    // The entry point is initialized several more times on top of the initialization done when
+    // loading the library. The factory needs to remain usable until the last `deinit()` call.
+    for depth in 1..=ENTRY_POINT_NESTING_DEPTH {
+        library.init_entry_point().with_context(|| {
+            format!("Error while initializing the entry point a nested {depth} time(s)")
+        })?;
+        check_factory(&library, &expected_plugins, host_settings).with_context(|| {
+            format!("Error after initializing the entry point a nested {depth} time(s)")
+        })?;
+    }
+    for remaining in (1..=ENTRY_POINT_NESTING_DEPTH).rev() {
+        library.deinit_entry_point();
+        check_factory(&library, &expected_plugins, host_settings).with_context(|| {
+            format!(
+                "Error after deinitializing the entry point with {remaining} outstanding \
+                 initialization(s) remaining"
+            )
+        })?;
+    }
+
+    // The entry point is then fully deinitialized and initialized again, like a host would do
+    // when it first scans a plugin and then loads it again later
+    for cycle in 1..=ENTRY_POINT_NUM_SEQUENTIAL_CYCLES {
+        library.deinit_entry_point();
+        if let Err(err) = library.init_entry_point() {
+            // The entry point is no longer initialized, so it should not be deinitialized again
+            // when the library gets dropped
+            std::mem::forget(library);
+
+            return Err(err).with_context(|| {
+                format!(
+                    "Error while reinitializing the entry point after it was deinitialized, cycle \
+                     {cycle} out of {ENTRY_POINT_NUM_SEQUENTIAL_CYCLES}"
+                )
+            });
+        }
+
+        check_factory(&library, &expected_plugins, host_settings).with_context(|| {
+            format!(
+                "Error after reinitializing the entry point, cycle {cycle} out of \
+                 {ENTRY_POINT_NUM_SEQUENTIAL_CYCLES}"
+            )
+        })?;
+    }
+
+    Ok(TestStatus::Success { details: None })
+}
+
+/// Check that the plugin factory returns the same descriptors as before, and that it can still be
+/// used to create and initialize an instance of every plugin.
+fn check_factory(
+    library: &PluginLibrary,
+    expected_plugins: &[PluginMetadata],
+    host_settings: &HostSettings,
+) -> Result<()> {
+    let metadata = library
+        .metadata()
+        .context("Could not query the plugin's metadata")?;
+    if metadata.plugins != expected_plugins {
+        anyhow::bail!(
+            "The plugin descriptors returned by the factory changed. Expected \
+             {expected_plugins:#?}, got {:#?}.",
+            metadata.plugins
+        );
+    }
+
+    let host = Host::new(host_settings);
+    for plugin_metadata in expected_plugins {
+        let plugin = library
+            .create_plugin(&plugin_metadata.id, host.clone())
+            .with_context(|| format!("Could not create an instance of '{}'", plugin_metadata.id))?;
+        plugin
+            .init()
+            .with_context(|| format!("Error while initializing '{}'", plugin_metadata.id))?;
+        host.handle_callbacks_once();
+    }
+
+    host.callback_error_check()
+        .context("An error occured during a host callback")
+}
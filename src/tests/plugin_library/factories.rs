@@ -4,7 +4,7 @@ use anyhow::{Context, Result};
 use clap_sys::version::clap_version_is_compatible;
 use std::path::Path;
 
-use crate::plugin::host::Host;
+use crate::plugin::host::{Host, HostSettings};
 use crate::plugin::library::PluginLibrary;
 use crate::tests::TestStatus;
 
@@ -31,7 +31,10 @@ pub fn test_query_nonexistent_factory(library_path: &Path) -> Result<TestStatus>
 }
 
 /// The test for `PluginLibraryTestCase::CreateIdWithTrailingGarbage`.
-pub fn test_create_id_with_trailing_garbage(library_path: &Path) -> Result<TestStatus> {
+pub fn test_create_id_with_trailing_garbage(
+    library_path: &Path,
+    host_settings: &HostSettings,
+) -> Result<TestStatus> {
     let library = PluginLibrary::load(library_path)
         .with_context(|| format!("Could not load '{}'", library_path.display()))?;
 
@@ -86,7 +89,10 @@ pub fn test_create_id_with_trailing_garbage(library_path: &Path) -> Result<TestS
 
     // This should return an error/null-pointer instead of actually instantiating a
     // plugin
-    if library.create_plugin(&fake_plugin_id, Host::new()).is_ok() {
+    if library
+        .create_plugin(&fake_plugin_id, Host::new(host_settings))
+        .is_ok()
+    {
         anyhow::bail!(
             "Creating a plugin instance with a non-existent plugin ID '{fake_plugin_id}' should \
              return a null pointer, but it did not."
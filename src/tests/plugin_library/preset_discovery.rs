@@ -6,10 +6,12 @@ use std::collections::BTreeMap;
 use std::path::Path;
 
 use crate::plugin::ext::audio_ports::AudioPorts;
-use crate::plugin::ext::preset_load::PresetLoad;
+use crate::plugin::ext::preset_load::{PresetLoad, PresetLoadNotification};
+use crate::plugin::ext::state::State;
 use crate::plugin::ext::Extension;
-use crate::plugin::host::Host;
+use crate::plugin::host::{Host, HostSettings};
 use crate::plugin::instance::process::ProcessConfig;
+use crate::plugin::instance::Plugin;
 use crate::plugin::library::PluginLibrary;
 use crate::plugin::preset_discovery::{LocationValue, PluginAbi, Preset, PresetFile};
 use crate::tests::plugin::ProcessingTest;
@@ -23,7 +25,11 @@ const BUFFER_SIZE: usize = 512;
 /// The test for `PluginLibraryTestCase::PresetDiscoveryCrawl`. Makes sure that all of a plugin's
 /// reported preset locations can be crawled successfully. If `load_presets` is enabled, then the
 /// crawled presets are also loaded.
-pub fn test_crawl(library_path: &Path, load_presets: bool) -> Result<TestStatus> {
+pub fn test_crawl(
+    library_path: &Path,
+    load_presets: bool,
+    host_settings: &HostSettings,
+) -> Result<TestStatus> {
     let library = PluginLibrary::load(library_path)
         .with_context(|| format!("Could not load '{}'", library_path.display()))?;
     let preset_discovery_factory = match library.preset_discovery_factory() {
@@ -65,6 +71,11 @@ pub fn test_crawl(library_path: &Path, load_presets: bool) -> Result<TestStatus>
         }
     }
 
+    // Loading a preset changes the plugin's state without the host's involvement, so the plugin
+    // should call `clap_host_state::mark_dirty()` afterwards. Presets for which this didn't happen
+    // result in a warning.
+    let mut presets_not_marked_dirty: Vec<String> = Vec::new();
+
     // After crawling, group the presets by CLAP plugin ID and try to load them
     if load_presets {
         // Because container presets can contain presets for multiple different plugins storing all
@@ -115,7 +126,7 @@ pub fn test_crawl(library_path: &Path, load_presets: bool) -> Result<TestStatus>
         // With everything indexed, we can try loading these presets. We'll reuse one plugin
         // instance per plugin.
         for (plugin_id, presets) in loadable_presets_by_plugin_id {
-            let host = Host::new();
+            let host = Host::new(host_settings);
             let plugin = library
                 .create_plugin(&plugin_id, host.clone())
                 .with_context(|| format!("Could not create a plugin instance for '{plugin_id}'"))?;
@@ -138,6 +149,7 @@ pub fn test_crawl(library_path: &Path, load_presets: bool) -> Result<TestStatus>
             // We'll try to run some audio through the plugin to make sure the preset change was
             // successful, but it doesn't matter if the plugin doesn't have any audio ports
             let audio_ports = plugin.get_extension::<AudioPorts>();
+            let has_state = plugin.get_extension::<State>().is_some();
             host.handle_callbacks_once();
 
             let audio_ports_config = audio_ports
@@ -157,6 +169,8 @@ pub fn test_crawl(library_path: &Path, load_presets: bool) -> Result<TestStatus>
                 // TODO: We now always deactivate the plugin before loading presets, but presets can
                 //       be loaded at any point, even when the plugin is processing audio. Test
                 //       this.
+                check_stray_preset_load_notifications(&plugin, &plugin_id)?;
+                plugin.state.dirty_marks.lock().clear();
                 let load_result = preset_load
                     .from_location(&location, load_key.as_deref())
                     .with_context(|| {
@@ -179,6 +193,36 @@ pub fn test_crawl(library_path: &Path, load_presets: bool) -> Result<TestStatus>
                 // See above
                 load_result?;
 
+                // Errors reported through `clap_host_preset_load::on_error()` have been caught by
+                // the callback error check above, so the plugin should have confirmed the load
+                let expected_notification = PresetLoadNotification::Loaded {
+                    location: location.clone(),
+                    load_key: load_key.clone(),
+                };
+                let notifications =
+                    std::mem::take(&mut *plugin.state.preset_load_notifications.lock());
+                match notifications.as_slice() {
+                    [notification] if *notification == expected_notification => (),
+                    [] => anyhow::bail!(
+                        "The plugin did not call 'clap_host_preset_load::loaded()' after loading \
+                         the preset '{}' for plugin '{}'.",
+                        preset.name,
+                        plugin_id
+                    ),
+                    notifications => anyhow::bail!(
+                        "Expected a single call to {} after loading the preset '{}' for plugin \
+                         '{}', but the plugin made the following calls instead: {}",
+                        expected_notification,
+                        preset.name,
+                        plugin_id,
+                        format_notifications(notifications)
+                    ),
+                }
+
+                if has_state && plugin.state.dirty_marks.lock().is_empty() {
+                    presets_not_marked_dirty.push(format!("'{}' ({plugin_id})", preset.name));
+                }
+
                 // We'll process a single buffer of silent audio just to make sure everything's
                 // settled in
                 ProcessingTest::new_out_of_place(&plugin, &mut input_buffers, &mut output_buffers)?
@@ -200,9 +244,20 @@ pub fn test_crawl(library_path: &Path, load_presets: bool) -> Result<TestStatus>
             host.callback_error_check().with_context(|| {
                 format!("An error occured during a host callback made by '{plugin_id}'")
             })?;
+            check_stray_preset_load_notifications(&plugin, &plugin_id)?;
         }
     }
 
+    if !presets_not_marked_dirty.is_empty() {
+        return Ok(TestStatus::Warning {
+            details: Some(format!(
+                "The plugin did not call 'clap_host_state::mark_dirty()' after loading the \
+                 following presets: {}",
+                presets_not_marked_dirty.join(", ")
+            )),
+        });
+    }
+
     Ok(TestStatus::Success { details: None })
 }
 
@@ -255,3 +310,27 @@ pub fn test_descriptor_consistency(library_path: &Path) -> Result<TestStatus> {
 
     Ok(TestStatus::Success { details: None })
 }
+
+/// Make sure the plugin has not sent any preset load notifications since the last check. These
+/// notifications should only be sent in response to the host loading a preset.
+fn check_stray_preset_load_notifications(plugin: &Plugin, plugin_id: &str) -> Result<()> {
+    let notifications = std::mem::take(&mut *plugin.state.preset_load_notifications.lock());
+    if notifications.is_empty() {
+        Ok(())
+    } else {
+        anyhow::bail!(
+            "'{}' made the following calls without a corresponding preset being loaded: {}",
+            plugin_id,
+            format_notifications(&notifications)
+        )
+    }
+}
+
+/// Format a list of preset load notifications for use in error messages.
+fn format_notifications(notifications: &[PresetLoadNotification]) -> String {
+    notifications
+        .iter()
+        .map(|notification| notification.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
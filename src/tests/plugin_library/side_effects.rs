@@ -0,0 +1,417 @@
+//! Tests for side effects caused by scanning the plugin library. Only available on Linux.
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::ffi::{CStr, OsStr};
+use std::fs;
+use std::os::raw::c_char;
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::plugin::library::PluginLibrary;
+use crate::tests::TestStatus;
+use crate::util::fork;
+use crate::util::seccomp::{Syscall, SyscallFilter, SyscallMonitor};
+use crate::Verbosity;
+
+/// The maximum amount of time the child process is allowed to take for scanning the library.
+const SCAN_TIMEOUT: Duration = Duration::from_secs(30);
+/// Anonymous memory mappings of at least this many bytes are reported as large allocations.
+const LARGE_ALLOCATION_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+const O_WRONLY: u32 = libc::O_WRONLY as u32;
+const O_RDWR: u32 = libc::O_RDWR as u32;
+const O_CREAT: u32 = libc::O_CREAT as u32;
+/// The open flags that indicate a file is opened for writing, or that it may be created.
+const OPEN_WRITE_FLAGS_MASK: u32 = O_WRONLY | O_RDWR | O_CREAT;
+/// All combinations of [`OPEN_WRITE_FLAGS_MASK`]'s flags, since the seccomp filter can only match
+/// exact values.
+const OPEN_WRITE_FLAGS: &[u32] = &[
+    O_WRONLY,
+    O_RDWR,
+    O_WRONLY | O_RDWR,
+    O_CREAT,
+    O_CREAT | O_WRONLY,
+    O_CREAT | O_RDWR,
+    O_CREAT | O_WRONLY | O_RDWR,
+];
+const SOCKET_DOMAINS: &[u32] = &[libc::AF_INET as u32, libc::AF_INET6 as u32];
+const CLONE_THREAD: &[u32] = &[libc::CLONE_THREAD as u32];
+
+/// System calls that modify the file system. Contains the system call's number and the index of
+/// the argument containing the affected path. Opening files is handled separately.
+const FILESYSTEM_SYSCALLS: &[(libc::c_long, usize)] = &[
+    #[cfg(target_arch = "x86_64")]
+    (libc::SYS_creat, 0),
+    #[cfg(target_arch = "x86_64")]
+    (libc::SYS_mkdir, 0),
+    (libc::SYS_mkdirat, 1),
+    #[cfg(target_arch = "x86_64")]
+    (libc::SYS_unlink, 0),
+    (libc::SYS_unlinkat, 1),
+    #[cfg(target_arch = "x86_64")]
+    (libc::SYS_rename, 1),
+    (libc::SYS_renameat, 3),
+    (libc::SYS_renameat2, 3),
+];
+
+/// The outcome of scanning the library in the child process. This is sent back to the parent
+/// process as JSON.
+#[derive(Debug, Deserialize, Serialize)]
+enum ScanOutcome {
+    /// The system call monitor could not be installed, so the test cannot be run.
+    MonitorUnavailable(String),
+    /// Loading or scanning the library failed.
+    ScanFailed(String),
+    /// The library was scanned, and these side effects were observed.
+    Scanned(SideEffects),
+}
+
+/// The side effects observed while scanning the library.
+#[derive(Debug, Deserialize, Serialize)]
+struct SideEffects {
+    num_threads_spawned: usize,
+    /// The paths passed to system calls that write to or modify the file system. These may be
+    /// relative to some directory.
+    files_written: BTreeSet<String>,
+    /// The address families of the network sockets that were opened.
+    sockets_opened: BTreeSet<String>,
+    /// The number of anonymous memory mappings of at least [`LARGE_ALLOCATION_THRESHOLD`] bytes.
+    num_large_allocations: usize,
+    /// The combined size of those mappings, in bytes.
+    large_allocations_size: u64,
+}
+
+/// The side effects recorded by the system call handler. The counters are atomics since memory
+/// may be allocated while the monitored thread holds the allocator's lock, and the handler must
+/// thus not allocate any memory when handling those calls.
+#[derive(Debug, Default)]
+struct RecordedSideEffects {
+    /// System calls are only recorded while this is set, since the monitor keeps running after
+    /// the library has been scanned.
+    recording: AtomicBool,
+    num_threads_spawned: AtomicUsize,
+    files_written: Mutex<BTreeSet<String>>,
+    sockets_opened: Mutex<BTreeSet<String>>,
+    num_large_allocations: AtomicUsize,
+    large_allocations_size: AtomicU64,
+}
+
+/// The test for `PluginLibraryTestCase::ScanSideEffects`.
+pub fn test_scan_side_effects(library_path: &Path) -> Result<TestStatus> {
+    // Like for out-of-process tests, the outcome is written to a temporary file
+    let output_file_path = tempfile::Builder::new()
+        .suffix(".json")
+        .tempfile()
+        .context("Could not create a temporary file path")?
+        .into_temp_path();
+
+    // The library is scanned in a freshly spawned validator process so the seccomp filter does not
+    // affect this process, and so the scan is not affected by anything that happened in this
+    // process before. Forking would not be safe here since loading the library needs to acquire
+    // locks other threads in this process may be holding.
+    let clap_validator_binary =
+        std::env::current_exe().context("Could not find the path to the current executable")?;
+    let mut child = Command::new(clap_validator_binary)
+        .arg("--verbosity")
+        .arg(current_verbosity().to_possible_value().unwrap().get_name())
+        .arg("scan-side-effects")
+        .arg(library_path)
+        .args([OsStr::new("--output-file"), output_file_path.as_os_str()])
+        // Anything the plugin prints would otherwise end up in this process' STDOUT stream
+        .stdout(Stdio::null())
+        .spawn()
+        .context("Could not call clap-validator to scan the plugin library")?;
+    let exit_status = match wait_timeout(&mut child, SCAN_TIMEOUT)? {
+        Some(exit_status) => exit_status,
+        None => anyhow::bail!(
+            "Scanning the plugin library did not finish within {} seconds.",
+            SCAN_TIMEOUT.as_secs()
+        ),
+    };
+    if !exit_status.success() {
+        match exit_status.signal() {
+            Some(signal) => anyhow::bail!(
+                "The process scanning the plugin library terminated with {}.",
+                fork::signal_name(signal)
+            ),
+            None => {
+                anyhow::bail!("The process scanning the plugin library exited with {exit_status}.")
+            }
+        }
+    }
+
+    let outcome: ScanOutcome =
+        serde_json::from_str(&fs::read_to_string(&output_file_path).with_context(|| {
+            format!(
+                "Could not read the scanning process' output from '{}'",
+                output_file_path.display()
+            )
+        })?)
+        .context("Could not parse the scanning process' output")?;
+    let side_effects = match outcome {
+        ScanOutcome::MonitorUnavailable(err) => {
+            return Ok(TestStatus::Skipped {
+                details: Some(format!(
+                    "The system calls made while scanning the plugin could not be monitored: \
+                     {err}"
+                )),
+            })
+        }
+        ScanOutcome::ScanFailed(err) => anyhow::bail!(err),
+        ScanOutcome::Scanned(side_effects) => side_effects,
+    };
+
+    let mut observations = Vec::new();
+    if side_effects.num_threads_spawned > 0 {
+        observations.push(format!(
+            "{} thread(s) were spawned.",
+            side_effects.num_threads_spawned
+        ));
+    }
+    if !side_effects.files_written.is_empty() {
+        let paths: Vec<String> = side_effects
+            .files_written
+            .iter()
+            .map(|path| format!("'{path}'"))
+            .collect();
+        observations.push(format!(
+            "The following paths were written to, created, or removed: {}.",
+            paths.join(", ")
+        ));
+    }
+    if !side_effects.sockets_opened.is_empty() {
+        let families: Vec<&str> = side_effects
+            .sockets_opened
+            .iter()
+            .map(String::as_str)
+            .collect();
+        observations.push(format!(
+            "Network sockets were opened ({}).",
+            families.join(", ")
+        ));
+    }
+    if side_effects.num_large_allocations > 0 {
+        observations.push(format!(
+            "{} large memory allocation(s) totalling {:.1} MiB were made.",
+            side_effects.num_large_allocations,
+            side_effects.large_allocations_size as f64 / 1024.0 / 1024.0
+        ));
+    }
+
+    if observations.is_empty() {
+        Ok(TestStatus::Success { details: None })
+    } else {
+        Ok(TestStatus::Warning {
+            details: Some(format!(
+                "Scanning the plugin library had side effects. Plugin scanning should be fast and \
+                 should not affect the rest of the system. {}",
+                observations.join(" ")
+            )),
+        })
+    }
+}
+
+/// Scan the library at `library_path` while monitoring its side effects, and write the outcome to
+/// `output_file_path` as JSON. This is run in the `clap-validator scan-side-effects` child process
+/// spawned by [`test_scan_side_effects()`].
+pub fn scan_to_file(library_path: &Path, output_file_path: &Path) -> Result<()> {
+    // The file needs to be opened before the monitor is installed, since opening files for writing
+    // will be recorded as a side effect
+    let output_file = fs::File::create(output_file_path).with_context(|| {
+        format!(
+            "Could not create the output file at '{}'",
+            output_file_path.display()
+        )
+    })?;
+
+    let outcome = scan_with_monitor(library_path);
+    serde_json::to_writer(output_file, &outcome).context("Could not write the scan's outcome")
+}
+
+/// Wait for `child` to exit, killing it if it does not exit within `timeout`. Returns `None` if
+/// the process timed out.
+fn wait_timeout(child: &mut Child, timeout: Duration) -> Result<Option<ExitStatus>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(exit_status) = child
+            .try_wait()
+            .context("Error while waiting for the scanning process to exit")?
+        {
+            return Ok(Some(exit_status));
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+
+            return Ok(None);
+        }
+
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Get the [`Verbosity`] corresponding to this process' logging level, so the child process logs
+/// the same things.
+fn current_verbosity() -> Verbosity {
+    match log::max_level() {
+        log::LevelFilter::Off => Verbosity::Quiet,
+        log::LevelFilter::Error => Verbosity::Error,
+        log::LevelFilter::Warn => Verbosity::Warn,
+        log::LevelFilter::Info => Verbosity::Info,
+        log::LevelFilter::Debug => Verbosity::Debug,
+        log::LevelFilter::Trace => Verbosity::Trace,
+    }
+}
+
+/// Load the library, query its metadata, and unload it again while monitoring the system calls
+/// made by this thread and any threads it spawns. This is run in the scanning child process. The
+/// monitor is never stopped since the intercepted system calls would start failing after that, so
+/// the process should exit shortly after this function returns.
+fn scan_with_monitor(library_path: &Path) -> ScanOutcome {
+    let mut filters = vec![
+        SyscallFilter {
+            nr: libc::SYS_clone,
+            arg: Some((0, libc::CLONE_THREAD as u32, CLONE_THREAD)),
+        },
+        // The flags for `clone3()` are stored in a struct, so they're checked in the handler
+        SyscallFilter::any(libc::SYS_clone3),
+        SyscallFilter {
+            nr: libc::SYS_openat,
+            arg: Some((2, OPEN_WRITE_FLAGS_MASK, OPEN_WRITE_FLAGS)),
+        },
+        #[cfg(target_arch = "x86_64")]
+        SyscallFilter {
+            nr: libc::SYS_open,
+            arg: Some((1, OPEN_WRITE_FLAGS_MASK, OPEN_WRITE_FLAGS)),
+        },
+        SyscallFilter {
+            nr: libc::SYS_socket,
+            arg: Some((0, u32::MAX, SOCKET_DOMAINS)),
+        },
+        SyscallFilter::any(libc::SYS_mmap),
+    ];
+    filters.extend(
+        FILESYSTEM_SYSCALLS
+            .iter()
+            .map(|&(nr, _)| SyscallFilter::any(nr)),
+    );
+
+    let recorded = Arc::new(RecordedSideEffects::default());
+    recorded.recording.store(true, Ordering::SeqCst);
+    let monitor = match SyscallMonitor::install(&filters, {
+        let recorded = recorded.clone();
+        move |syscall| record_syscall(&recorded, syscall)
+    }) {
+        Ok(monitor) => monitor,
+        Err(err) => return ScanOutcome::MonitorUnavailable(format!("{err:#}")),
+    };
+
+    let result = PluginLibrary::load(library_path)
+        .with_context(|| format!("Could not load '{}'", library_path.display()))
+        .and_then(|library| {
+            library
+                .metadata()
+                .context("Could not query the plugin's metadata")
+        });
+    recorded.recording.store(false, Ordering::SeqCst);
+    std::mem::forget(monitor);
+
+    match result {
+        Ok(_) => ScanOutcome::Scanned(SideEffects {
+            num_threads_spawned: recorded.num_threads_spawned.load(Ordering::SeqCst),
+            files_written: recorded.files_written.lock().clone(),
+            sockets_opened: recorded.sockets_opened.lock().clone(),
+            num_large_allocations: recorded.num_large_allocations.load(Ordering::SeqCst),
+            large_allocations_size: recorded.large_allocations_size.load(Ordering::SeqCst),
+        }),
+        Err(err) => ScanOutcome::ScanFailed(format!("{err:#}")),
+    }
+}
+
+/// Record an intercepted system call. The monitored thread is blocked while this is running, and
+/// since it runs in the same process, pointer arguments can be read directly.
+fn record_syscall(recorded: &RecordedSideEffects, syscall: &Syscall) {
+    if !recorded.recording.load(Ordering::SeqCst) {
+        return;
+    }
+
+    match syscall.nr {
+        libc::SYS_clone => {
+            recorded.num_threads_spawned.fetch_add(1, Ordering::SeqCst);
+        }
+        libc::SYS_clone3 => {
+            // `flags` is the first field of `struct clone_args`
+            let flags = unsafe { *(syscall.args[0] as *const u64) };
+            if flags & libc::CLONE_THREAD as u64 != 0 {
+                recorded.num_threads_spawned.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        libc::SYS_mmap => {
+            // Memory reserved with `PROT_NONE` is not actually used, glibc does this for its
+            // per-thread heaps
+            let length = syscall.args[1];
+            let is_anonymous = syscall.args[3] & libc::MAP_ANONYMOUS as u64 != 0;
+            let is_accessible = syscall.args[2] != libc::PROT_NONE as u64;
+            if is_anonymous && is_accessible && length >= LARGE_ALLOCATION_THRESHOLD {
+                recorded
+                    .num_large_allocations
+                    .fetch_add(1, Ordering::SeqCst);
+                recorded
+                    .large_allocations_size
+                    .fetch_add(length, Ordering::SeqCst);
+            }
+        }
+        libc::SYS_socket => {
+            let family = match syscall.args[0] as libc::c_int {
+                libc::AF_INET => "AF_INET",
+                libc::AF_INET6 => "AF_INET6",
+                _ => "unknown",
+            };
+            recorded.sockets_opened.lock().insert(String::from(family));
+        }
+        nr => {
+            if let Some(path_arg) = path_arg_index(nr) {
+                record_path(recorded, syscall.args[path_arg]);
+            }
+        }
+    }
+}
+
+/// Get the index of the argument containing the path for one of the intercepted file system
+/// related system calls.
+fn path_arg_index(nr: libc::c_long) -> Option<usize> {
+    #[cfg(target_arch = "x86_64")]
+    if nr == libc::SYS_open {
+        return Some(0);
+    }
+    if nr == libc::SYS_openat {
+        return Some(1);
+    }
+
+    FILESYSTEM_SYSCALLS
+        .iter()
+        .find(|&&(filesystem_nr, _)| filesystem_nr == nr)
+        .map(|&(_, path_arg)| path_arg)
+}
+
+/// Record a path that was written to. `path_ptr` is the pointer to the null terminated path in
+/// the system call's arguments.
+fn record_path(recorded: &RecordedSideEffects, path_ptr: u64) {
+    if path_ptr == 0 {
+        return;
+    }
+
+    let path = unsafe { CStr::from_ptr(path_ptr as *const c_char) };
+    recorded
+        .files_written
+        .lock()
+        .insert(path.to_string_lossy().into_owned());
+}
@@ -0,0 +1,217 @@
+//! Tests involving unloading the plugin library. Only available on Linux.
+
+use anyhow::{Context, Result};
+use clap_sys::version::clap_version_is_compatible;
+use std::collections::HashSet;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::plugin::host::{Host, HostSettings};
+use crate::plugin::instance::process::ProcessConfig;
+use crate::plugin::library::PluginLibrary;
+use crate::tests::TestStatus;
+use crate::util::fork::{self, ChildStatus};
+use crate::util::procfs;
+
+/// How long to wait for threads started by the plugin to terminate after the library has been
+/// unloaded. Threads may still be in the process of shutting down when `deinit()` returns.
+const THREAD_EXIT_GRACE_PERIOD: Duration = Duration::from_millis(500);
+/// How long to wait for the forked process running the exit handlers to terminate.
+const EXIT_HANDLERS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The test for `PluginLibraryTestCase::UnloadCleanup`.
+pub fn test_unload_cleanup(
+    library_path: &Path,
+    host_settings: &HostSettings,
+) -> Result<TestStatus> {
+    // Both the thread IDs and the library's mappings are recorded up front so they can be
+    // checked after the library has been unloaded
+    let canonical_path = fs::canonicalize(library_path)
+        .with_context(|| format!("Could not canonicalize '{}'", library_path.display()))?;
+    let threads_before: HashSet<libc::pid_t> = procfs::thread_ids()?.into_iter().collect();
+
+    let library = PluginLibrary::load(library_path)
+        .with_context(|| format!("Could not load '{}'", library_path.display()))?;
+    let library_ranges = procfs::mapped_ranges(&canonical_path)?;
+
+    let metadata = library
+        .metadata()
+        .context("Could not query the plugin's metadata")?;
+    if !clap_version_is_compatible(metadata.clap_version()) {
+        return Ok(TestStatus::Skipped {
+            details: Some(format!(
+                "'{}' uses an unsupported CLAP version ({}.{}.{})",
+                library_path.display(),
+                metadata.version.0,
+                metadata.version.1,
+                metadata.version.2
+            )),
+        });
+    }
+
+    create_and_destroy_instances(&library, host_settings)?;
+
+    // This calls `clap_plugin_entry::deinit()` and unloads the library
+    drop(library);
+
+    // Some libraries cannot be unmapped, for instance because they were linked with
+    // `-z nodelete` or because they contain unique symbols. Leftover threads and handlers
+    // would not crash the host in that case, so they're only reported as a warning. The plugin
+    // should still work after reloading either way.
+    let still_mapped = !procfs::mapped_ranges(&canonical_path)?.is_empty();
+
+    let mut problems = Vec::new();
+
+    let deadline = Instant::now() + THREAD_EXIT_GRACE_PERIOD;
+    let remaining_threads = loop {
+        let remaining_threads: Vec<libc::pid_t> = procfs::thread_ids()?
+            .into_iter()
+            .filter(|thread_id| !threads_before.contains(thread_id))
+            .collect();
+        if remaining_threads.is_empty() || Instant::now() >= deadline {
+            break remaining_threads;
+        }
+
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    if !remaining_threads.is_empty() {
+        let thread_descriptions: Vec<String> = remaining_threads
+            .iter()
+            .map(|&thread_id| match procfs::thread_name(thread_id) {
+                Some(name) => format!("'{name}' ({thread_id})"),
+                None => thread_id.to_string(),
+            })
+            .collect();
+        problems.push(format!(
+            "{} thread(s) started by the plugin were still running after the library was \
+             unloaded: {}.",
+            remaining_threads.len(),
+            thread_descriptions.join(", ")
+        ));
+    }
+
+    for (signal, handler) in signal_handlers_in(&library_ranges) {
+        problems.push(format!(
+            "The signal handler for {} still points to address {handler:#x} in the unloaded \
+             library.",
+            fork::signal_name(signal)
+        ));
+    }
+
+    if let Some(problem) = check_exit_handlers()? {
+        problems.push(problem);
+    }
+
+    if !problems.is_empty() && !still_mapped {
+        anyhow::bail!(
+            "The plugin did not clean up after itself when the library was unloaded. {}",
+            problems.join(" ")
+        );
+    }
+
+    let library = PluginLibrary::load(library_path)
+        .context("Could not load the library again after unloading it")?;
+    create_and_destroy_instances(&library, host_settings)
+        .context("Error after loading the library again after unloading it")?;
+    drop(library);
+
+    if !problems.is_empty() {
+        Ok(TestStatus::Warning {
+            details: Some(format!(
+                "The plugin did not clean up after itself when the library was unloaded, but the \
+                 library stayed mapped into memory so this would not crash the host. {}",
+                problems.join(" ")
+            )),
+        })
+    } else if still_mapped {
+        Ok(TestStatus::Success {
+            details: Some(String::from(
+                "The library stayed mapped into memory after it was unloaded.",
+            )),
+        })
+    } else {
+        Ok(TestStatus::Success { details: None })
+    }
+}
+
+/// Create, initialize, activate, deactivate, and destroy an instance of every plugin in the
+/// library.
+fn create_and_destroy_instances(
+    library: &PluginLibrary,
+    host_settings: &HostSettings,
+) -> Result<()> {
+    let metadata = library
+        .metadata()
+        .context("Could not query the plugin's metadata")?;
+
+    let host = Host::new(host_settings);
+    for plugin_metadata in &metadata.plugins {
+        let plugin = library
+            .create_plugin(&plugin_metadata.id, host.clone())
+            .with_context(|| format!("Could not create an instance of '{}'", plugin_metadata.id))?;
+        plugin
+            .init()
+            .with_context(|| format!("Error while initializing '{}'", plugin_metadata.id))?;
+        host.handle_callbacks_once();
+
+        plugin.activate(ProcessConfig::default().sample_rate, 1, 512)?;
+        plugin.deactivate();
+        host.handle_callbacks_once();
+    }
+
+    host.callback_error_check()
+        .context("An error occured during a host callback")
+}
+
+/// Find the installed signal handlers that point into one of `ranges`. Returns the signal
+/// numbers along with the handlers' addresses.
+fn signal_handlers_in(ranges: &[Range<usize>]) -> Vec<(libc::c_int, usize)> {
+    (1..=libc::SIGRTMAX())
+        .filter_map(|signal| {
+            let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+            if unsafe { libc::sigaction(signal, std::ptr::null(), &mut action) } != 0 {
+                return None;
+            }
+
+            let handler = action.sa_sigaction;
+            ranges
+                .iter()
+                .any(|range| range.contains(&handler))
+                .then_some((signal, handler))
+        })
+        .collect()
+}
+
+/// Fork the process and call `exit()` in the child process. This runs the exit handlers
+/// registered with `atexit()`. If the plugin registered an exit handler and did not remove it
+/// when it got unloaded, then this will cause the child process to crash. Returns a description
+/// of the problem if the child process did not exit cleanly.
+fn check_exit_handlers() -> Result<Option<String>> {
+    let status = fork::fork_and_wait(EXIT_HANDLERS_TIMEOUT, || unsafe { libc::exit(0) })?;
+    match status {
+        ChildStatus::Exited(0) => Ok(None),
+        ChildStatus::Exited(exit_code) => Ok(Some(format!(
+            "Running the process' exit handlers after the library was unloaded caused the \
+             process to exit with code {exit_code}."
+        ))),
+        ChildStatus::Signaled(signal) => Ok(Some(format!(
+            "Running the process' exit handlers after the library was unloaded caused the \
+             process to terminate with {}. The plugin likely registered an exit handler that \
+             was not removed when the library was unloaded.",
+            fork::signal_name(signal)
+        ))),
+        // Forking a multithreaded process can cause deadlocks that have nothing to do with the
+        // plugin, so this is not treated as a failure
+        ChildStatus::TimedOut => {
+            log::warn!(
+                "The forked process running the exit handlers did not exit within {} seconds, \
+                 skipping the exit handler check",
+                EXIT_HANDLERS_TIMEOUT.as_secs()
+            );
+
+            Ok(None)
+        }
+    }
+}
@@ -0,0 +1,358 @@
+//! Persistent worker processes for running plugin tests out-of-process. Spawning a new process
+//! and loading the plugin library again for every test adds up when running many tests, so
+//! instead a worker process loads the library once and then runs tests until it is shut down.
+
+use anyhow::{Context, Result};
+use parking_lot::{Condvar, Mutex};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdout, ExitStatus, Stdio};
+use tempfile::TempDir;
+
+use super::{
+    child_process_command, crash_details, OutOfProcessSettings, PluginTestCase, TestCase,
+    TestResult, TestStatus,
+};
+use crate::util;
+
+/// Precedes a test's JSON result in a worker process' STDOUT stream. The plugin may also write to
+/// STDOUT, so the worker's output is read line by line and the results are picked out using this
+/// marker. Everything else is passed through to the validator's own STDOUT stream.
+pub const WORKER_RESULT_PREFIX: &str = "clap-validator-worker-result:";
+
+/// A request for a worker process to run a test. These are sent to the worker process' STDIN as
+/// single lines of JSON. The worker answers every request by writing the test's [`TestResult`] to
+/// its STDOUT as a single line of JSON, preceded by [`WORKER_RESULT_PREFIX`].
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WorkerRequest {
+    /// The ID of the plugin within the worker's plugin library that should be tested.
+    pub plugin_id: String,
+    /// The name of the [`PluginTestCase`] to run.
+    pub test_name: String,
+}
+
+/// A pool of worker processes for running plugin tests. Workers are spawned as needed, so when
+/// tests are run in parallel there may be multiple workers for the same plugin library. A worker is
+/// replaced after it crashes, or after it has run a configurable number of tests. At most
+/// `max_workers` workers exist at the same time. Idle workers for other plugin libraries are shut
+/// down to make room for new ones.
+#[derive(Debug)]
+pub struct WorkerPool {
+    /// The settings the worker processes are spawned with.
+    settings: OutOfProcessSettings,
+    /// The number of tests a worker may run before it gets replaced, if set.
+    max_tests_per_worker: Option<NonZeroUsize>,
+    /// The maximum number of worker processes that may exist at the same time.
+    max_workers: NonZeroUsize,
+    state: Mutex<PoolState>,
+    /// Notified when a worker becomes idle or is shut down.
+    worker_released: Condvar,
+}
+
+/// The mutable part of a [`WorkerPool`].
+#[derive(Debug, Default)]
+struct PoolState {
+    /// Workers that are not currently running a test.
+    idle_workers: Vec<Worker>,
+    /// The number of workers that currently exist, including the ones running a test.
+    num_workers: usize,
+    /// The number of workers spawned so far. Used to give every worker its own core dump
+    /// directory.
+    num_spawned: usize,
+}
+
+/// A single worker process that runs tests for one plugin library.
+#[derive(Debug)]
+struct Worker {
+    /// The plugin library this worker has loaded.
+    library_path: PathBuf,
+    child: Child,
+    /// The directory the worker writes its core dumps to, if core dumps are enabled.
+    core_dump_dir: Option<PathBuf>,
+    /// The worker's STDOUT stream. The worker sends its results back through here.
+    stdout: BufReader<ChildStdout>,
+    /// Whether the worker's other output should be passed through to this process' STDOUT stream.
+    echo_output: bool,
+    /// Whether that output should be passed through to this process' STDERR stream instead.
+    echo_to_stderr: bool,
+    /// The directory passed to the worker for the sandbox's files, if it is run in a sandbox.
+    /// This needs to outlive the process.
+    _sandbox_dir: Option<TempDir>,
+    /// The number of tests this worker has been asked to run so far.
+    num_tests: usize,
+}
+
+/// How a test requested from a worker ended.
+enum WorkerOutcome {
+    /// The test finished and the worker sent back the result.
+    Finished(TestResult),
+    /// The worker process exited before the test finished.
+    Exited(ExitStatus),
+}
+
+impl WorkerPool {
+    /// Create an empty worker pool. Worker processes are spawned with `settings` when they are
+    /// first needed, and at most `max_workers` of them exist at the same time.
+    pub fn new(
+        settings: OutOfProcessSettings,
+        max_tests_per_worker: Option<NonZeroUsize>,
+        max_workers: NonZeroUsize,
+    ) -> Self {
+        WorkerPool {
+            settings,
+            max_tests_per_worker,
+            max_workers,
+            state: Mutex::new(PoolState::default()),
+            worker_released: Condvar::new(),
+        }
+    }
+
+    /// Whether `test` can be run in a worker process. Tests that inspect state shared by the entire
+    /// process would be affected by the tests that ran in the same worker before them, so those need
+    /// to be run in a fresh process using [`TestCase::run_out_of_process()`] instead. The same
+    /// applies to all tests when the audio thread is being audited.
+    pub fn supports(&self, test: &PluginTestCase) -> bool {
+        !self.settings.audit_audio_thread && !test.inspects_process_state()
+    }
+
+    /// Run a plugin test in one of the worker processes for the plugin library at `library_path`,
+    /// spawning a new worker if there are no idle ones. If the worker crashes while running the
+    /// test, then the result will have a status of [`TestStatus::Crashed`] like with
+    /// [`TestCase::run_out_of_process()`]. `test` must be [supported][Self::supports()] by the pool.
+    ///
+    /// This will only return an error if the worker process could not be spawned or communicated
+    /// with.
+    pub fn run_test(
+        &self,
+        test: &PluginTestCase,
+        library_path: &Path,
+        plugin_id: &str,
+    ) -> Result<TestResult> {
+        assert!(self.supports(test));
+
+        let mut worker = self.take_worker(library_path)?;
+        let outcome = worker.run_test(test, plugin_id);
+        match outcome {
+            Ok(WorkerOutcome::Finished(result)) => {
+                let worker_exhausted = self
+                    .max_tests_per_worker
+                    .map_or(false, |max_tests| worker.num_tests >= max_tests.get());
+                if worker_exhausted {
+                    self.shut_down_worker(worker);
+                } else {
+                    self.state.lock().idle_workers.push(worker);
+                    self.worker_released.notify_all();
+                }
+
+                Ok(result)
+            }
+            Ok(WorkerOutcome::Exited(exit_status)) => {
+                let details = crash_details(exit_status, worker.core_dump_dir.as_deref());
+                self.shut_down_worker(worker);
+
+                Ok(TestResult {
+                    name: test.to_string(),
+                    description: test.description(),
+                    status: TestStatus::Crashed { details },
+                    benchmark: None,
+                    sandbox_writes: Vec::new(),
+                    sandbox_read_only_dirs: Vec::new(),
+                    plugin_log: Vec::new(),
+                })
+            }
+            Err(err) => {
+                self.shut_down_worker(worker);
+
+                Err(err)
+            }
+        }
+    }
+
+    /// Take an idle worker for the plugin library at `library_path` out of the pool, or spawn a new
+    /// one. If the pool is full, then an idle worker for another library is shut down first, or
+    /// this waits until a worker gets released.
+    fn take_worker(&self, library_path: &Path) -> Result<Worker> {
+        let mut state = self.state.lock();
+        loop {
+            if let Some(idx) = state
+                .idle_workers
+                .iter()
+                .position(|worker| worker.library_path == library_path)
+            {
+                return Ok(state.idle_workers.swap_remove(idx));
+            }
+
+            if state.num_workers < self.max_workers.get() {
+                state.num_workers += 1;
+                state.num_spawned += 1;
+                let worker_idx = state.num_spawned;
+                drop(state);
+
+                return Worker::spawn(library_path, worker_idx, &self.settings).map_err(|err| {
+                    self.state.lock().num_workers -= 1;
+                    self.worker_released.notify_all();
+
+                    err
+                });
+            }
+
+            if state.idle_workers.is_empty() {
+                self.worker_released.wait(&mut state);
+            } else {
+                // Shutting down the worker waits for it to exit, so this is done without holding
+                // the lock
+                let worker = state.idle_workers.remove(0);
+                drop(state);
+                self.shut_down_worker(worker);
+                state = self.state.lock();
+            }
+        }
+    }
+
+    /// Shut down a worker that was taken out of the pool, making room for a new one.
+    fn shut_down_worker(&self, worker: Worker) {
+        drop(worker);
+
+        self.state.lock().num_workers -= 1;
+        self.worker_released.notify_all();
+    }
+}
+
+impl Worker {
+    /// Spawn a new worker process for the plugin library at `library_path`. `worker_idx` is a
+    /// unique number used to name the worker's core dump directory.
+    fn spawn(
+        library_path: &Path,
+        worker_idx: usize,
+        settings: &OutOfProcessSettings,
+    ) -> Result<Self> {
+        let sandbox_dir = if settings.sandbox {
+            Some(tempfile::tempdir().context("Could not create a directory for the sandbox")?)
+        } else {
+            None
+        };
+        // A worker runs many tests, but it only crashes once. Its core dumps are thus stored in a
+        // directory for the worker rather than for the test.
+        let core_dump_dir = if settings.core_dumps {
+            let core_dump_dir = util::validator_temp_dir()
+                .join("workers")
+                .join(format!("worker-{worker_idx}"));
+            fs::create_dir_all(&core_dump_dir)
+                .context("Could not create the directory for the worker's core dumps")?;
+
+            Some(core_dump_dir)
+        } else {
+            None
+        };
+
+        let mut command = child_process_command(
+            settings,
+            "worker",
+            sandbox_dir.as_ref().map(|dir| dir.path()),
+        )?;
+        command
+            .arg(library_path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped());
+        if let Some(core_dump_dir) = &core_dump_dir {
+            command.args([OsStr::new("--core-dump-dir"), core_dump_dir.as_os_str()]);
+        }
+        let mut child = command
+            .spawn()
+            .context("Could not call clap-validator to spawn a worker process")?;
+        let stdout = BufReader::new(
+            child
+                .stdout
+                .take()
+                .expect("The worker's STDOUT stream was not captured"),
+        );
+
+        Ok(Worker {
+            library_path: library_path.to_owned(),
+            child,
+            core_dump_dir,
+            stdout,
+            echo_output: !settings.hide_output,
+            echo_to_stderr: settings.stdout_to_stderr,
+            _sandbox_dir: sandbox_dir,
+            num_tests: 0,
+        })
+    }
+
+    /// Ask the worker to run a test, and wait until it has either finished running the test or
+    /// until the worker process exits.
+    fn run_test(&mut self, test: &PluginTestCase, plugin_id: &str) -> Result<WorkerOutcome> {
+        self.num_tests += 1;
+        let request = WorkerRequest {
+            plugin_id: plugin_id.to_owned(),
+            test_name: test.to_string(),
+        };
+
+        // If the worker already exited, then writing the request will fail. That's handled the
+        // same way as the worker crashing while running the test.
+        let request_json =
+            serde_json::to_string(&request).context("Could not format the request as JSON")?;
+        let stdin = self
+            .child
+            .stdin
+            .as_mut()
+            .expect("The worker's STDIN has already been closed");
+        let _ = writeln!(stdin, "{request_json}").and_then(|()| stdin.flush());
+
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let num_bytes = self
+                .stdout
+                .read_until(b'\n', &mut line)
+                .context("Could not read the worker process' output")?;
+
+            // The worker only closes its STDOUT stream when it exits, so the test did not finish
+            if num_bytes == 0 {
+                let exit_status = self
+                    .child
+                    .wait()
+                    .context("Error while waiting on the worker process")?;
+
+                return Ok(WorkerOutcome::Exited(exit_status));
+            }
+
+            // If the plugin wrote to STDOUT without ending its output with a newline, then the
+            // result will be preceded by the plugin's output on the same line
+            let line = String::from_utf8_lossy(&line);
+            let (output, result_json) = match line.find(WORKER_RESULT_PREFIX) {
+                Some(idx) => (
+                    &line[..idx],
+                    Some(&line[idx + WORKER_RESULT_PREFIX.len()..]),
+                ),
+                None => (&line[..], None),
+            };
+            if self.echo_output && !output.is_empty() {
+                let _ = if self.echo_to_stderr {
+                    std::io::stderr().write_all(output.as_bytes())
+                } else {
+                    std::io::stdout().write_all(output.as_bytes())
+                };
+            }
+
+            if let Some(result_json) = result_json {
+                let result = serde_json::from_str(result_json)
+                    .context("Could not parse the worker process output to JSON")?;
+
+                return Ok(WorkerOutcome::Finished(result));
+            }
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        // Closing STDIN causes the worker to exit after it has unloaded the plugin library
+        drop(self.child.stdin.take());
+        let _ = self.child.wait();
+    }
+}
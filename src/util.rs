@@ -7,6 +7,19 @@ use std::ffi::CStr;
 use std::os::raw::c_char;
 use std::path::PathBuf;
 
+#[cfg(target_os = "linux")]
+pub mod crash;
+#[cfg(target_os = "linux")]
+pub mod epoll;
+#[cfg(target_os = "linux")]
+pub mod fork;
+#[cfg(target_os = "linux")]
+pub mod procfs;
+#[cfg(target_os = "linux")]
+pub mod sandbox;
+#[cfg(target_os = "linux")]
+pub mod seccomp;
+
 // TODO: Remove these attributes once we start implementing host interfaces
 
 /// Assert that the specified pointers are non-null. Panics if this is not the case.
@@ -0,0 +1,126 @@
+//! Utilities for debugging crashes in out-of-process tests. Only available on Linux.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ExitStatus};
+
+/// The signals caused by crashes. These are the signals that are intercepted by
+/// [`pause_on_crash()`].
+const CRASH_SIGNALS: &[libc::c_int] = &[
+    libc::SIGSEGV,
+    libc::SIGBUS,
+    libc::SIGILL,
+    libc::SIGFPE,
+    libc::SIGABRT,
+];
+
+/// Raise the current process' core dump size limit as far as possible, and change its working
+/// directory to `dir`. Unless the system's core pattern says otherwise, core dumps are written to
+/// the working directory. Relative paths should be resolved before calling this function.
+pub fn enable_core_dumps(dir: &Path) -> Result<()> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(libc::RLIMIT_CORE, &mut limit) } != 0 {
+        return Err(std::io::Error::last_os_error())
+            .context("Could not query the core dump size limit");
+    }
+    if limit.rlim_max == 0 {
+        anyhow::bail!("Core dumps have been disabled with a hard resource limit.");
+    }
+
+    limit.rlim_cur = limit.rlim_max;
+    if unsafe { libc::setrlimit(libc::RLIMIT_CORE, &limit) } != 0 {
+        return Err(std::io::Error::last_os_error())
+            .context("Could not raise the core dump size limit");
+    }
+
+    std::env::set_current_dir(dir).with_context(|| {
+        format!(
+            "Could not change the working directory to '{}'",
+            dir.display()
+        )
+    })
+}
+
+/// Describe the core dumps written to `dir` by [`enable_core_dumps()`] after a crash, or explain
+/// why none were found.
+pub fn describe_core_dumps(dir: &Path) -> String {
+    let mut core_dumps: Vec<PathBuf> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("core"))
+        .map(|entry| entry.path())
+        .collect();
+    core_dumps.sort();
+
+    if core_dumps.is_empty() {
+        let core_pattern = fs::read_to_string("/proc/sys/kernel/core_pattern")
+            .map(|pattern| pattern.trim().to_owned())
+            .unwrap_or_default();
+        format!(
+            "No core dump was written to '{}'. The system's core pattern is '{core_pattern}'.",
+            dir.display()
+        )
+    } else {
+        let paths: Vec<String> = core_dumps
+            .iter()
+            .map(|path| format!("'{}'", path.display()))
+            .collect();
+        format!("A core dump has been written to {}.", paths.join(", "))
+    }
+}
+
+/// Install signal handlers that stop the current process with `SIGSTOP` when it crashes, so a
+/// debugger can be attached. When the process is resumed, the default signal handler is restored
+/// and the crash happens as normal.
+pub fn pause_on_crash() -> Result<()> {
+    extern "C" fn handler(_signal: libc::c_int) {
+        unsafe { libc::raise(libc::SIGSTOP) };
+    }
+
+    for &signal in CRASH_SIGNALS {
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        action.sa_sigaction = handler as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESETHAND | libc::SA_ONSTACK;
+        unsafe { libc::sigemptyset(&mut action.sa_mask) };
+
+        if unsafe { libc::sigaction(signal, &action, std::ptr::null_mut()) } != 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Could not install a handler for signal {signal}"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Wait for `child` to exit like [`Child::wait()`]. `on_pause` is called with the child's process
+/// ID the first time the child gets stopped, which happens when it crashes after calling
+/// [`pause_on_crash()`].
+pub fn wait_reporting_pause(child: &Child, mut on_pause: impl FnMut(u32)) -> Result<ExitStatus> {
+    let pid = child.id() as libc::pid_t;
+    let mut reported = false;
+    loop {
+        let mut status = 0;
+        if unsafe { libc::waitpid(pid, &mut status, libc::WUNTRACED) } == -1 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+
+            return Err(err).context("Error while waiting for the child process to exit");
+        }
+
+        if !libc::WIFSTOPPED(status) {
+            return Ok(ExitStatus::from_raw(status));
+        }
+        if !reported {
+            on_pause(child.id());
+            reported = true;
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! A minimal wrapper around Linux' epoll API. Used by the host to poll the file descriptors plugins
+//! register through `clap_host_posix_fd_support`. Only available on Linux.
+
+use anyhow::{Context, Result};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+/// The maximum number of events returned by a single call to [`Epoll::poll()`].
+const MAX_EVENTS: usize = 64;
+
+/// An epoll instance. The file descriptor is closed when this object is dropped.
+#[derive(Debug)]
+pub struct Epoll {
+    epoll_fd: OwnedFd,
+}
+
+impl Epoll {
+    /// Create a new epoll instance.
+    pub fn new() -> Result<Self> {
+        let epoll_fd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if epoll_fd < 0 {
+            return Err(std::io::Error::last_os_error())
+                .context("Could not create an epoll instance");
+        }
+
+        Ok(Self {
+            epoll_fd: unsafe { OwnedFd::from_raw_fd(epoll_fd) },
+        })
+    }
+
+    /// Start watching `fd` for `events`, which is a combination of `EPOLLIN`, `EPOLLOUT` and
+    /// similar flags. Errors and hangups are always reported.
+    pub fn add(&self, fd: RawFd, events: u32) -> Result<()> {
+        self.ctl(libc::EPOLL_CTL_ADD, fd, events)
+    }
+
+    /// Change the events `fd` is being watched for.
+    pub fn modify(&self, fd: RawFd, events: u32) -> Result<()> {
+        self.ctl(libc::EPOLL_CTL_MOD, fd, events)
+    }
+
+    /// Stop watching `fd`.
+    pub fn delete(&self, fd: RawFd) -> Result<()> {
+        self.ctl(libc::EPOLL_CTL_DEL, fd, 0)
+    }
+
+    /// Return the file descriptors that are ready along with their events, without blocking.
+    pub fn poll(&self) -> Result<Vec<(RawFd, u32)>> {
+        let mut events = [libc::epoll_event { events: 0, u64: 0 }; MAX_EVENTS];
+        let num_events = unsafe {
+            libc::epoll_wait(
+                self.epoll_fd.as_raw_fd(),
+                events.as_mut_ptr(),
+                MAX_EVENTS as libc::c_int,
+                0,
+            )
+        };
+        if num_events < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                return Ok(Vec::new());
+            }
+
+            return Err(err).context("Error while polling the epoll instance");
+        }
+
+        Ok(events[..num_events as usize]
+            .iter()
+            .map(|event| (event.u64 as RawFd, event.events))
+            .collect())
+    }
+
+    fn ctl(&self, op: libc::c_int, fd: RawFd, events: u32) -> Result<()> {
+        let mut event = libc::epoll_event {
+            events,
+            u64: fd as u64,
+        };
+        if unsafe { libc::epoll_ctl(self.epoll_fd.as_raw_fd(), op, fd, &mut event) } != 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("'epoll_ctl()' failed for file descriptor {fd}"));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+//! Running code in a forked child process. Only available on Linux.
+
+use anyhow::{Context, Result};
+use std::ffi::CStr;
+use std::time::{Duration, Instant};
+
+/// How a forked child process terminated. See [`fork_and_wait()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChildStatus {
+    /// The child process exited normally with this exit code.
+    Exited(libc::c_int),
+    /// The child process was terminated by this signal.
+    Signaled(libc::c_int),
+    /// The child process did not terminate in time, and it has been killed.
+    TimedOut,
+}
+
+/// Fork the process, run `child` in the child process, and exit the child process with the exit
+/// code returned by `child`. The child process exits using `_exit()`, so exit handlers and
+/// destructors are not run. If the child process does not terminate within `timeout`, then it is
+/// killed.
+///
+/// Only the calling thread exists in the child process. Any locks held by other threads at the
+/// time of the fork will stay locked forever in the child process, so `child` should do as little
+/// as possible.
+pub fn fork_and_wait<F>(timeout: Duration, child: F) -> Result<ChildStatus>
+where
+    F: FnOnce() -> libc::c_int,
+{
+    // Any buffered C stdio output would otherwise be written twice
+    unsafe { libc::fflush(std::ptr::null_mut()) };
+
+    let pid = unsafe { libc::fork() };
+    if pid == -1 {
+        return Err(std::io::Error::last_os_error()).context("Could not fork the process");
+    } else if pid == 0 {
+        let exit_code = child();
+        unsafe { libc::_exit(exit_code) };
+    }
+
+    let deadline = Instant::now() + timeout;
+    let mut status = 0;
+    loop {
+        match unsafe { libc::waitpid(pid, &mut status, libc::WNOHANG) } {
+            -1 => {
+                return Err(std::io::Error::last_os_error())
+                    .context("Error while waiting for the forked process to exit")
+            }
+            0 if Instant::now() >= deadline => {
+                unsafe {
+                    libc::kill(pid, libc::SIGKILL);
+                    libc::waitpid(pid, &mut status, 0);
+                }
+
+                return Ok(ChildStatus::TimedOut);
+            }
+            0 => std::thread::sleep(Duration::from_millis(10)),
+            _ => break,
+        }
+    }
+
+    if libc::WIFSIGNALED(status) {
+        Ok(ChildStatus::Signaled(libc::WTERMSIG(status)))
+    } else {
+        Ok(ChildStatus::Exited(libc::WEXITSTATUS(status)))
+    }
+}
+
+/// Get a human readable name for a signal, e.g. `'Segmentation fault' (signal 11)`.
+pub fn signal_name(signal: libc::c_int) -> String {
+    let description = unsafe { libc::strsignal(signal) };
+    if description.is_null() {
+        format!("signal {signal}")
+    } else {
+        format!(
+            "'{}' (signal {signal})",
+            unsafe { CStr::from_ptr(description) }.to_string_lossy()
+        )
+    }
+}
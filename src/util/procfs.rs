@@ -0,0 +1,92 @@
+//! Querying information about the current process through `/proc/self`. Only available on Linux.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+/// A snapshot of the current process' resource usage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceUsage {
+    /// The process' resident set size, in bytes.
+    pub rss_bytes: u64,
+    /// The number of open file descriptors.
+    pub num_fds: usize,
+    /// The number of threads in the process.
+    pub num_threads: usize,
+}
+
+impl ResourceUsage {
+    /// Take a snapshot of the current process' resource usage.
+    pub fn current() -> Result<Self> {
+        // The second field in `statm` is the resident set size in pages
+        let statm =
+            fs::read_to_string("/proc/self/statm").context("Could not read '/proc/self/statm'")?;
+        let rss_pages: u64 = statm
+            .split_whitespace()
+            .nth(1)
+            .and_then(|pages| pages.parse().ok())
+            .with_context(|| format!("Could not parse '/proc/self/statm': '{}'", statm.trim()))?;
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u64;
+
+        // The directory listing itself opens a file descriptor, so that one needs to be subtracted
+        let num_fds = fs::read_dir("/proc/self/fd")
+            .context("Could not list '/proc/self/fd'")?
+            .count()
+            .saturating_sub(1);
+
+        Ok(ResourceUsage {
+            rss_bytes: rss_pages * page_size,
+            num_fds,
+            num_threads: thread_ids()?.len(),
+        })
+    }
+}
+
+/// Get the IDs of all threads in the current process.
+pub fn thread_ids() -> Result<Vec<libc::pid_t>> {
+    let mut ids = Vec::new();
+    for entry in fs::read_dir("/proc/self/task").context("Could not list '/proc/self/task'")? {
+        let entry = entry.context("Could not list '/proc/self/task'")?;
+        if let Some(id) = entry.file_name().to_str().and_then(|id| id.parse().ok()) {
+            ids.push(id);
+        }
+    }
+
+    Ok(ids)
+}
+
+/// Get the name of one of the current process' threads, or `None` if the thread no longer exists.
+pub fn thread_name(thread_id: libc::pid_t) -> Option<String> {
+    fs::read_to_string(format!("/proc/self/task/{thread_id}/comm"))
+        .ok()
+        .map(|name| name.trim_end().to_owned())
+}
+
+/// Get the address ranges of all memory mappings of the file at `path`. The path needs to be
+/// canonicalized since that is how the kernel reports it.
+pub fn mapped_ranges(path: &Path) -> Result<Vec<Range<usize>>> {
+    let maps = fs::read_to_string("/proc/self/maps").context("Could not read '/proc/self/maps'")?;
+
+    let mut ranges = Vec::new();
+    for line in maps.lines() {
+        // Lines look like `<start>-<end> <perms> <offset> <dev> <inode>   <path>`. The path is
+        // the only field that may contain spaces.
+        let mut fields = line.splitn(6, ' ');
+        let range = fields.next().unwrap_or_default();
+        let mapped_path = fields.nth(4).unwrap_or_default().trim_start();
+        if Path::new(mapped_path) != path {
+            continue;
+        }
+
+        let parsed_range = range.split_once('-').and_then(|(start, end)| {
+            Some(usize::from_str_radix(start, 16).ok()?..usize::from_str_radix(end, 16).ok()?)
+        });
+        match parsed_range {
+            Some(range) => ranges.push(range),
+            None => anyhow::bail!("Could not parse '/proc/self/maps' line: '{line}'"),
+        }
+    }
+
+    Ok(ranges)
+}
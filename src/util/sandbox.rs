@@ -0,0 +1,397 @@
+//! Running tests in a sandbox using Linux user, mount, and network namespaces. Only available on
+//! Linux.
+
+use anyhow::{Context, Result};
+use std::ffi::{CString, OsString};
+use std::fs;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use walkdir::WalkDir;
+
+use super::fork::{self, ChildStatus};
+
+/// The namespaces the sandboxed process is moved into. The new network namespace only contains a
+/// loopback interface that is not brought up, so the plugin won't have any network access.
+const NAMESPACE_FLAGS: libc::c_int = libc::CLONE_NEWUSER | libc::CLONE_NEWNS | libc::CLONE_NEWNET;
+/// How long to wait for the forked process checking whether the namespaces can be created.
+const SUPPORT_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Directories besides the home directory that are replaced by overlays. Writes to these
+/// directories would otherwise persist after the test. Other writable mounts, like `/dev/shm` and
+/// `$XDG_RUNTIME_DIR`, are found through [`writable_mounts()`].
+const EXTRA_OVERLAY_DIRS: &[&str] = &["/var/tmp"];
+/// File systems that are never covered by overlays. These don't contain regular files the plugin
+/// could leave behind.
+const PSEUDO_FILE_SYSTEMS: &[&str] = &[
+    "autofs",
+    "binfmt_misc",
+    "bpf",
+    "cgroup",
+    "cgroup2",
+    "configfs",
+    "debugfs",
+    "devpts",
+    "devtmpfs",
+    "efivarfs",
+    "fusectl",
+    "hugetlbfs",
+    "mqueue",
+    "nsfs",
+    "proc",
+    "pstore",
+    "securityfs",
+    "selinuxfs",
+    "sysfs",
+    "tracefs",
+];
+
+/// A sandbox the current process has been moved into using [`Sandbox::enter()`]. The home
+/// directory and all other writable mounts are covered by copy-on-write overlays backed by a
+/// tmpfs, so the plugin can write to them without affecting the actual file system. The system's
+/// temporary directory is left as is, since that's where the test results are written to.
+#[derive(Debug)]
+pub struct Sandbox {
+    /// The overlays mounted over the home directory, the directories from [`EXTRA_OVERLAY_DIRS`],
+    /// and the other writable mounts.
+    overlays: Vec<Overlay>,
+    /// Directories that should have been covered by an overlay, but that were made read-only
+    /// instead because the overlay could not be mounted. Writes to these directories fail, and
+    /// they are not recorded.
+    read_only_dirs: Vec<PathBuf>,
+}
+
+/// A copy-on-write overlay over a directory.
+#[derive(Debug)]
+struct Overlay {
+    /// The directory the overlay has been mounted on.
+    target: PathBuf,
+    /// The overlay's upper directory. This contains all files that were written to, created, or
+    /// removed (as whiteout files) in the overlay.
+    upper_dir: PathBuf,
+}
+
+/// Check whether the current user can create the namespaces needed for the sandbox. This is done in
+/// a forked process, since a process can only be moved to a new user namespace once.
+pub fn check_supported() -> Result<()> {
+    let status = fork::fork_and_wait(SUPPORT_CHECK_TIMEOUT, || {
+        if unsafe { libc::unshare(NAMESPACE_FLAGS) } == 0 {
+            0
+        } else {
+            std::io::Error::last_os_error().raw_os_error().unwrap_or(1)
+        }
+    })?;
+
+    match status {
+        ChildStatus::Exited(0) => Ok(()),
+        ChildStatus::Exited(errno) => Err(std::io::Error::from_raw_os_error(errno)).context(
+            "Could not create the user, mount, and network namespaces. Unprivileged user \
+             namespaces may be disabled on this system.",
+        ),
+        ChildStatus::Signaled(signal) => anyhow::bail!(
+            "The process checking for namespace support terminated with {}.",
+            fork::signal_name(signal)
+        ),
+        ChildStatus::TimedOut => anyhow::bail!(
+            "The process checking for namespace support did not exit within {} seconds.",
+            SUPPORT_CHECK_TIMEOUT.as_secs()
+        ),
+    }
+}
+
+impl Sandbox {
+    /// Move the current process into new user, mount, and network namespaces, and cover the home
+    /// directory and the other writable mounts with overlays. `scratch_dir` must be an empty
+    /// directory that the backing tmpfs can be mounted on. This needs to be called before spawning
+    /// any threads, since a multithreaded process cannot enter a new user namespace.
+    ///
+    /// If the overlay for a directory cannot be mounted, then that directory is made read-only
+    /// instead. Writes to those directories will fail, and they will not be recorded. These
+    /// directories are listed in [`read_only_dirs()`][Self::read_only_dirs()].
+    pub fn enter(scratch_dir: &Path) -> Result<Self> {
+        // These all need to be queried before entering the new namespaces
+        let uid = unsafe { libc::getuid() };
+        let gid = unsafe { libc::getgid() };
+        let home_dir = std::env::var_os("HOME")
+            .map(PathBuf::from)
+            .context("'$HOME' is not set")?;
+        let mut overlay_targets = vec![fs::canonicalize(&home_dir)
+            .with_context(|| format!("Could not canonicalize '{}'", home_dir.display()))?];
+        overlay_targets.extend(
+            EXTRA_OVERLAY_DIRS
+                .iter()
+                .filter_map(|path| fs::canonicalize(path).ok()),
+        );
+        let temp_dir = fs::canonicalize(std::env::temp_dir())
+            .context("Could not canonicalize the temporary directory")?;
+        let writable_mounts = writable_mounts(&temp_dir, &overlay_targets)?;
+        overlay_targets.extend(writable_mounts);
+
+        if unsafe { libc::unshare(NAMESPACE_FLAGS) } != 0 {
+            return Err(std::io::Error::last_os_error())
+                .context("Could not create the user, mount, and network namespaces");
+        }
+
+        // The process keeps its user and group IDs inside of the namespace. The group mapping can
+        // only be written after disabling `setgroups()`.
+        fs::write("/proc/self/uid_map", format!("{uid} {uid} 1"))
+            .context("Could not write the user ID mapping")?;
+        fs::write("/proc/self/setgroups", "deny").context("Could not disable 'setgroups()'")?;
+        fs::write("/proc/self/gid_map", format!("{gid} {gid} 1"))
+            .context("Could not write the group ID mapping")?;
+
+        // None of the mounts should propagate back to the original mount namespace
+        mount(
+            None,
+            Path::new("/"),
+            None,
+            libc::MS_REC | libc::MS_PRIVATE,
+            None,
+        )?;
+        mount(
+            Some(Path::new("tmpfs")),
+            scratch_dir,
+            Some("tmpfs"),
+            0,
+            None,
+        )?;
+
+        // If the temporary directory is inside of one of the overlaid directories, then the
+        // original directory is mounted back on top of the overlay so it stays writable
+        let temp_dir_mount = if overlay_targets
+            .iter()
+            .any(|target| temp_dir.starts_with(target))
+        {
+            let temp_dir_mount = scratch_dir.join("temp");
+            fs::create_dir(&temp_dir_mount)
+                .context("Could not create the temporary directory's mount point")?;
+            mount(Some(&temp_dir), &temp_dir_mount, None, libc::MS_BIND, None)?;
+
+            Some(temp_dir_mount)
+        } else {
+            None
+        };
+
+        let mut overlays = Vec::new();
+        let mut read_only_dirs = Vec::new();
+        for (idx, target) in overlay_targets.into_iter().enumerate() {
+            let upper_dir = scratch_dir.join(format!("upper-{idx}"));
+            let work_dir = scratch_dir.join(format!("work-{idx}"));
+            fs::create_dir(&upper_dir).context("Could not create the overlay's upper directory")?;
+            fs::create_dir(&work_dir).context("Could not create the overlay's work directory")?;
+
+            let options = format!(
+                "lowerdir={},upperdir={},workdir={},userxattr",
+                target.display(),
+                upper_dir.display(),
+                work_dir.display()
+            );
+            match mount(
+                Some(Path::new("overlay")),
+                &target,
+                Some("overlay"),
+                0,
+                Some(&options),
+            ) {
+                Ok(()) => overlays.push(Overlay { target, upper_dir }),
+                Err(err) => {
+                    log::warn!(
+                        "Could not mount an overlay over '{}', making it read-only instead: \
+                         {err:#}",
+                        target.display()
+                    );
+
+                    mount(
+                        Some(&target),
+                        &target,
+                        None,
+                        libc::MS_BIND | libc::MS_REC,
+                        None,
+                    )?;
+                    mount(
+                        None,
+                        &target,
+                        None,
+                        libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY,
+                        None,
+                    )?;
+                    read_only_dirs.push(target);
+                }
+            }
+        }
+
+        if let Some(temp_dir_mount) = temp_dir_mount {
+            mount(Some(&temp_dir_mount), &temp_dir, None, libc::MS_BIND, None)?;
+        }
+
+        Ok(Sandbox {
+            overlays,
+            read_only_dirs,
+        })
+    }
+
+    /// Get the directories that were made read-only because their overlays could not be mounted.
+    /// Writes to these directories fail, so they can't be listed by
+    /// [`written_paths()`][Self::written_paths()].
+    pub fn read_only_dirs(&self) -> &[PathBuf] {
+        &self.read_only_dirs
+    }
+
+    /// Get the paths of all files that were written to, created, or removed in the overlaid
+    /// directories since entering the sandbox. Directories are not included since those are also
+    /// created when writing to a file in a nested directory.
+    pub fn written_paths(&self) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for overlay in &self.overlays {
+            for entry in WalkDir::new(&overlay.upper_dir)
+                .min_depth(1)
+                .sort_by_file_name()
+            {
+                let entry = entry
+                    .with_context(|| format!("Could not list '{}'", overlay.upper_dir.display()))?;
+                if entry.file_type().is_dir() {
+                    continue;
+                }
+
+                let relative_path = entry
+                    .path()
+                    .strip_prefix(&overlay.upper_dir)
+                    .expect("The path is not inside of the overlay's upper directory");
+                paths.push(overlay.target.join(relative_path));
+            }
+        }
+
+        Ok(paths)
+    }
+}
+
+/// Find the mount points besides `overlay_targets` that the current user can write to, so they can
+/// also be covered by overlays. Mounts inside of or containing the temporary directory, mounts
+/// inside of `overlay_targets`, and pseudo file systems are skipped. Mounts that have other mounts
+/// nested inside of them are also skipped, since mounting an overlay on top of them would hide the
+/// nested mounts.
+fn writable_mounts(temp_dir: &Path, overlay_targets: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mount_info =
+        fs::read("/proc/self/mountinfo").context("Could not read '/proc/self/mountinfo'")?;
+
+    // See proc(5) for the format. The fields after the optional fields are preceded by a dash.
+    let mut mounts: Vec<(PathBuf, bool, String)> = Vec::new();
+    for line in mount_info.split(|&c| c == b'\n') {
+        let fields: Vec<&[u8]> = line.split(|&c| c == b' ').collect();
+        let separator_idx = match fields.iter().position(|field| *field == b"-") {
+            Some(idx) if idx >= 6 && fields.len() > idx + 1 => idx,
+            _ => continue,
+        };
+
+        let mount_point = PathBuf::from(OsString::from_vec(unescape_mount_info(fields[4])));
+        let is_read_write = fields[5]
+            .split(|&c| c == b',')
+            .any(|option| option == b"rw");
+        let fs_type = String::from_utf8_lossy(fields[separator_idx + 1]).into_owned();
+        mounts.push((mount_point, is_read_write, fs_type));
+    }
+
+    let mut writable_mounts: Vec<PathBuf> = Vec::new();
+    for (mount_point, is_read_write, fs_type) in &mounts {
+        if !is_read_write
+            || mount_point == Path::new("/")
+            || PSEUDO_FILE_SYSTEMS.contains(&fs_type.as_str())
+            || mount_point.starts_with(temp_dir)
+            || temp_dir.starts_with(mount_point)
+            || overlay_targets
+                .iter()
+                .any(|target| mount_point.starts_with(target))
+            || writable_mounts.contains(mount_point)
+            || !mount_point.is_dir()
+            || !is_writable(mount_point)
+        {
+            continue;
+        }
+
+        if mounts
+            .iter()
+            .any(|(other, _, _)| other != mount_point && other.starts_with(mount_point))
+        {
+            log::warn!(
+                "Not covering '{}' with an overlay since it contains other mounts. Writes to this \
+                 directory will not be recorded.",
+                mount_point.display()
+            );
+            continue;
+        }
+
+        writable_mounts.push(mount_point.clone());
+    }
+
+    Ok(writable_mounts)
+}
+
+/// Decode the octal escape sequences used for spaces and other special characters in
+/// `/proc/self/mountinfo`.
+fn unescape_mount_info(field: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(field.len());
+    let mut idx = 0;
+    while idx < field.len() {
+        let escaped = field
+            .get(idx + 1..idx + 4)
+            .filter(|digits| field[idx] == b'\\' && digits.iter().all(u8::is_ascii_digit))
+            .and_then(|digits| u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok());
+        match escaped {
+            Some(c) => {
+                result.push(c);
+                idx += 4;
+            }
+            None => {
+                result.push(field[idx]);
+                idx += 1;
+            }
+        }
+    }
+
+    result
+}
+
+/// Whether the current user can write to `path`.
+fn is_writable(path: &Path) -> bool {
+    match CString::new(path.as_os_str().as_bytes()) {
+        Ok(path) => unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 },
+        Err(_) => false,
+    }
+}
+
+/// A thin wrapper around `mount()`.
+fn mount(
+    source: Option<&Path>,
+    target: &Path,
+    fstype: Option<&str>,
+    flags: libc::c_ulong,
+    data: Option<&str>,
+) -> Result<()> {
+    let source = source
+        .map(|source| CString::new(source.as_os_str().as_bytes()))
+        .transpose()?;
+    let target_cstr = CString::new(target.as_os_str().as_bytes())?;
+    let fstype = fstype.map(CString::new).transpose()?;
+    let data = data.map(CString::new).transpose()?;
+
+    let result = unsafe {
+        libc::mount(
+            source
+                .as_ref()
+                .map_or(std::ptr::null(), |source| source.as_ptr()),
+            target_cstr.as_ptr(),
+            fstype
+                .as_ref()
+                .map_or(std::ptr::null(), |fstype| fstype.as_ptr()),
+            flags,
+            data.as_ref().map_or(std::ptr::null(), |data| {
+                data.as_ptr() as *const libc::c_void
+            }),
+        )
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Could not mount '{}'", target.display()));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,402 @@
+//! A small wrapper around seccomp's user notification mechanism for observing the system calls made
+//! by a thread. This is only available on Linux, and only on x86_64 and AArch64. `libc` does not
+//! yet expose the user notification API, so the relevant kernel definitions are replicated here.
+
+use anyhow::{Context, Result};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+
+const SECCOMP_SET_MODE_FILTER: libc::c_ulong = 1;
+const SECCOMP_FILTER_FLAG_NEW_LISTENER: libc::c_ulong = 1 << 3;
+const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+const SECCOMP_RET_USER_NOTIF: u32 = 0x7fc0_0000;
+const SECCOMP_USER_NOTIF_FLAG_CONTINUE: u32 = 1 << 0;
+/// `_IOWR('!', 0, struct seccomp_notif)`.
+const SECCOMP_IOCTL_NOTIF_RECV: u32 = 0xc050_2100;
+/// `_IOWR('!', 1, struct seccomp_notif_resp)`.
+const SECCOMP_IOCTL_NOTIF_SEND: u32 = 0xc018_2101;
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_003e);
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_00b7);
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const AUDIT_ARCH: Option<u32> = None;
+
+const BPF_LD_W_ABS: u16 = 0x20;
+const BPF_ALU_AND_K: u16 = 0x54;
+const BPF_JMP_JEQ_K: u16 = 0x15;
+const BPF_RET_K: u16 = 0x06;
+
+/// Offsets into `struct seccomp_data`. The argument offsets point to the lower 32 bits of the
+/// argument on little endian platforms.
+const SECCOMP_DATA_NR_OFFSET: u32 = 0;
+const SECCOMP_DATA_ARCH_OFFSET: u32 = 4;
+const SECCOMP_DATA_ARGS_OFFSET: u32 = 16;
+
+/// `struct seccomp_data`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct SeccompData {
+    nr: libc::c_int,
+    arch: u32,
+    instruction_pointer: u64,
+    args: [u64; 6],
+}
+
+/// `struct seccomp_notif`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct SeccompNotif {
+    id: u64,
+    pid: u32,
+    flags: u32,
+    data: SeccompData,
+}
+
+/// `struct seccomp_notif_resp`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct SeccompNotifResp {
+    id: u64,
+    val: i64,
+    error: i32,
+    flags: u32,
+}
+
+/// Used to hand the notification listener's file descriptor over to the supervisor thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Handoff {
+    /// The supervisor thread has not started running yet.
+    Starting,
+    /// The supervisor thread is waiting for the filter to be installed.
+    Waiting,
+    /// The filter has been installed, and this is the listener's file descriptor.
+    Installed(libc::c_int),
+    /// The filter could not be installed.
+    Failed,
+}
+
+/// A system call that should be intercepted by a [`SyscallMonitor`].
+#[derive(Debug, Clone, Copy)]
+pub struct SyscallFilter {
+    /// The system call's number, e.g. `libc::SYS_futex`.
+    pub nr: libc::c_long,
+    /// If set, then the system call is only intercepted if the lower 32 bits of argument `.0`,
+    /// masked with `.1`, match any of the values in `.2`.
+    pub arg: Option<(usize, u32, &'static [u32])>,
+}
+
+/// A system call intercepted by a [`SyscallMonitor`].
+#[derive(Debug, Clone, Copy)]
+pub struct Syscall {
+    /// The ID of the thread that made the system call.
+    pub tid: libc::pid_t,
+    /// The system call's number.
+    pub nr: libc::c_long,
+    /// The system call's arguments.
+    pub args: [u64; 6],
+}
+
+/// The handler function passed to [`SyscallMonitor::install()`].
+type Handler = Box<dyn FnMut(&Syscall) + Send>;
+
+/// Observes the system calls made by a thread, and by any threads spawned from that thread after
+/// the monitor was installed. Intercepted system calls are passed to a handler function on a
+/// separate supervisor thread, after which the system call is allowed to continue as normal.
+#[derive(Debug)]
+pub struct SyscallMonitor {
+    /// An eventfd that is written to in order to make the supervisor thread exit.
+    stop_fd: libc::c_int,
+    /// The supervisor thread. This is only `None` while dropping the monitor.
+    supervisor: Option<JoinHandle<()>>,
+}
+
+impl SyscallFilter {
+    /// Intercept every call to this system call.
+    pub const fn any(nr: libc::c_long) -> Self {
+        SyscallFilter { nr, arg: None }
+    }
+}
+
+impl SyscallMonitor {
+    /// Install a seccomp filter on the **calling** thread that intercepts the system calls matching
+    /// `filters`. `handler` is called on a supervisor thread for every intercepted system call.
+    /// The thread making the system call is blocked until the handler returns, so the handler must
+    /// not acquire any locks the monitored thread may be holding. This also sets the thread's
+    /// `no_new_privs` attribute, which cannot be undone.
+    ///
+    /// Seccomp filters cannot be removed again, and any threads spawned by the monitored thread
+    /// inherit the filter. Once the monitor is stopped using [`stop()`][Self::stop()] or dropped,
+    /// the supervisor thread terminates and the intercepted system calls from any remaining
+    /// threads using the filter fail with `ENOSYS`. The monitor should thus outlive the monitored
+    /// thread.
+    pub fn install<F>(filters: &[SyscallFilter], handler: F) -> Result<Self>
+    where
+        F: FnMut(&Syscall) + Send + 'static,
+    {
+        let program = build_filter_program(filters)?;
+        let mut handler: Handler = Box::new(handler);
+
+        let stop_fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+        if stop_fd < 0 {
+            anyhow::bail!(
+                "Could not create an eventfd for the syscall monitor: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+
+        // The supervisor thread needs to be spawned before installing the filter. Otherwise it
+        // would inherit it.
+        let handoff = Arc::new((Mutex::new(Handoff::Starting), Condvar::new()));
+        let supervisor = std::thread::Builder::new()
+            .name(String::from("syscall-monitor"))
+            .spawn({
+                let handoff = handoff.clone();
+                move || {
+                    let (state, condvar) = &*handoff;
+                    let mut state = state.lock().unwrap();
+                    *state = Handoff::Waiting;
+                    condvar.notify_all();
+
+                    let state = condvar
+                        .wait_while(state, |state| *state == Handoff::Waiting)
+                        .unwrap();
+                    if let Handoff::Installed(listener_fd) = *state {
+                        drop(state);
+                        supervise(listener_fd, stop_fd, &mut handler);
+                        unsafe { libc::close(listener_fd) };
+                    }
+                }
+            });
+        let supervisor = match supervisor {
+            Ok(supervisor) => supervisor,
+            Err(err) => {
+                unsafe { libc::close(stop_fd) };
+                return Err(err).context("Could not spawn the syscall monitor thread");
+            }
+        };
+
+        // The filter can only be installed once the supervisor thread is running. Starting a
+        // thread registers thread-local destructors, which acquires the dynamic linker's lock. If
+        // the monitored thread holds that lock while making an intercepted system call, for
+        // instance while loading a library, then the two threads would deadlock. The standard
+        // library's mutexes and condition variables don't use any thread-local storage.
+        let (state, condvar) = &*handoff;
+        drop(
+            condvar
+                .wait_while(state.lock().unwrap(), |state| *state == Handoff::Starting)
+                .unwrap(),
+        );
+
+        let listener_fd = unsafe { install_filter(&program) };
+        let mut state = state.lock().unwrap();
+        match listener_fd {
+            Ok(listener_fd) => {
+                *state = Handoff::Installed(listener_fd);
+                condvar.notify_all();
+
+                Ok(SyscallMonitor {
+                    stop_fd,
+                    supervisor: Some(supervisor),
+                })
+            }
+            Err(err) => {
+                // This makes the supervisor thread exit immediately
+                *state = Handoff::Failed;
+                condvar.notify_all();
+                drop(state);
+                let _ = supervisor.join();
+                unsafe { libc::close(stop_fd) };
+
+                Err(err)
+            }
+        }
+    }
+
+    /// Stop the supervisor thread and wait for it to terminate. Once this returns the handler will
+    /// not be called again. See [`install()`][Self::install()] for the caveats.
+    pub fn stop(self) {
+        drop(self);
+    }
+}
+
+impl Drop for SyscallMonitor {
+    fn drop(&mut self) {
+        let value: u64 = 1;
+        unsafe {
+            libc::write(
+                self.stop_fd,
+                &value as *const u64 as *const libc::c_void,
+                std::mem::size_of::<u64>(),
+            )
+        };
+
+        if let Some(supervisor) = self.supervisor.take() {
+            if supervisor.join().is_err() {
+                log::error!("The syscall monitor thread panicked");
+            }
+        }
+        unsafe { libc::close(self.stop_fd) };
+    }
+}
+
+/// Build a BPF program that returns `SECCOMP_RET_USER_NOTIF` for the system calls matching
+/// `filters`, and `SECCOMP_RET_ALLOW` for everything else.
+fn build_filter_program(filters: &[SyscallFilter]) -> Result<Vec<libc::sock_filter>> {
+    let audit_arch =
+        AUDIT_ARCH.context("Syscall monitoring is not supported on this architecture")?;
+    let stmt = |code, k| libc::sock_filter {
+        code,
+        jt: 0,
+        jf: 0,
+        k,
+    };
+    let jump = |k, jt, jf| libc::sock_filter {
+        code: BPF_JMP_JEQ_K,
+        jt,
+        jf,
+        k,
+    };
+
+    // System calls from other architectures' ABIs have different numbers, so those are ignored
+    let mut program = vec![
+        stmt(BPF_LD_W_ABS, SECCOMP_DATA_ARCH_OFFSET),
+        jump(audit_arch, 1, 0),
+        stmt(BPF_RET_K, SECCOMP_RET_ALLOW),
+    ];
+
+    // Every filter is a self-contained block that reloads the system call number and falls through
+    // to the next block if it doesn't match
+    for filter in filters {
+        program.push(stmt(BPF_LD_W_ABS, SECCOMP_DATA_NR_OFFSET));
+        match filter.arg {
+            None => {
+                program.push(jump(filter.nr as u32, 0, 1));
+                program.push(stmt(BPF_RET_K, SECCOMP_RET_USER_NOTIF));
+            }
+            Some((arg_idx, mask, values)) => {
+                anyhow::ensure!(arg_idx < 6, "Invalid syscall argument index {arg_idx}");
+                let block_len = 2 + (values.len() * 2);
+                anyhow::ensure!(
+                    block_len <= u8::MAX as usize,
+                    "Too many syscall argument values"
+                );
+
+                program.push(jump(filter.nr as u32, 0, block_len as u8));
+                program.push(stmt(
+                    BPF_LD_W_ABS,
+                    SECCOMP_DATA_ARGS_OFFSET + (arg_idx as u32 * 8),
+                ));
+                program.push(stmt(BPF_ALU_AND_K, mask));
+                for value in values {
+                    program.push(jump(*value, 0, 1));
+                    program.push(stmt(BPF_RET_K, SECCOMP_RET_USER_NOTIF));
+                }
+            }
+        }
+    }
+    program.push(stmt(BPF_RET_K, SECCOMP_RET_ALLOW));
+
+    Ok(program)
+}
+
+/// Install the filter program on the calling thread and return the notification listener's file
+/// descriptor.
+unsafe fn install_filter(program: &[libc::sock_filter]) -> Result<libc::c_int> {
+    // This is required to install seccomp filters without `CAP_SYS_ADMIN`
+    if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+        anyhow::bail!(
+            "Could not set the 'no_new_privs' thread attribute: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+
+    let program = libc::sock_fprog {
+        len: program.len() as libc::c_ushort,
+        filter: program.as_ptr() as *mut libc::sock_filter,
+    };
+    let listener_fd = libc::syscall(
+        libc::SYS_seccomp,
+        SECCOMP_SET_MODE_FILTER,
+        SECCOMP_FILTER_FLAG_NEW_LISTENER,
+        &program as *const libc::sock_fprog,
+    );
+    if listener_fd < 0 {
+        anyhow::bail!(
+            "Could not install the seccomp filter, the kernel may not support seccomp user \
+             notifications: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+
+    Ok(listener_fd as libc::c_int)
+}
+
+/// Receive and respond to notifications until `stop_fd` becomes readable, or until all threads
+/// using the filter have exited. The handler is called for every notification.
+fn supervise(listener_fd: libc::c_int, stop_fd: libc::c_int, handler: &mut Handler) {
+    loop {
+        let mut poll_fds = [
+            libc::pollfd {
+                fd: listener_fd,
+                events: libc::POLLIN,
+                revents: 0,
+            },
+            libc::pollfd {
+                fd: stop_fd,
+                events: libc::POLLIN,
+                revents: 0,
+            },
+        ];
+        let num_ready = unsafe { libc::poll(poll_fds.as_mut_ptr(), poll_fds.len() as _, -1) };
+        if num_ready <= 0 {
+            continue;
+        }
+        if poll_fds[1].revents != 0 {
+            break;
+        }
+
+        let [listener_poll_fd, _] = poll_fds;
+        if listener_poll_fd.revents & libc::POLLIN == 0 {
+            if listener_poll_fd.revents & (libc::POLLHUP | libc::POLLERR | libc::POLLNVAL) != 0 {
+                break;
+            }
+
+            continue;
+        }
+
+        let mut notification = SeccompNotif::default();
+        let result = unsafe {
+            libc::ioctl(
+                listener_fd,
+                SECCOMP_IOCTL_NOTIF_RECV as _,
+                &mut notification as *mut SeccompNotif,
+            )
+        };
+        if result != 0 {
+            // This can happen if the monitored thread got interrupted by a signal
+            continue;
+        }
+
+        handler(&Syscall {
+            tid: notification.pid as libc::pid_t,
+            nr: notification.data.nr as libc::c_long,
+            args: notification.data.args,
+        });
+
+        let mut response = SeccompNotifResp {
+            id: notification.id,
+            val: 0,
+            error: 0,
+            flags: SECCOMP_USER_NOTIF_FLAG_CONTINUE,
+        };
+        unsafe {
+            libc::ioctl(
+                listener_fd,
+                SECCOMP_IOCTL_NOTIF_SEND as _,
+                &mut response as *mut SeccompNotifResp,
+            )
+        };
+    }
+}
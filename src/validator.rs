@@ -3,17 +3,29 @@
 
 use anyhow::{Context, Result};
 use clap::{Args, ValueEnum};
+use clap_sys::ext::audio_ports::{
+    CLAP_AUDIO_PORTS_RESCAN_CHANNEL_COUNT, CLAP_AUDIO_PORTS_RESCAN_FLAGS,
+    CLAP_AUDIO_PORTS_RESCAN_IN_PLACE_PAIR, CLAP_AUDIO_PORTS_RESCAN_LIST,
+    CLAP_AUDIO_PORTS_RESCAN_NAMES, CLAP_AUDIO_PORTS_RESCAN_PORT_TYPE,
+};
 use clap_sys::version::clap_version_is_compatible;
 use rayon::prelude::*;
 use regex::{Regex, RegexBuilder};
 use serde::Serialize;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Display;
 use std::fs;
-use std::path::PathBuf;
+use std::io::{BufRead, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 use strum::IntoEnumIterator;
 
+use crate::plugin::host::HostSettings;
 use crate::plugin::library::{PluginLibrary, PluginMetadata};
-use crate::tests::{PluginLibraryTestCase, PluginTestCase, TestCase, TestResult, TestStatus};
+use crate::tests::{
+    OutOfProcessSettings, PluginLibraryTestCase, PluginTestCase, TestCase, TestResult, TestStatus,
+    WorkerPool, WorkerRequest, WORKER_RESULT_PREFIX,
+};
 use crate::util;
 use crate::Verbosity;
 
@@ -33,6 +45,42 @@ pub struct ValidationResult {
     pub plugin_tests: BTreeMap<String, Vec<TestResult>>,
 }
 
+/// A progress event emitted by [`validate()`] while running the tests. These are emitted from
+/// multiple threads at the same time when the tests are run in parallel.
+#[derive(Debug, Clone, Copy)]
+#[allow(clippy::enum_variant_names)]
+pub enum ValidationEvent<'a> {
+    /// A test is about to be run.
+    TestStarted {
+        subject: TestSubject<'a>,
+        test_name: &'a str,
+    },
+    /// A test has finished running.
+    TestFinished {
+        subject: TestSubject<'a>,
+        result: &'a TestResult,
+    },
+    /// A test could not be run because of an error. This error is propagated out of [`validate()`]
+    /// after this event has been emitted.
+    TestErrored {
+        subject: TestSubject<'a>,
+        test_name: &'a str,
+        error: &'a anyhow::Error,
+    },
+}
+
+/// The plugin library or plugin a test is run for.
+#[derive(Debug, Clone, Copy)]
+pub enum TestSubject<'a> {
+    /// A plugin library test for the library at this path.
+    PluginLibrary(&'a Path),
+    /// A plugin test for a plugin in a library.
+    Plugin {
+        library_path: &'a Path,
+        plugin_id: &'a str,
+    },
+}
+
 /// Statistics for the validator.
 pub struct ValidationTally {
     /// The number of passed test cases.
@@ -61,6 +109,18 @@ pub struct ValidatorSettings {
     /// Print the test output as JSON instead of human readable text.
     #[arg(long)]
     pub json: bool,
+    /// Print each test's result as a line of JSON as soon as the test has finished.
+    ///
+    /// The JSON objects contain the same fields as the test results in the --json output, along
+    /// with the plugin library's path and the plugin's ID. The plugin ID is null for plugin
+    /// library tests. Nothing else is printed to STDOUT in this mode, and the output of the tested
+    /// plugins is sent to STDERR instead. When running the tests in-process, the plugin can still
+    /// write directly to STDOUT.
+    #[arg(long, conflicts_with = "json")]
+    pub stream_json: bool,
+    /// Don't print the validator's progress to STDERR while the tests are running.
+    #[arg(long)]
+    pub no_progress: bool,
     /// Only run the tests that match this case-insensitive regular expression.
     #[arg(short = 'f', long)]
     pub test_filter: Option<String>,
@@ -90,6 +150,98 @@ pub struct ValidatorSettings {
     /// --in-process option is used. Can be useful for keeping plugin output in the correct order.
     #[arg(long, conflicts_with = "in_process")]
     pub no_parallel: bool,
+    /// Run at most this many out-of-process tests at the same time.
+    ///
+    /// By default as many tests are run in parallel as there are logical CPU cores. Lowering this
+    /// can help with plugins that use a lot of memory or take a long time to load.
+    #[arg(
+        short = 'j',
+        long,
+        conflicts_with_all = ["in_process", "no_parallel"],
+        value_name = "N"
+    )]
+    pub jobs: Option<NonZeroUsize>,
+    /// Never run multiple tests for the same plugin at the same time.
+    ///
+    /// Tests for different plugins are still run in parallel, but a plugin's tests are run one
+    /// after another. The plugin library tests are also run one after another for each library.
+    /// This is useful for plugins that use machine-wide resources like license files or shared
+    /// memory.
+    #[arg(long, conflicts_with = "in_process")]
+    pub serialize_plugin_tests: bool,
+    /// Record blocking system calls made on the audio thread while the plugin is processing audio.
+    ///
+    /// Things like locking mutexes, sleeping, and file or network I/O made in the plugin's start
+    /// processing, process, and stop processing calls will cause the test to fail. This uses
+    /// seccomp and is only supported on Linux.
+    #[arg(long, conflicts_with = "in_process")]
+    pub audit_audio_thread: bool,
+    /// Run each out-of-process test in a sandbox without network access.
+    ///
+    /// The tests are run in new user, mount, and network namespaces. The home directory, /var/tmp,
+    /// and other writable mounts like /dev/shm are covered by temporary copy-on-write overlays, and
+    /// any files the plugin writes to, creates, or removes there are listed in the test's results.
+    /// Directories that cannot be covered by an overlay are made read-only instead. This is only
+    /// supported on Linux.
+    #[arg(long, conflicts_with = "in_process")]
+    pub sandbox: bool,
+    /// Run the out-of-process tests through this command.
+    ///
+    /// The command is prefixed to the validator's own invocation for running a single test, e.g.
+    /// `--wrapper "valgrind --error-exitcode=99"` or `--wrapper "rr record"`. The command and its
+    /// arguments are separated by whitespace. If the command exits with an error after the test
+    /// has finished, then the test is marked as failed by the wrapper.
+    #[arg(long, conflicts_with = "in_process", allow_hyphen_values = true)]
+    pub wrapper: Option<String>,
+    /// Enable core dumps for the out-of-process tests.
+    ///
+    /// Core dumps of crashed tests are stored in the validator's temporary directory alongside the
+    /// test's other temporary files, and their paths are included in the test results. Whether
+    /// and where core dumps are written ultimately depends on the system's core pattern. This is
+    /// only supported on Linux.
+    #[arg(long, conflicts_with = "in_process")]
+    pub core_dumps: bool,
+    /// Pause out-of-process tests when they crash.
+    ///
+    /// The crashed process is stopped instead of being terminated, and the validator prints the
+    /// process ID along with a command for attaching a debugger. The test continues once the
+    /// process is resumed. This cannot be combined with --wrapper, since the crashed process would
+    /// then be a child of the wrapper. This is only supported on Linux.
+    #[arg(long, conflicts_with_all = ["in_process", "wrapper"])]
+    pub pause_on_crash: bool,
+    /// Run the out-of-process plugin tests in persistent worker processes.
+    ///
+    /// Instead of spawning a new process for every test, worker processes load the plugin library
+    /// once and then run one test after another. A crash still only affects the test that was
+    /// running at the time, after which a new worker is spawned for the remaining tests. Plugin
+    /// library tests, tests that inspect the process' resource usage, and all tests when
+    /// --audit-audio-thread is enabled are always run in their own processes.
+    #[arg(
+        long,
+        conflicts_with_all = ["in_process", "wrapper", "pause_on_crash"]
+    )]
+    pub workers: bool,
+    /// Replace each worker process after it has run this many tests.
+    ///
+    /// This limits how much state from earlier tests can leak into later ones. Workers are only
+    /// replaced after crashing by default.
+    #[arg(long, requires = "workers", value_name = "N")]
+    pub worker_max_tests: Option<NonZeroUsize>,
+    /// Report these audio port rescan flags as unsupported to the plugin.
+    ///
+    /// The host answers `clap_host_audio_ports::is_rescan_flag_supported()` with false for these
+    /// flags, and calls to `clap_host_audio_ports::rescan()` using them cause the test to fail.
+    /// This can be used to test plugins against hosts that don't allow changing certain parts of
+    /// the audio port configuration.
+    #[arg(long, value_delimiter = ',', value_name = "FLAGS")]
+    pub reject_audio_ports_rescan_flags: Vec<AudioPortsRescanFlag>,
+    /// Fail tests during which the plugin logged a message with the `CLAP_LOG_PLUGIN_MISBEHAVING`
+    /// severity.
+    ///
+    /// Messages logged through `clap_host_log::log()` are always included in the test results, but
+    /// by default they don't affect the test's outcome.
+    #[arg(long)]
+    pub fail_on_plugin_misbehaving: bool,
 }
 
 /// Options for running a single test. This is used for the out-of-process testing method. This
@@ -108,6 +260,67 @@ pub struct SingleTestSettings {
     /// because the hosted plugin may also write things there.
     #[arg(long)]
     pub output_file: PathBuf,
+    /// Enable core dumps, and write them to this directory. See [`ValidatorSettings::core_dumps`].
+    #[arg(long)]
+    pub core_dump_dir: Option<PathBuf>,
+    #[command(flatten)]
+    pub process: ChildProcessSettings,
+}
+
+/// Options for a worker process that loads a plugin library once and then runs the plugin tests
+/// requested through its STDIN until STDIN is closed. See [`WorkerRequest`] for the format of
+/// these requests. Like [`SingleTestSettings`], this is hidden from the CLI.
+#[derive(Debug, Args)]
+pub struct WorkerSettings {
+    /// The path to the plugin's library.
+    pub path: PathBuf,
+    /// Enable core dumps, and write them to this directory. See [`ValidatorSettings::core_dumps`].
+    #[arg(long)]
+    pub core_dump_dir: Option<PathBuf>,
+    #[command(flatten)]
+    pub process: ChildProcessSettings,
+}
+
+/// Options for the child process the `scan-side-effects` test scans the plugin library in. Like
+/// [`SingleTestSettings`], this is hidden from the CLI.
+#[cfg(target_os = "linux")]
+#[derive(Debug, Args)]
+pub struct ScanSideEffectsSettings {
+    /// The path to the plugin's library.
+    pub path: PathBuf,
+    /// The name of the file to write the side effects observed while scanning the library to as
+    /// JSON.
+    #[arg(long)]
+    pub output_file: PathBuf,
+}
+
+/// Options shared by all child processes used for out-of-process validation.
+#[derive(Debug, Args)]
+pub struct ChildProcessSettings {
+    /// Record blocking system calls made on the audio thread. See
+    /// [`ValidatorSettings::audit_audio_thread`].
+    #[arg(long)]
+    pub audit_audio_thread: bool,
+    /// Run the tests in a sandbox, using this empty directory for the sandbox's files. See
+    /// [`ValidatorSettings::sandbox`].
+    #[arg(long)]
+    pub sandbox_dir: Option<PathBuf>,
+    /// Stop the process when it crashes. See [`ValidatorSettings::pause_on_crash`].
+    #[arg(long)]
+    pub pause_on_crash: bool,
+    /// Report these audio port rescan flags as unsupported. See
+    /// [`ValidatorSettings::reject_audio_ports_rescan_flags`].
+    #[arg(long, value_delimiter = ',')]
+    pub reject_audio_ports_rescan_flags: Vec<AudioPortsRescanFlag>,
+}
+
+/// The state of a child process set up using [`ChildProcess::set_up()`].
+struct ChildProcess {
+    /// The settings for the hosts created by the tests run in this process.
+    host_settings: HostSettings,
+    /// The sandbox the process has been moved into, if any.
+    #[cfg(target_os = "linux")]
+    sandbox: Option<util::sandbox::Sandbox>,
 }
 
 /// The type of test to run when only running a single test. This is only used for out-of-process
@@ -122,14 +335,57 @@ pub enum SingleTestType {
     Plugin,
 }
 
-/// Run the validator using the specified settings. Returns an error if any of the plugin paths
-/// could not loaded, or if the plugin ID filter did not match any plugins.
-pub fn validate(verbosity: Verbosity, settings: &ValidatorSettings) -> Result<ValidationResult> {
+/// A `CLAP_AUDIO_PORTS_RESCAN_*` flag. Used to configure which flags the host supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AudioPortsRescanFlag {
+    /// `CLAP_AUDIO_PORTS_RESCAN_NAMES`.
+    Names,
+    /// `CLAP_AUDIO_PORTS_RESCAN_FLAGS`.
+    Flags,
+    /// `CLAP_AUDIO_PORTS_RESCAN_CHANNEL_COUNT`.
+    ChannelCount,
+    /// `CLAP_AUDIO_PORTS_RESCAN_PORT_TYPE`.
+    PortType,
+    /// `CLAP_AUDIO_PORTS_RESCAN_IN_PLACE_PAIR`.
+    InPlacePair,
+    /// `CLAP_AUDIO_PORTS_RESCAN_LIST`.
+    List,
+}
+
+/// Run the validator using the specified settings. `on_event` is called before and after running
+/// each test so the progress can be reported while the validator is running. Returns an error if
+/// any of the plugin paths could not loaded, or if the plugin ID filter did not match any plugins.
+pub fn validate(
+    verbosity: Verbosity,
+    settings: &ValidatorSettings,
+    on_event: &(dyn Fn(ValidationEvent) + Sync),
+) -> Result<ValidationResult> {
+    if settings.audit_audio_thread && !cfg!(target_os = "linux") {
+        anyhow::bail!("The --audit-audio-thread option is only supported on Linux.");
+    }
+    if settings.core_dumps && !cfg!(target_os = "linux") {
+        anyhow::bail!("The --core-dumps option is only supported on Linux.");
+    }
+    if settings.pause_on_crash && !cfg!(target_os = "linux") {
+        anyhow::bail!("The --pause-on-crash option is only supported on Linux.");
+    }
+    if matches!(&settings.wrapper, Some(wrapper) if wrapper.trim().is_empty()) {
+        anyhow::bail!("The --wrapper option cannot be empty.");
+    }
+    if settings.sandbox {
+        #[cfg(target_os = "linux")]
+        util::sandbox::check_supported()
+            .context("The --sandbox option cannot be used on this system")?;
+        #[cfg(not(target_os = "linux"))]
+        anyhow::bail!("The --sandbox option is only supported on Linux.");
+    }
+
     // Before doing anything, we need to make sure any temporary artifact files from the previous
     // run are cleaned up. These are used for things like state dumps when one of the state tests
     // fail. This is allowed to fail since the directory may not exist and even if it does and we
     // cannot remove it, then that may not be a problem.
     let _ = std::fs::remove_dir_all(util::validator_temp_dir());
+    let host_settings = host_settings(&settings.reject_audio_ports_rescan_flags);
     let test_filter_re = settings
         .test_filter
         .as_deref()
@@ -140,6 +396,25 @@ pub fn validate(verbosity: Verbosity, settings: &ValidatorSettings) -> Result<Va
                 .context("The test filter is not a valid regular expression")
         })
         .transpose()?;
+    let worker_pool = if settings.workers {
+        // There's no point in keeping around more workers than there are tests running at the same
+        // time
+        let max_workers = if settings.no_parallel {
+            NonZeroUsize::new(1).unwrap()
+        } else {
+            settings.jobs.unwrap_or_else(|| {
+                std::thread::available_parallelism().unwrap_or(NonZeroUsize::new(1).unwrap())
+            })
+        };
+
+        Some(WorkerPool::new(
+            out_of_process_settings(verbosity, settings),
+            settings.worker_max_tests,
+            max_workers,
+        ))
+    } else {
+        None
+    };
 
     // The tests can optionally be run in parallel. This is not the default since some plugins may
     // not handle it correctly, event when the plugins are loaded in different processes. It's also
@@ -168,7 +443,22 @@ pub fn validate(verbosity: Verbosity, settings: &ValidatorSettings) -> Result<Va
                     library_path.clone(),
                     PluginLibraryTestCase::iter()
                         .filter(|test| test_filter(test, settings, &test_filter_re))
-                        .map(|test| run_test(&test, verbosity, settings, library_path))
+                        .map(|test| {
+                            run_reported(
+                                &test,
+                                TestSubject::PluginLibrary(library_path),
+                                on_event,
+                                settings,
+                                || {
+                                    run_test(
+                                        &test,
+                                        verbosity,
+                                        settings,
+                                        (library_path, &host_settings),
+                                    )
+                                },
+                            )
+                        })
                         .collect::<Result<Vec<TestResult>>>()?,
                 );
 
@@ -211,11 +501,34 @@ pub fn validate(verbosity: Verbosity, settings: &ValidatorSettings) -> Result<Va
                             PluginTestCase::iter()
                                 .filter(|test| test_filter(test, settings, &test_filter_re))
                                 .map(|test| {
-                                    run_test(
+                                    run_reported(
                                         &test,
-                                        verbosity,
+                                        TestSubject::Plugin {
+                                            library_path,
+                                            plugin_id: &plugin_metadata.id,
+                                        },
+                                        on_event,
                                         settings,
-                                        (&library_path, &plugin_library, &plugin_metadata.id),
+                                        || match &worker_pool {
+                                            Some(worker_pool) if worker_pool.supports(&test) => {
+                                                worker_pool.run_test(
+                                                    &test,
+                                                    library_path,
+                                                    &plugin_metadata.id,
+                                                )
+                                            }
+                                            _ => run_test(
+                                                &test,
+                                                verbosity,
+                                                settings,
+                                                (
+                                                    library_path,
+                                                    &plugin_library,
+                                                    &plugin_metadata.id,
+                                                    &host_settings,
+                                                ),
+                                            ),
+                                        },
                                     )
                                 })
                                 .collect::<Result<Vec<TestResult>>>()?,
@@ -247,83 +560,148 @@ pub fn validate(verbosity: Verbosity, settings: &ValidatorSettings) -> Result<Va
             })
             .unwrap_or_else(|| Ok(ValidationResult::default()))
     } else {
-        settings
-            .paths
-            .par_iter()
-            .map(|library_path| {
-                let mut plugin_library_tests: BTreeMap<PathBuf, Vec<TestResult>> = BTreeMap::new();
-                plugin_library_tests.insert(
-                    library_path.clone(),
-                    PluginLibraryTestCase::iter()
-                        .par_bridge()
-                        .filter(|test| test_filter(test, settings, &test_filter_re))
-                        .map(|test| run_test(&test, verbosity, settings, library_path))
-                        .collect::<Result<Vec<TestResult>>>()?,
-                );
-
-                let plugin_library = PluginLibrary::load(library_path)
-                    .with_context(|| format!("Could not load '{}'", library_path.display()))?;
-                let plugin_metadata = plugin_library.metadata().with_context(|| {
-                    format!(
-                        "Could not fetch plugin metadata for '{}'",
-                        library_path.display()
-                    )
-                })?;
-                if !clap_version_is_compatible(plugin_metadata.clap_version()) {
-                    log::debug!(
-                        "'{}' uses an unsupported CLAP version ({}.{}.{}), skipping...",
-                        library_path.display(),
-                        plugin_metadata.version.0,
-                        plugin_metadata.version.1,
-                        plugin_metadata.version.2
+        // Rayon won't split the tests for a plugin into multiple jobs when they need to be
+        // serialized, so they will run one after another on a single thread
+        let min_tests_per_job = if settings.serialize_plugin_tests {
+            usize::MAX
+        } else {
+            1
+        };
+        let validate_parallel = || {
+            settings
+                .paths
+                .par_iter()
+                .map(|library_path| {
+                    let mut plugin_library_tests: BTreeMap<PathBuf, Vec<TestResult>> =
+                        BTreeMap::new();
+                    plugin_library_tests.insert(
+                        library_path.clone(),
+                        PluginLibraryTestCase::iter()
+                            .filter(|test| test_filter(test, settings, &test_filter_re))
+                            .collect::<Vec<_>>()
+                            .into_par_iter()
+                            .with_min_len(min_tests_per_job)
+                            .map(|test| {
+                                run_reported(
+                                    &test,
+                                    TestSubject::PluginLibrary(library_path),
+                                    on_event,
+                                    settings,
+                                    || {
+                                        run_test(
+                                            &test,
+                                            verbosity,
+                                            settings,
+                                            (library_path, &host_settings),
+                                        )
+                                    },
+                                )
+                            })
+                            .collect::<Result<Vec<TestResult>>>()?,
                     );
 
-                    return Ok(ValidationResult::default());
-                }
+                    let plugin_library = PluginLibrary::load(library_path)
+                        .with_context(|| format!("Could not load '{}'", library_path.display()))?;
+                    let plugin_metadata = plugin_library.metadata().with_context(|| {
+                        format!(
+                            "Could not fetch plugin metadata for '{}'",
+                            library_path.display()
+                        )
+                    })?;
+                    if !clap_version_is_compatible(plugin_metadata.clap_version()) {
+                        log::debug!(
+                            "'{}' uses an unsupported CLAP version ({}.{}.{}), skipping...",
+                            library_path.display(),
+                            plugin_metadata.version.0,
+                            plugin_metadata.version.1,
+                            plugin_metadata.version.2
+                        );
 
-                let plugin_tests: BTreeMap<String, Vec<TestResult>> = plugin_metadata
-                    .plugins
-                    .into_par_iter()
-                    .filter(|plugin_metadata| plugin_filter(plugin_metadata, settings))
-                    .map(|plugin_metadata| {
-                        Ok((
-                            plugin_metadata.id.clone(),
-                            PluginTestCase::iter()
-                                .par_bridge()
-                                .filter(|test| test_filter(test, settings, &test_filter_re))
-                                .map(|test| {
-                                    run_test(
-                                        &test,
-                                        verbosity,
-                                        settings,
-                                        (&library_path, &plugin_library, &plugin_metadata.id),
-                                    )
-                                })
-                                .collect::<Result<Vec<TestResult>>>()?,
-                        ))
-                    })
-                    .collect::<Result<BTreeMap<_, _>>>()?;
+                        return Ok(ValidationResult::default());
+                    }
 
-                Ok(ValidationResult {
-                    plugin_library_tests,
-                    plugin_tests,
+                    let plugin_tests: BTreeMap<String, Vec<TestResult>> = plugin_metadata
+                        .plugins
+                        .into_par_iter()
+                        .filter(|plugin_metadata| plugin_filter(plugin_metadata, settings))
+                        .map(|plugin_metadata| {
+                            Ok((
+                                plugin_metadata.id.clone(),
+                                PluginTestCase::iter()
+                                    .filter(|test| test_filter(test, settings, &test_filter_re))
+                                    .collect::<Vec<_>>()
+                                    .into_par_iter()
+                                    .with_min_len(min_tests_per_job)
+                                    .map(|test| {
+                                        run_reported(
+                                            &test,
+                                            TestSubject::Plugin {
+                                                library_path,
+                                                plugin_id: &plugin_metadata.id,
+                                            },
+                                            on_event,
+                                            settings,
+                                            || match &worker_pool {
+                                                Some(worker_pool)
+                                                    if worker_pool.supports(&test) =>
+                                                {
+                                                    worker_pool.run_test(
+                                                        &test,
+                                                        library_path,
+                                                        &plugin_metadata.id,
+                                                    )
+                                                }
+                                                _ => run_test(
+                                                    &test,
+                                                    verbosity,
+                                                    settings,
+                                                    (
+                                                        library_path,
+                                                        &plugin_library,
+                                                        &plugin_metadata.id,
+                                                        &host_settings,
+                                                    ),
+                                                ),
+                                            },
+                                        )
+                                    })
+                                    .collect::<Result<Vec<TestResult>>>()?,
+                            ))
+                        })
+                        .collect::<Result<BTreeMap<_, _>>>()?;
+
+                    Ok(ValidationResult {
+                        plugin_library_tests,
+                        plugin_tests,
+                    })
                 })
-            })
-            .reduce(
-                || Ok(ValidationResult::default()),
-                |a, b| {
-                    let (a, b) = (a?, b?);
-
-                    if a.intersects(&b) {
-                        anyhow::bail!(
-                            "Duplicate plugin ID in validation results. Maybe multiple versions \
-                             of the same plugin are being validated."
-                        );
-                    }
+                .reduce(
+                    || Ok(ValidationResult::default()),
+                    |a, b| {
+                        let (a, b) = (a?, b?);
+
+                        if a.intersects(&b) {
+                            anyhow::bail!(
+                                "Duplicate plugin ID in validation results. Maybe multiple \
+                                 versions of the same plugin are being validated."
+                            );
+                        }
+
+                        Ok(ValidationResult::union(a, b))
+                    },
+                )
+        };
 
-                    Ok(ValidationResult::union(a, b))
-                },
-            )
+        // Each test blocks a thread while its process is running, so limiting the number of
+        // threads also limits the number of tests running at the same time
+        match settings.jobs {
+            Some(jobs) => rayon::ThreadPoolBuilder::new()
+                .num_threads(jobs.get())
+                .build()
+                .context("Could not create the thread pool for running the tests")?
+                .install(validate_parallel),
+            None => validate_parallel(),
+        }
     }?;
 
     // The parallel iterators don't preserve order, so this needs to be sorted to make sure the test
@@ -348,6 +726,21 @@ pub fn validate(verbosity: Verbosity, settings: &ValidatorSettings) -> Result<Va
 /// Run a single test case, and write the result to specified the output file path. This is used for
 /// the out-of-process validation mode.
 pub fn run_single_test(settings: &SingleTestSettings) -> Result<()> {
+    let child_process = ChildProcess::set_up(&settings.process)?;
+
+    // Enabling core dumps changes the working directory, so the library's path needs to be
+    // resolved first
+    let library_path = match &settings.core_dump_dir {
+        Some(core_dump_dir) => {
+            let library_path = fs::canonicalize(&settings.path)
+                .with_context(|| format!("Could not canonicalize '{}'", settings.path.display()))?;
+            enable_core_dumps(core_dump_dir)?;
+
+            library_path
+        }
+        None => settings.path.clone(),
+    };
+
     let result = match settings.test_type {
         SingleTestType::PluginLibrary => {
             let test_case = settings
@@ -355,19 +748,29 @@ pub fn run_single_test(settings: &SingleTestSettings) -> Result<()> {
                 .parse::<PluginLibraryTestCase>()
                 .with_context(|| format!("Unknown test name: {}", &settings.name))?;
 
-            test_case.run_in_process(&settings.path)
+            test_case.run_in_process((&library_path, &child_process.host_settings))
         }
         SingleTestType::Plugin => {
-            let plugin_library = PluginLibrary::load(&settings.path)
-                .with_context(|| format!("Could not load '{}'", settings.path.display()))?;
+            let plugin_library = PluginLibrary::load(&library_path)
+                .with_context(|| format!("Could not load '{}'", library_path.display()))?;
             let test_case = settings
                 .name
                 .parse::<PluginTestCase>()
                 .with_context(|| format!("Unknown test name: {}", &settings.name))?;
 
-            test_case.run_in_process((&settings.path, &plugin_library, &settings.plugin_id))
+            test_case.run_in_process((
+                &library_path,
+                &plugin_library,
+                &settings.plugin_id,
+                &child_process.host_settings,
+            ))
         }
     };
+    let result = TestResult {
+        sandbox_writes: child_process.sandbox_writes()?,
+        sandbox_read_only_dirs: child_process.sandbox_read_only_dirs(),
+        ..result
+    };
 
     fs::write(
         &settings.output_file,
@@ -381,6 +784,147 @@ pub fn run_single_test(settings: &SingleTestSettings) -> Result<()> {
     })
 }
 
+/// Run the plugin tests requested through STDIN until STDIN is closed, writing each test's result
+/// to STDOUT. This is used for the worker process mode. The plugin library is only loaded once, and
+/// it is unloaded after the last test.
+pub fn run_worker(settings: &WorkerSettings) -> Result<()> {
+    let child_process = ChildProcess::set_up(&settings.process)?;
+
+    // The working directory is changed to enable core dumps, so the library's path needs to be
+    // resolved first. This is only done once since the working directory is shared by all tests.
+    let library_path = fs::canonicalize(&settings.path)
+        .with_context(|| format!("Could not canonicalize '{}'", settings.path.display()))?;
+    if let Some(core_dump_dir) = &settings.core_dump_dir {
+        enable_core_dumps(core_dump_dir)?;
+    }
+    let plugin_library = PluginLibrary::load(&library_path)
+        .with_context(|| format!("Could not load '{}'", library_path.display()))?;
+
+    // The sandbox's overlays are shared between all tests run by this worker, so only the newly
+    // written files are included in a test's result
+    let mut reported_sandbox_writes: HashSet<PathBuf> = HashSet::new();
+    for line in std::io::stdin().lock().lines() {
+        let line = line.context("Could not read the next request")?;
+        let request: WorkerRequest =
+            serde_json::from_str(&line).context("Could not parse the request")?;
+
+        let test_case = request
+            .test_name
+            .parse::<PluginTestCase>()
+            .with_context(|| format!("Unknown test name: {}", &request.test_name))?;
+        let result = test_case.run_in_process((
+            &library_path,
+            &plugin_library,
+            &request.plugin_id,
+            &child_process.host_settings,
+        ));
+        let sandbox_writes: Vec<PathBuf> = child_process
+            .sandbox_writes()?
+            .into_iter()
+            .filter(|path| !reported_sandbox_writes.contains(path))
+            .collect();
+        reported_sandbox_writes.extend(sandbox_writes.iter().cloned());
+        let result = TestResult {
+            sandbox_writes,
+            sandbox_read_only_dirs: child_process.sandbox_read_only_dirs(),
+            ..result
+        };
+
+        let result_json =
+            serde_json::to_string(&result).context("Could not format the result as JSON")?;
+        let mut stdout = std::io::stdout().lock();
+        writeln!(stdout, "{WORKER_RESULT_PREFIX}{result_json}")
+            .and_then(|()| stdout.flush())
+            .context("Could not send the result to the validator")?;
+    }
+
+    Ok(())
+}
+
+impl ChildProcess {
+    /// Apply the [`ChildProcessSettings`] to the current process. This needs to happen before any
+    /// threads are spawned, since a multithreaded process cannot be moved to a new user namespace.
+    fn set_up(settings: &ChildProcessSettings) -> Result<Self> {
+        #[cfg(target_os = "linux")]
+        let sandbox = settings
+            .sandbox_dir
+            .as_deref()
+            .map(util::sandbox::Sandbox::enter)
+            .transpose()
+            .context("Could not set up the sandbox")?;
+        #[cfg(not(target_os = "linux"))]
+        if settings.sandbox_dir.is_some() {
+            anyhow::bail!("Sandboxing is only supported on Linux.");
+        }
+
+        if settings.audit_audio_thread {
+            #[cfg(target_os = "linux")]
+            crate::plugin::instance::audit::enable();
+            #[cfg(not(target_os = "linux"))]
+            anyhow::bail!("The audio thread audit mode is only supported on Linux.");
+        }
+
+        if settings.pause_on_crash {
+            #[cfg(target_os = "linux")]
+            util::crash::pause_on_crash().context("Could not install the crash handlers")?;
+            #[cfg(not(target_os = "linux"))]
+            anyhow::bail!("Pausing on crashes is only supported on Linux.");
+        }
+
+        Ok(ChildProcess {
+            host_settings: host_settings(&settings.reject_audio_ports_rescan_flags),
+            #[cfg(target_os = "linux")]
+            sandbox,
+        })
+    }
+
+    /// Get the paths of all files written to outside of the sandbox's temporary directory so far.
+    /// This is empty when the process is not running in a sandbox.
+    fn sandbox_writes(&self) -> Result<Vec<PathBuf>> {
+        #[cfg(target_os = "linux")]
+        if let Some(sandbox) = &self.sandbox {
+            return sandbox
+                .written_paths()
+                .context("Could not list the files written to in the sandbox");
+        }
+
+        Ok(Vec::new())
+    }
+
+    /// Get the directories that were made read-only in the sandbox because they could not be
+    /// covered by an overlay. This is empty when the process is not running in a sandbox.
+    fn sandbox_read_only_dirs(&self) -> Vec<PathBuf> {
+        #[cfg(target_os = "linux")]
+        if let Some(sandbox) = &self.sandbox {
+            return sandbox.read_only_dirs().to_vec();
+        }
+
+        Vec::new()
+    }
+}
+
+/// Get the settings for the tests' hosts. The hosts report the `rejected_flags` as unsupported
+/// when the plugin calls `clap_host_audio_ports::is_rescan_flag_supported()`.
+fn host_settings(rejected_flags: &[AudioPortsRescanFlag]) -> HostSettings {
+    let rejected_flags = rejected_flags
+        .iter()
+        .fold(0, |rejected_flags, flag| rejected_flags | flag.clap_flag());
+
+    HostSettings {
+        supported_audio_ports_rescan_flags: !rejected_flags,
+        ..HostSettings::default()
+    }
+}
+
+/// Enable core dumps for the current process and write them to `core_dump_dir`. This changes the
+/// working directory, so relative paths need to be resolved before calling this.
+fn enable_core_dumps(core_dump_dir: &Path) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    return util::crash::enable_core_dumps(core_dump_dir).context("Could not enable core dumps");
+    #[cfg(not(target_os = "linux"))]
+    anyhow::bail!("Core dumps are only supported on Linux.");
+}
+
 /// The filter function for determining whether or not a test should be run based on the validator's
 /// settings settings.
 fn test_filter<'a, T: TestCase<'a>>(
@@ -407,6 +951,43 @@ fn plugin_filter(plugin_metadata: &PluginMetadata, settings: &ValidatorSettings)
     }
 }
 
+/// Run a test using `run_test`, and emit [`ValidationEvent`]s before and after running it. The
+/// result is adjusted according to the validator's settings before it is reported. If the test
+/// could not be run, then a [`ValidationEvent::TestErrored`] event is emitted instead.
+fn run_reported<'a, T: TestCase<'a>>(
+    test: &T,
+    subject: TestSubject,
+    on_event: &(dyn Fn(ValidationEvent) + Sync),
+    settings: &ValidatorSettings,
+    run_test: impl FnOnce() -> Result<TestResult>,
+) -> Result<TestResult> {
+    let test_name = test.to_string();
+    on_event(ValidationEvent::TestStarted {
+        subject,
+        test_name: &test_name,
+    });
+    let mut result = match run_test() {
+        Ok(result) => result,
+        Err(error) => {
+            on_event(ValidationEvent::TestErrored {
+                subject,
+                test_name: &test_name,
+                error: &error,
+            });
+            return Err(error);
+        }
+    };
+    if settings.fail_on_plugin_misbehaving {
+        result.fail_on_plugin_misbehaving();
+    }
+    on_event(ValidationEvent::TestFinished {
+        subject,
+        result: &result,
+    });
+
+    Ok(result)
+}
+
 /// The filter function for determining whether or not a test should be run based on the validator's
 /// settings settings.
 fn run_test<'a, T: TestCase<'a>>(
@@ -418,7 +999,43 @@ fn run_test<'a, T: TestCase<'a>>(
     if settings.in_process {
         Ok(test.run_in_process(args))
     } else {
-        test.run_out_of_process(args, verbosity, settings.hide_output)
+        test.run_out_of_process(args, &out_of_process_settings(verbosity, settings))
+    }
+}
+
+/// Get the settings for running tests out-of-process from the validator's settings.
+fn out_of_process_settings(
+    verbosity: Verbosity,
+    settings: &ValidatorSettings,
+) -> OutOfProcessSettings {
+    OutOfProcessSettings {
+        verbosity,
+        hide_output: settings.hide_output,
+        stdout_to_stderr: settings.stream_json,
+        audit_audio_thread: settings.audit_audio_thread,
+        sandbox: settings.sandbox,
+        wrapper: settings
+            .wrapper
+            .as_deref()
+            .map(|wrapper| wrapper.split_whitespace().map(String::from).collect())
+            .unwrap_or_default(),
+        core_dumps: settings.core_dumps,
+        pause_on_crash: settings.pause_on_crash,
+        reject_audio_ports_rescan_flags: settings.reject_audio_ports_rescan_flags.clone(),
+    }
+}
+
+impl AudioPortsRescanFlag {
+    /// Get the `CLAP_AUDIO_PORTS_RESCAN_*` flag this value corresponds to.
+    pub fn clap_flag(self) -> u32 {
+        match self {
+            AudioPortsRescanFlag::Names => CLAP_AUDIO_PORTS_RESCAN_NAMES,
+            AudioPortsRescanFlag::Flags => CLAP_AUDIO_PORTS_RESCAN_FLAGS,
+            AudioPortsRescanFlag::ChannelCount => CLAP_AUDIO_PORTS_RESCAN_CHANNEL_COUNT,
+            AudioPortsRescanFlag::PortType => CLAP_AUDIO_PORTS_RESCAN_PORT_TYPE,
+            AudioPortsRescanFlag::InPlacePair => CLAP_AUDIO_PORTS_RESCAN_IN_PLACE_PAIR,
+            AudioPortsRescanFlag::List => CLAP_AUDIO_PORTS_RESCAN_LIST,
+        }
     }
 }
 
@@ -437,7 +1054,9 @@ impl ValidationResult {
         {
             match test.status {
                 TestStatus::Success { .. } => num_passed += 1,
-                TestStatus::Crashed { .. } | TestStatus::Failed { .. } => num_failed += 1,
+                TestStatus::Crashed { .. }
+                | TestStatus::Failed { .. }
+                | TestStatus::WrapperFailed { .. } => num_failed += 1,
                 TestStatus::Skipped { .. } => num_skipped += 1,
                 TestStatus::Warning { .. } => num_warnings += 1,
             }
@@ -479,6 +1098,15 @@ impl ValidationResult {
     }
 }
 
+impl Display for TestSubject<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TestSubject::PluginLibrary(library_path) => write!(f, "{}", library_path.display()),
+            TestSubject::Plugin { plugin_id, .. } => write!(f, "{plugin_id}"),
+        }
+    }
+}
+
 impl ValidationTally {
     /// Get the total number of tests run.
     pub fn total(&self) -> u32 {